@@ -122,6 +122,16 @@ pub mod closure {
         include_bytes!("../test_data/ttf/variations_closure.ttf");
     pub static VARIATIONS_GLYPHS: &str =
         include_str!("../test_data/fea/variations_closure_glyphs.txt");
+    pub static VARIATIONS_CONDITIONS_CLOSURE: &[u8] =
+        include_bytes!("../test_data/ttf/variations_closure_conditions.ttf");
+    pub static VARIATIONS_CONDITIONS_GLYPHS: &str =
+        include_str!("../test_data/fea/variations_closure_conditions_glyphs.txt");
+    pub static CROSS_TABLE: &[u8] = include_bytes!("../test_data/ttf/cross_table_closure.ttf");
+    pub static CROSS_TABLE_MALFORMED: &[u8] =
+        include_bytes!("../test_data/ttf/cross_table_closure_malformed.ttf");
+    pub static FEATURE_CLOSURE: &[u8] = include_bytes!("../test_data/ttf/feature_closure.ttf");
+    pub static FEATURE_CLOSURE_GLYPHS: &str =
+        include_str!("../test_data/fea/feature_closure_glyphs.txt");
 }
 
 pub mod post {
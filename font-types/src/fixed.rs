@@ -98,6 +98,12 @@ macro_rules! fixed_impl {
                 Self(self.0.saturating_sub(other.0))
             }
 
+            /// Checked substitution.
+            #[inline(always)]
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                self.0.checked_sub(other.0).map(|inner| Self(inner))
+            }
+
             /// The representation of this number as a big-endian byte array.
             #[inline(always)]
             pub const fn to_be_bytes(self) -> [u8; $bits / 8] {
@@ -173,14 +179,34 @@ macro_rules! fixed_mul_div {
                     result as i32
                 })
             }
+
+            /// Checked multiplication.
+            ///
+            /// Returns `None` if the rounded product is out of range.
+            #[inline(always)]
+            pub fn checked_mul(self, other: Self) -> Option<Self> {
+                i32::try_from(self.wide_mul(other)).ok().map(Self)
+            }
+
+            /// Saturating multiplication.
+            #[inline(always)]
+            pub fn saturating_mul(self, other: Self) -> Self {
+                Self(self.wide_mul(other).clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+            }
+
+            // the rounded product, before truncation to 32 bits
+            #[inline(always)]
+            const fn wide_mul(self, other: Self) -> i64 {
+                let ab = self.0 as i64 * other.0 as i64;
+                (ab + 0x8000 - (ab < 0) as i64) >> 16
+            }
         }
 
         impl Mul for $ty {
             type Output = Self;
             #[inline(always)]
             fn mul(self, other: Self) -> Self::Output {
-                let ab = self.0 as i64 * other.0 as i64;
-                Self(((ab + 0x8000 - i64::from(ab < 0)) >> 16) as i32)
+                Self(self.wide_mul(other) as i32)
             }
         }
 
@@ -485,4 +511,35 @@ mod tests {
             Fixed::from_f64(0.25)
         );
     }
+
+    #[test]
+    fn fixed_checked_and_saturating() {
+        let one = Fixed::ONE;
+        assert_eq!(Fixed::MAX.checked_add(Fixed::EPSILON), None);
+        assert_eq!(Fixed::MAX.saturating_add(one), Fixed::MAX);
+        assert_eq!(Fixed::MIN.checked_sub(Fixed::EPSILON), None);
+        assert_eq!(Fixed::MIN.saturating_sub(one), Fixed::MIN);
+        assert_eq!((Fixed::MAX - one).checked_add(one), Some(Fixed::MAX));
+        assert_eq!((Fixed::MIN + one).checked_sub(one), Some(Fixed::MIN));
+
+        // 16.16 tops out just below 32768.0
+        let big = Fixed::from_i32(0x4000);
+        assert_eq!(big.checked_mul(Fixed::from_i32(2)), None);
+        assert_eq!(
+            big.checked_mul(Fixed::from_f64(1.5)),
+            Some(Fixed::from_i32(0x6000))
+        );
+        assert_eq!(big.saturating_mul(Fixed::from_i32(2)), Fixed::MAX);
+        assert_eq!(big.saturating_mul(Fixed::from_i32(-3)), Fixed::MIN);
+        assert_eq!(
+            big.checked_mul(Fixed::from_i32(-2)),
+            Some(Fixed::from_i32(-0x8000))
+        );
+        assert_eq!(Fixed::MIN.checked_mul(-one), None);
+        assert_eq!(Fixed::MAX.checked_mul(one), Some(Fixed::MAX));
+        assert_eq!(
+            Fixed::from_f64(0.5).checked_mul(Fixed::from_f64(2.0)),
+            Some(one)
+        );
+    }
 }
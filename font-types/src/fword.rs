@@ -1,5 +1,7 @@
 //! 16-bit signed and unsigned font-units
 
+use core::ops::{Div, Mul};
+
 use super::Fixed;
 
 /// 16-bit signed quantity in font design units.
@@ -16,6 +18,11 @@ pub struct FWord(i16);
 #[repr(transparent)]
 pub struct UfWord(u16);
 
+/// The error type returned when a value is out of range for an [`FWord`] or
+/// [`UfWord`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfRange(i32);
+
 impl FWord {
     pub const fn new(raw: i16) -> Self {
         Self(raw)
@@ -25,6 +32,14 @@ impl FWord {
         self.0
     }
 
+    /// Create an `FWord` from an `i32`, returning an error if the value does
+    /// not fit in an `i16`.
+    pub fn try_from_i32(value: i32) -> Result<FWord, OutOfRange> {
+        i16::try_from(value)
+            .map(FWord)
+            .map_err(|_| OutOfRange(value))
+    }
+
     /// Converts this number to a 16.16 fixed point value.
     pub const fn to_fixed(self) -> Fixed {
         Fixed::from_i32(self.0 as i32)
@@ -34,6 +49,41 @@ impl FWord {
     pub const fn to_be_bytes(self) -> [u8; 2] {
         self.0.to_be_bytes()
     }
+
+    /// Scale this value from one units-per-em to another.
+    ///
+    /// The result is rounded to the nearest integer, with ties rounded to
+    /// even, and is clamped to the range of an `i16`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `from_upm` is zero.
+    pub fn rescale(self, from_upm: u16, to_upm: u16) -> FWord {
+        let scaled = div_round_half_even(self.0 as i64 * to_upm as i64, from_upm as i64);
+        FWord(scaled.clamp(i16::MIN as i64, i16::MAX as i64) as i16)
+    }
+
+    /// Multiply by an integer, returning `None` on overflow.
+    pub const fn checked_mul(self, rhs: i16) -> Option<FWord> {
+        match self.0.checked_mul(rhs) {
+            Some(value) => Some(FWord(value)),
+            None => None,
+        }
+    }
+
+    /// Multiply by an integer, clamping the result to the range of an `i16`.
+    pub const fn saturating_mul(self, rhs: i16) -> FWord {
+        FWord(self.0.saturating_mul(rhs))
+    }
+
+    /// Divide by an integer, returning `None` if `rhs` is zero or the result
+    /// overflows.
+    pub const fn checked_div(self, rhs: i16) -> Option<FWord> {
+        match self.0.checked_div(rhs) {
+            Some(value) => Some(FWord(value)),
+            None => None,
+        }
+    }
 }
 
 impl UfWord {
@@ -45,6 +95,14 @@ impl UfWord {
         self.0
     }
 
+    /// Create a `UfWord` from an `i32`, returning an error if the value is
+    /// negative or does not fit in a `u16`.
+    pub fn try_from_i32(value: i32) -> Result<UfWord, OutOfRange> {
+        u16::try_from(value)
+            .map(UfWord)
+            .map_err(|_| OutOfRange(value))
+    }
+
     /// Converts this number to a 16.16 fixed point value.
     pub const fn to_fixed(self) -> Fixed {
         Fixed::from_i32(self.0 as i32)
@@ -54,8 +112,118 @@ impl UfWord {
     pub const fn to_be_bytes(self) -> [u8; 2] {
         self.0.to_be_bytes()
     }
+
+    /// Scale this value from one units-per-em to another.
+    ///
+    /// The result is rounded to the nearest integer, with ties rounded to
+    /// even, and is clamped to the range of a `u16`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `from_upm` is zero.
+    pub fn rescale(self, from_upm: u16, to_upm: u16) -> UfWord {
+        let scaled = div_round_half_even(self.0 as i64 * to_upm as i64, from_upm as i64);
+        UfWord(scaled.min(u16::MAX as i64) as u16)
+    }
+
+    /// Multiply by an integer, returning `None` on overflow.
+    pub const fn checked_mul(self, rhs: u16) -> Option<UfWord> {
+        match self.0.checked_mul(rhs) {
+            Some(value) => Some(UfWord(value)),
+            None => None,
+        }
+    }
+
+    /// Multiply by an integer, clamping the result to the range of a `u16`.
+    pub const fn saturating_mul(self, rhs: u16) -> UfWord {
+        UfWord(self.0.saturating_mul(rhs))
+    }
+
+    /// Divide by an integer, returning `None` if `rhs` is zero.
+    pub const fn checked_div(self, rhs: u16) -> Option<UfWord> {
+        match self.0.checked_div(rhs) {
+            Some(value) => Some(UfWord(value)),
+            None => None,
+        }
+    }
+}
+
+/// Divide `num` by the positive `den`, rounding ties to even.
+fn div_round_half_even(num: i64, den: i64) -> i64 {
+    let quot = num.div_euclid(den);
+    let twice_rem = num.rem_euclid(den) * 2;
+    if twice_rem > den || (twice_rem == den && quot % 2 != 0) {
+        quot + 1
+    } else {
+        quot
+    }
+}
+
+/// Multiplication by an integer.
+///
+/// This wraps on overflow; see [`FWord::checked_mul`] and
+/// [`FWord::saturating_mul`] for alternatives.
+impl Mul<i16> for FWord {
+    type Output = FWord;
+
+    fn mul(self, rhs: i16) -> FWord {
+        FWord(self.0.wrapping_mul(rhs))
+    }
+}
+
+/// Division by an integer.
+///
+/// As with integer division, the result is truncated toward zero, and
+/// dividing by zero panics. Dividing `i16::MIN` by `-1` wraps to `i16::MIN`;
+/// see [`FWord::checked_div`] for an alternative.
+impl Div<i16> for FWord {
+    type Output = FWord;
+
+    fn div(self, rhs: i16) -> FWord {
+        FWord(self.0.wrapping_div(rhs))
+    }
+}
+
+/// Multiplication by an integer.
+///
+/// This wraps on overflow; see [`UfWord::checked_mul`] and
+/// [`UfWord::saturating_mul`] for alternatives.
+impl Mul<u16> for UfWord {
+    type Output = UfWord;
+
+    fn mul(self, rhs: u16) -> UfWord {
+        UfWord(self.0.wrapping_mul(rhs))
+    }
+}
+
+/// Division by an integer.
+///
+/// As with integer division, the result is truncated, and dividing by zero
+/// panics; see [`UfWord::checked_div`] for an alternative.
+impl Div<u16> for UfWord {
+    type Output = UfWord;
+
+    fn div(self, rhs: u16) -> UfWord {
+        UfWord(self.0 / rhs)
+    }
+}
+
+impl OutOfRange {
+    /// The value that could not be converted.
+    pub fn value(&self) -> i32 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "value {} out of range for font units", self.0)
+    }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for OutOfRange {}
+
 impl std::fmt::Display for FWord {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         self.0.fmt(f)
@@ -95,3 +263,93 @@ impl From<UfWord> for u16 {
 crate::newtype_scalar!(FWord, [u8; 2]);
 crate::newtype_scalar!(UfWord, [u8; 2]);
 //TODO: we can add addition/etc as needed
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_from_i32() {
+        assert_eq!(FWord::try_from_i32(-32768), Ok(FWord::new(i16::MIN)));
+        assert_eq!(FWord::try_from_i32(32767), Ok(FWord::new(i16::MAX)));
+        assert_eq!(FWord::try_from_i32(32768), Err(OutOfRange(32768)));
+        assert_eq!(FWord::try_from_i32(-32769), Err(OutOfRange(-32769)));
+        assert_eq!(UfWord::try_from_i32(0), Ok(UfWord::new(0)));
+        assert_eq!(UfWord::try_from_i32(65535), Ok(UfWord::new(u16::MAX)));
+        assert_eq!(UfWord::try_from_i32(65536), Err(OutOfRange(65536)));
+        assert_eq!(UfWord::try_from_i32(-1).map_err(|e| e.value()), Err(-1));
+    }
+
+    #[test]
+    fn mul_div() {
+        assert_eq!(FWord::new(-12) * 3, FWord::new(-36));
+        assert_eq!(FWord::new(7) / 2, FWord::new(3));
+        assert_eq!(FWord::new(-7) / 2, FWord::new(-3));
+        assert_eq!(UfWord::new(12) * 3, UfWord::new(36));
+        assert_eq!(UfWord::new(7) / 2, UfWord::new(3));
+    }
+
+    #[test]
+    fn mul_div_overflow() {
+        assert_eq!(FWord::new(20000) * 2, FWord::new(-25536));
+        assert_eq!(FWord::new(i16::MIN) / -1, FWord::new(i16::MIN));
+        assert_eq!(FWord::new(20000).checked_mul(2), None);
+        assert_eq!(FWord::new(-12).checked_mul(3), Some(FWord::new(-36)));
+        assert_eq!(FWord::new(20000).saturating_mul(-2), FWord::new(i16::MIN));
+        assert_eq!(FWord::new(i16::MIN).checked_div(-1), None);
+        assert_eq!(FWord::new(7).checked_div(0), None);
+        assert_eq!(FWord::new(-7).checked_div(2), Some(FWord::new(-3)));
+
+        assert_eq!(UfWord::new(40000) * 2, UfWord::new(14464));
+        assert_eq!(UfWord::new(40000).checked_mul(2), None);
+        assert_eq!(UfWord::new(40000).saturating_mul(2), UfWord::new(u16::MAX));
+        assert_eq!(UfWord::new(7).checked_div(0), None);
+        assert_eq!(UfWord::new(7).checked_div(2), Some(UfWord::new(3)));
+    }
+
+    #[test]
+    fn rescale_1000_to_2048() {
+        for (from, to) in [
+            (0, 0),
+            (1, 2),
+            (250, 512),
+            (500, 1024),
+            (700, 1434),
+            (-300, -614),
+            (-1, -2),
+            (1000, 2048),
+        ] {
+            assert_eq!(
+                FWord::new(from).rescale(1000, 2048),
+                FWord::new(to),
+                "{from}"
+            );
+        }
+        for (from, to) in [(0, 0), (1, 2), (333, 682), (1000, 2048)] {
+            assert_eq!(
+                UfWord::new(from).rescale(1000, 2048),
+                UfWord::new(to),
+                "{from}"
+            );
+        }
+    }
+
+    #[test]
+    fn rescale_rounds_half_to_even() {
+        assert_eq!(FWord::new(1).rescale(4, 2), FWord::new(0));
+        assert_eq!(FWord::new(3).rescale(4, 2), FWord::new(2));
+        assert_eq!(FWord::new(-1).rescale(4, 2), FWord::new(0));
+        assert_eq!(FWord::new(-3).rescale(4, 2), FWord::new(-2));
+        assert_eq!(UfWord::new(5).rescale(4, 2), UfWord::new(2));
+    }
+
+    #[test]
+    fn rescale_clamps() {
+        assert_eq!(FWord::new(20000).rescale(1000, 2048), FWord::new(i16::MAX));
+        assert_eq!(FWord::new(-20000).rescale(1000, 2048), FWord::new(i16::MIN));
+        assert_eq!(
+            UfWord::new(40000).rescale(1000, 2048),
+            UfWord::new(u16::MAX)
+        );
+    }
+}
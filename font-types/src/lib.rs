@@ -34,7 +34,7 @@ mod serde_test;
 
 pub use bbox::BoundingBox;
 pub use fixed::{F26Dot6, F2Dot14, F4Dot12, F6Dot10, Fixed};
-pub use fword::{FWord, UfWord};
+pub use fword::{FWord, OutOfRange, UfWord};
 pub use glyph_id::{GlyphId, GlyphId16, TryFromGlyphIdError};
 pub use int24::Int24;
 pub use longdatetime::LongDateTime;
@@ -3,16 +3,17 @@
 //! This means taking a set of glyphs and updating it to include any other glyphs
 //! reachable from those glyphs via substitution, recursively.
 
-use font_types::GlyphId16;
+use font_types::{F2Dot14, GlyphId16, Tag};
 use types::BigEndian;
 
 use crate::{
     collections::IntSet,
+    tables::fvar::Fvar,
     tables::layout::{
         ChainedClassSequenceRule, ChainedClassSequenceRuleSet, ChainedSequenceContextFormat1,
         ChainedSequenceContextFormat2, ChainedSequenceContextFormat3, ChainedSequenceRule,
-        ChainedSequenceRuleSet, ClassSequenceRule, ClassSequenceRuleSet, ExtensionLookup,
-        SequenceContextFormat1, SequenceContextFormat2, SequenceContextFormat3,
+        ChainedSequenceRuleSet, ClassSequenceRule, ClassSequenceRuleSet, Condition, ConditionSet,
+        ExtensionLookup, SequenceContextFormat1, SequenceContextFormat2, SequenceContextFormat3,
         SequenceLookupRecord, SequenceRule, SequenceRuleSet, Subtables,
     },
     ArrayOfOffsets, FontRead, ReadError,
@@ -21,7 +22,8 @@ use crate::{
 use super::{
     AlternateSubstFormat1, ChainedSequenceContext, ClassDef, CoverageTable, Gsub,
     LigatureSubstFormat1, MultipleSubstFormat1, ReverseChainSingleSubstFormat1, SequenceContext,
-    SingleSubst, SingleSubstFormat1, SingleSubstFormat2, SubstitutionLookup, SubstitutionSubtables,
+    SingleSubst, SingleSubstFormat1, SingleSubstFormat2, SubstitutionLookup,
+    SubstitutionLookupList, SubstitutionSubtables,
 };
 
 // we put ClosureCtx in its own module to enforce visibility rules;
@@ -155,16 +157,62 @@ trait GlyphClosure {
 
 impl Gsub<'_> {
     /// Return the set of glyphs reachable from the input set via any substitution.
+    ///
+    /// This includes substitutions from every feature variation, regardless
+    /// of its conditions.
     pub fn closure_glyphs(
+        &self,
+        glyphs: IntSet<GlyphId16>,
+    ) -> Result<IntSet<GlyphId16>, ReadError> {
+        self.closure_glyphs_at_coords(glyphs, None)
+    }
+
+    /// Return the set of glyphs reachable from the input set via any
+    /// substitution, with feature variations filtered by location.
+    ///
+    /// The location is a list of axis tags and normalized coordinates. Since
+    /// conditions identify axes by their index in the `fvar` table, the tags
+    /// are resolved against the axes of `fvar`; tags that are not axes in the
+    /// font are ignored, and axes that are missing from the location are
+    /// assumed to be at the default. Only the feature variations that would
+    /// be applied at this location are included; the lookups of the default
+    /// features are always included.
+    ///
+    /// Conditions that cannot be evaluated here (such as those that depend on
+    /// an item variation store) are assumed to match. If `location` is `None`,
+    /// all feature variations are included, as in
+    /// [`closure_glyphs`](Self::closure_glyphs).
+    pub fn closure_glyphs_at_location(
+        &self,
+        glyphs: IntSet<GlyphId16>,
+        fvar: &Fvar,
+        location: Option<&[(Tag, F2Dot14)]>,
+    ) -> Result<IntSet<GlyphId16>, ReadError> {
+        let Some(location) = location else {
+            return self.closure_glyphs_at_coords(glyphs, None);
+        };
+        let axes = fvar.axes()?;
+        let mut coords = vec![F2Dot14::ZERO; axes.len()];
+        for (tag, value) in location {
+            if let Some(idx) = axes.iter().position(|axis| axis.axis_tag() == *tag) {
+                coords[idx] = *value;
+            }
+        }
+        self.closure_glyphs_at_coords(glyphs, Some(&coords))
+    }
+
+    // `coords` are normalized coordinates, in fvar axis order
+    fn closure_glyphs_at_coords(
         &self,
         mut glyphs: IntSet<GlyphId16>,
+        location: Option<&[F2Dot14]>,
     ) -> Result<IntSet<GlyphId16>, ReadError> {
         // we need to do this iteratively, since any glyph found in one pass
         // over the lookups could also be the target of substitutions.
 
         let mut ctx = ClosureCtx::new(&mut glyphs);
 
-        let reachable_lookups = self.find_reachable_lookups()?;
+        let reachable_lookups = self.find_reachable_lookups(location)?;
         let mut prev_lookup_count = 0;
         let mut prev_glyph_count = 0;
         let mut new_glyph_count = ctx.glyphs().len();
@@ -202,31 +250,50 @@ impl Gsub<'_> {
         Ok(())
     }
 
-    fn find_reachable_lookups(&self) -> Result<IntSet<u16>, ReadError> {
+    fn find_reachable_lookups(
+        &self,
+        location: Option<&[F2Dot14]>,
+    ) -> Result<IntSet<u16>, ReadError> {
         let feature_list = self.feature_list()?;
         let mut lookup_ids = IntSet::new();
 
-        let feature_variations = self
-            .feature_variations()
-            .transpose()?
-            .map(|vars| {
-                let data = vars.offset_data();
-                vars.feature_variation_records()
-                    .iter()
-                    .filter_map(move |rec| {
-                        rec.feature_table_substitution(data)
-                            .transpose()
-                            .ok()
-                            .flatten()
-                    })
-                    .flat_map(|subs| {
-                        subs.substitutions()
-                            .iter()
-                            .map(move |sub| sub.alternate_feature(subs.offset_data()))
-                    })
-            })
-            .into_iter()
-            .flatten();
+        let mut substitutions = Vec::new();
+        if let Some(vars) = self.feature_variations().transpose()? {
+            let data = vars.offset_data();
+            for rec in vars.feature_variation_records() {
+                // without a location we conservatively include every record
+                let matches = match location {
+                    Some(coords) => match rec.condition_set(data).transpose()? {
+                        Some(set) => condition_set_matches(&set, coords)?,
+                        // a record without conditions always matches
+                        None => Some(true),
+                    },
+                    None => None,
+                };
+                if matches == Some(false) {
+                    continue;
+                }
+                if let Some(subs) = rec
+                    .feature_table_substitution(data)
+                    .transpose()
+                    .ok()
+                    .flatten()
+                {
+                    substitutions.push(subs);
+                }
+                // only the first matching record is applied, but if we aren't
+                // sure that this one matched we need to keep going.
+                if matches == Some(true) {
+                    break;
+                }
+            }
+        }
+        let feature_variations = substitutions.iter().flat_map(|subs| {
+            subs.substitutions()
+                .iter()
+                .map(move |sub| sub.alternate_feature(subs.offset_data()))
+        });
+
         for feature in feature_list
             .feature_records()
             .iter()
@@ -239,6 +306,60 @@ impl Gsub<'_> {
     }
 }
 
+/// Evaluate a condition set at the given location.
+///
+/// Returns `None` if this can't be determined.
+fn condition_set_matches(
+    set: &ConditionSet,
+    coords: &[F2Dot14],
+) -> Result<Option<bool>, ReadError> {
+    all_conditions_match(set.conditions().iter(), coords)
+}
+
+fn all_conditions_match<'a>(
+    conditions: impl Iterator<Item = Result<Condition<'a>, ReadError>>,
+    coords: &[F2Dot14],
+) -> Result<Option<bool>, ReadError> {
+    let mut result = Some(true);
+    for condition in conditions {
+        match condition_matches(&condition?, coords)? {
+            Some(false) => return Ok(Some(false)),
+            None => result = None,
+            Some(true) => (),
+        }
+    }
+    Ok(result)
+}
+
+fn condition_matches(condition: &Condition, coords: &[F2Dot14]) -> Result<Option<bool>, ReadError> {
+    Ok(match condition {
+        Condition::Format1AxisRange(cond) => {
+            let coord = coords
+                .get(cond.axis_index() as usize)
+                .copied()
+                .unwrap_or_default();
+            Some(cond.filter_range_min_value() <= coord && coord <= cond.filter_range_max_value())
+        }
+        // this requires evaluating deltas from an item variation store
+        Condition::Format2VariableValue(_) => None,
+        Condition::Format3And(cond) => all_conditions_match(cond.conditions().iter(), coords)?,
+        Condition::Format4Or(cond) => {
+            let mut result = Some(false);
+            for condition in cond.conditions().iter() {
+                match condition_matches(&condition?, coords)? {
+                    Some(true) => return Ok(Some(true)),
+                    None => result = None,
+                    Some(false) => (),
+                }
+            }
+            result
+        }
+        Condition::Format5Negate(cond) => {
+            condition_matches(&cond.condition()?, coords)?.map(|b| !b)
+        }
+    })
+}
+
 impl GlyphClosure for SubstitutionLookup<'_> {
     fn add_reachable_glyphs(&self, ctx: &mut ClosureCtx) -> Result<(), ReadError> {
         self.subtables()?.add_reachable_glyphs(ctx)
@@ -368,6 +489,9 @@ impl GlyphClosure for LigatureSubstFormat1<'_> {
 }
 
 impl GlyphClosure for ReverseChainSingleSubstFormat1<'_> {
+    // this never references nested lookups; the substitutes are reachable only
+    // if every backtrack and lookahead position can be matched by some glyph
+    // in the closure, and then only for the active input glyphs.
     fn add_reachable_glyphs(&self, ctx: &mut ClosureCtx<'_>) -> Result<(), ReadError> {
         for coverage in self
             .backtrack_coverages()
@@ -808,6 +932,112 @@ impl GlyphClosure for ContextFormat3<'_> {
     }
 }
 
+impl SubstitutionLookupList<'_> {
+    /// Return the set of glyphs in the coverage of any subtable of the given lookups.
+    ///
+    /// For contextual lookups, this is the coverage of the first input glyph.
+    /// Lookup indices that are out of range are an error.
+    pub fn input_glyphs(&self, lookups: &IntSet<u16>) -> Result<IntSet<GlyphId16>, ReadError> {
+        let mut glyphs = IntSet::empty();
+        for idx in lookups.iter() {
+            let lookup = self.lookups().get(idx as usize)?;
+            lookup.subtables()?.add_input_glyphs(&mut glyphs)?;
+        }
+        Ok(glyphs)
+    }
+}
+
+/// A trait for tables with a coverage table that determines where they apply
+trait InputGlyphs {
+    /// Add the glyphs in this table's (input) coverage to `glyphs`.
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError>;
+}
+
+impl InputGlyphs for SubstitutionSubtables<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        match self {
+            SubstitutionSubtables::Single(tables) => tables.add_input_glyphs(glyphs),
+            SubstitutionSubtables::Multiple(tables) => tables.add_input_glyphs(glyphs),
+            SubstitutionSubtables::Alternate(tables) => tables.add_input_glyphs(glyphs),
+            SubstitutionSubtables::Ligature(tables) => tables.add_input_glyphs(glyphs),
+            SubstitutionSubtables::Reverse(tables) => tables.add_input_glyphs(glyphs),
+            SubstitutionSubtables::Contextual(tables) => tables.add_input_glyphs(glyphs),
+            SubstitutionSubtables::ChainContextual(tables) => tables.add_input_glyphs(glyphs),
+        }
+    }
+}
+
+impl<'a, T: FontRead<'a> + InputGlyphs + 'a, Ext: ExtensionLookup<'a, T> + 'a> InputGlyphs
+    for Subtables<'a, T, Ext>
+{
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        self.iter().try_for_each(|t| t?.add_input_glyphs(glyphs))
+    }
+}
+
+impl InputGlyphs for SingleSubst<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        let coverage = match self {
+            SingleSubst::Format1(table) => table.coverage()?,
+            SingleSubst::Format2(table) => table.coverage()?,
+        };
+        glyphs.extend(coverage.iter());
+        Ok(())
+    }
+}
+
+impl InputGlyphs for MultipleSubstFormat1<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        glyphs.extend(self.coverage()?.iter());
+        Ok(())
+    }
+}
+
+impl InputGlyphs for AlternateSubstFormat1<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        glyphs.extend(self.coverage()?.iter());
+        Ok(())
+    }
+}
+
+impl InputGlyphs for LigatureSubstFormat1<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        glyphs.extend(self.coverage()?.iter());
+        Ok(())
+    }
+}
+
+impl InputGlyphs for ReverseChainSingleSubstFormat1<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        glyphs.extend(self.coverage()?.iter());
+        Ok(())
+    }
+}
+
+impl InputGlyphs for SequenceContext<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        let coverage = match self {
+            Self::Format1(table) => table.coverage()?,
+            Self::Format2(table) => table.coverage()?,
+            Self::Format3(table) => table.coverages().get(0)?,
+        };
+        glyphs.extend(coverage.iter());
+        Ok(())
+    }
+}
+
+impl InputGlyphs for ChainedSequenceContext<'_> {
+    fn add_input_glyphs(&self, glyphs: &mut IntSet<GlyphId16>) -> Result<(), ReadError> {
+        let coverage = match self {
+            Self::Format1(table) => table.coverage()?,
+            Self::Format2(table) => table.coverage()?,
+            Self::Format3(table) => table.input_coverages().get(0)?,
+        };
+        glyphs.extend(coverage.iter());
+        Ok(())
+    }
+}
+
 /// The set of classes for this set of glyphs
 fn make_class_set(glyphs: &IntSet<GlyphId16>, classdef: &ClassDef) -> IntSet<u16> {
     glyphs.iter().map(|gid| classdef.get(gid)).collect()
@@ -924,6 +1154,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn input_glyphs() {
+        // test input is font-test-data/test_data/fea/simple_closure.fea
+        let gsub = get_gsub(test_data::SIMPLE);
+        let glyph_map = GlyphMap::new(test_data::SIMPLE_GLYPHS);
+        let lookup_list = gsub.lookup_list().unwrap();
+
+        // single_sub_f2 covers 'a' and 'X'
+        let result = lookup_list.input_glyphs(&IntSet::from([1u16])).unwrap();
+        assert_closure_result!(glyph_map, result, &["a", "X"]);
+
+        // the remaining lookups only match 'a'
+        let result = lookup_list
+            .input_glyphs(&IntSet::from([0u16, 2, 3, 4, 5]))
+            .unwrap();
+        assert_closure_result!(glyph_map, result, &["a"]);
+    }
+
     #[test]
     fn recursive() {
         // a scenario in which one substitution adds glyphs that trigger additional
@@ -1013,6 +1261,49 @@ mod tests {
         assert_closure_result!(glyph_map, input, &["a", "b", "c"]);
     }
 
+    #[test]
+    fn feature_variations_at_location() {
+        // test input is font-test-data/test_data/fea/variations_closure_conditions.fea
+        let font = FontRef::new(test_data::VARIATIONS_CONDITIONS_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let fvar = font.fvar().unwrap();
+        let glyph_map = GlyphMap::new(test_data::VARIATIONS_CONDITIONS_GLYPHS);
+        let closure_at = |location: Option<&[(&[u8; 4], f32)]>| {
+            let location = location.map(|loc| {
+                loc.iter()
+                    .map(|(tag, value)| (Tag::new(tag), F2Dot14::from_f32(*value)))
+                    .collect::<Vec<_>>()
+            });
+            let input = [glyph_map.get_gid("a").unwrap()].into_iter().collect();
+            gsub.closure_glyphs_at_location(input, &fvar, location.as_deref())
+                .unwrap()
+        };
+
+        // no location: every condition is included
+        assert_closure_result!(glyph_map, closure_at(None), &["a", "b", "c", "d"]);
+        // the default location matches neither condition
+        assert_closure_result!(glyph_map, closure_at(Some(&[])), &["a", "b"]);
+        // wght=800 matches 'heavy' but not 'wide'
+        assert_closure_result!(
+            glyph_map,
+            closure_at(Some(&[(b"wght", 0.75)])),
+            &["a", "b", "c"]
+        );
+        // wdth=200 matches 'wide' but not 'heavy'; the order of the axes
+        // doesn't matter, and unknown axes are ignored
+        assert_closure_result!(
+            glyph_map,
+            closure_at(Some(&[(b"wdth", 1.0), (b"XXXX", 1.0)])),
+            &["a", "b", "d"]
+        );
+        // when both match, only the first record is applied
+        assert_closure_result!(
+            glyph_map,
+            closure_at(Some(&[(b"wdth", 1.0), (b"wght", 0.75)])),
+            &["a", "b", "c"]
+        );
+    }
+
     #[test]
     fn context_with_unreachable_rules() {
         let gsub = get_gsub(test_data::CONTEXT_WITH_UNREACHABLE_BITS);
@@ -1033,4 +1324,38 @@ mod tests {
         let nop = compute_closure(&gsub, &glyph_map, &["a", "b", "c"]);
         assert_closure_result!(glyph_map, nop, &["a", "b", "c"]);
     }
+
+    #[test]
+    fn reverse_chain_lookahead() {
+        use crate::FontRead;
+        use font_test_data::bebuffer::BeBuffer;
+
+        // rsub a' b by a.alt;
+        #[rustfmt::skip]
+        let words = [
+            1_u16, // format
+            14,    // coverage offset
+            0,     // backtrack count
+            1,     // lookahead count
+            20,    // lookahead coverage offset
+            1,     // substitute count
+            3,     // substitute: a.alt
+            1, 1, 1, // coverage: [a]
+            1, 1, 2, // lookahead coverage: [b]
+        ];
+        let buf = BeBuffer::new().extend(words);
+        let rsub = ReverseChainSingleSubstFormat1::read(buf.data().into()).unwrap();
+        let closure = |input: &[u16]| {
+            let mut glyphs = input.iter().copied().map(GlyphId16::new).collect();
+            rsub.add_reachable_glyphs(&mut ClosureCtx::new(&mut glyphs))
+                .unwrap();
+            glyphs.iter().map(|gid| gid.to_u16()).collect::<Vec<_>>()
+        };
+
+        // the lookahead glyph is required for the substitution to apply
+        assert_eq!(closure(&[1]), [1]);
+        // and on its own it does nothing
+        assert_eq!(closure(&[2]), [2]);
+        assert_eq!(closure(&[1, 2]), [1, 2, 3]);
+    }
 }
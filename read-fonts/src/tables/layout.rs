@@ -1,11 +1,19 @@
 //! OpenType Layout common table formats
 
+#[cfg(feature = "std")]
+mod closure;
 mod feature;
 mod lookup_flag;
 mod script;
 
 use core::cmp::Ordering;
 
+#[cfg(feature = "std")]
+pub use closure::{
+    closure_glyphs, closure_lookups_cross_table, merged_feature_tags, ClosureStats,
+    CollectFeaturesContext, CrossTableLookups, LangSysNode, LayoutTable, LookupReferenceWarning,
+    ScriptNode,
+};
 pub use lookup_flag::LookupFlag;
 pub use script::{ScriptTags, SelectedScript, UNICODE_TO_NEW_OPENTYPE_SCRIPT_TAGS};
 
@@ -148,7 +156,92 @@ impl FeatureTableSubstitutionRecord {
     }
 }
 
+/// A problem with the structure of a coverage table.
+///
+/// See [`CoverageTable::validate`]. Each variant contains the index of the
+/// offending glyph or range record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoverageError {
+    /// A glyph in a format 1 table is not greater than the previous glyph.
+    UnsortedGlyphs(usize),
+    /// A range record starts before the previous range record.
+    UnsortedRanges(usize),
+    /// A range record overlaps the previous range record.
+    OverlappingRanges(usize),
+    /// A range record ends before it starts.
+    InvalidRange(usize),
+    /// The start coverage index of a range record is not the number of glyphs
+    /// in the preceding records.
+    InconsistentCoverageIndex(usize),
+}
+
+impl core::fmt::Display for CoverageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoverageError::UnsortedGlyphs(ix) => write!(f, "glyph {ix} is out of order"),
+            CoverageError::UnsortedRanges(ix) => write!(f, "range {ix} is out of order"),
+            CoverageError::OverlappingRanges(ix) => {
+                write!(f, "range {ix} overlaps the previous range")
+            }
+            CoverageError::InvalidRange(ix) => write!(f, "range {ix} ends before it starts"),
+            CoverageError::InconsistentCoverageIndex(ix) => {
+                write!(f, "range {ix} has an incorrect start coverage index")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CoverageError {}
+
 impl<'a> CoverageTable<'a> {
+    /// Check that the glyphs in this table are sorted and unique.
+    ///
+    /// Lookups use a binary search, so a malformed table can cause glyphs
+    /// to be missed, or to be given the wrong coverage index. In format 2,
+    /// this also checks that each range's start coverage index is correct.
+    pub fn validate(&self) -> Result<(), CoverageError> {
+        match self {
+            CoverageTable::Format1(table) => {
+                let glyphs = table.glyph_array();
+                match glyphs
+                    .windows(2)
+                    .position(|pair| pair[0].get() >= pair[1].get())
+                {
+                    Some(ix) => Err(CoverageError::UnsortedGlyphs(ix + 1)),
+                    None => Ok(()),
+                }
+            }
+            CoverageTable::Format2(table) => {
+                let mut prev: Option<&RangeRecord> = None;
+                let mut coverage_index = 0;
+                for (ix, record) in table.range_records().iter().enumerate() {
+                    if record.start_glyph_id() > record.end_glyph_id() {
+                        return Err(CoverageError::InvalidRange(ix));
+                    }
+                    if let Some(prev) = prev {
+                        if record.start_glyph_id() < prev.start_glyph_id() {
+                            return Err(CoverageError::UnsortedRanges(ix));
+                        }
+                        if record.start_glyph_id() <= prev.end_glyph_id() {
+                            return Err(CoverageError::OverlappingRanges(ix));
+                        }
+                    }
+                    if record.start_coverage_index() as usize != coverage_index {
+                        return Err(CoverageError::InconsistentCoverageIndex(ix));
+                    }
+                    coverage_index += record.population();
+                    prev = Some(record);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Iterate over the glyphs in this table, regardless of format.
+    ///
+    /// Glyphs are returned in coverage index order; for format 2 each range
+    /// record is expanded into its glyphs.
     pub fn iter(&self) -> impl Iterator<Item = GlyphId16> + 'a {
         // all one expression so that we have a single return type
         let (iter1, iter2) = match self {
@@ -181,6 +274,46 @@ impl<'a> CoverageTable<'a> {
             CoverageTable::Format2(sub) => sub.intersects(glyphs),
         }
     }
+
+    /// Return the glyphs in `universe` that are not in this table.
+    #[cfg(feature = "std")]
+    pub fn complement(&self, universe: &IntSet<GlyphId16>) -> IntSet<GlyphId16> {
+        let mut result = universe.clone();
+        match self {
+            CoverageTable::Format1(sub) => {
+                result.remove_all(sub.glyph_array().iter().map(|gid| gid.get()))
+            }
+            CoverageTable::Format2(sub) => {
+                for record in sub.range_records() {
+                    result.remove_range(record.start_glyph_id()..=record.end_glyph_id());
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns `true` if any glyph appears more than once in this table.
+    ///
+    /// In a well-formed table the coverage index of each glyph is its
+    /// position in the table; a duplicated glyph takes up more than one index.
+    /// Tools that rebuild the table (which removes the duplicates and so
+    /// renumbers any later glyphs) can use this to reject such tables instead.
+    #[cfg(feature = "std")]
+    pub fn has_duplicates(&self) -> bool {
+        let mut seen = IntSet::<u16>::empty();
+        match self {
+            CoverageTable::Format1(sub) => !sub
+                .glyph_array()
+                .iter()
+                .all(|gid| seen.insert(gid.get().to_u16())),
+            CoverageTable::Format2(sub) => sub.range_records().iter().any(|record| {
+                let range = record.start_glyph_id().to_u16()..=record.end_glyph_id().to_u16();
+                let duplicate = seen.intersects_range(range.clone());
+                seen.insert_range(range);
+                duplicate
+            }),
+        }
+    }
 }
 
 impl CoverageFormat1<'_> {
@@ -410,6 +543,19 @@ impl ClassDef<'_> {
             ClassDef::Format2(table) => table.population(),
         }
     }
+
+    /// Group the glyphs in this table by class.
+    ///
+    /// This is the inverse of [`get`](Self::get). Class 0 is omitted, since
+    /// it implicitly contains every glyph that is not assigned another class.
+    #[cfg(feature = "std")]
+    pub fn class_glyphs(&self) -> std::collections::BTreeMap<u16, IntSet<GlyphId16>> {
+        let mut result = std::collections::BTreeMap::<u16, IntSet<GlyphId16>>::new();
+        for (gid, class) in self.iter().filter(|(_, class)| *class != 0) {
+            result.entry(class).or_default().insert(gid);
+        }
+        result
+    }
 }
 
 impl<'a> Device<'a> {
@@ -525,6 +671,96 @@ mod tests {
         assert_eq!(coverage.get(GlyphId::new(40)), None);
     }
 
+    #[test]
+    fn coverage_iter_formats_agree() {
+        // glyphs (5, 6, 7, 30, 31) in each format
+        const COV1_DATA: FontData = FontData::new(&[0, 1, 0, 5, 0, 5, 0, 6, 0, 7, 0, 30, 0, 31]);
+        const COV2_DATA: FontData =
+            FontData::new(&[0, 2, 0, 2, 0, 5, 0, 7, 0, 0, 0, 30, 0, 31, 0, 3]);
+        let format1 = CoverageTable::read(COV1_DATA).unwrap();
+        let format2 = CoverageTable::read(COV2_DATA).unwrap();
+        assert!(matches!(format1, CoverageTable::Format1(_)));
+        assert!(matches!(format2, CoverageTable::Format2(_)));
+        let expected = [5, 6, 7, 30, 31].map(GlyphId16::new);
+        assert_eq!(format1.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(format2.iter().collect::<Vec<_>>(), expected);
+        for (i, gid) in format2.iter().enumerate() {
+            assert_eq!(format2.get(gid), Some(i as u16));
+        }
+    }
+
+    #[test]
+    fn coverage_validate() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        fn format2(ranges: &[[u16; 3]]) -> BeBuffer {
+            BeBuffer::new()
+                .extend([2u16, ranges.len() as u16])
+                .extend(ranges.iter().flatten().copied())
+        }
+        let check = |buf: BeBuffer| CoverageTable::read(buf.data().into()).unwrap().validate();
+
+        assert_eq!(check(format2(&[[5, 7, 0], [30, 31, 3]])), Ok(()));
+        assert_eq!(
+            check(format2(&[[5, 7, 0], [7, 9, 3]])),
+            Err(CoverageError::OverlappingRanges(1))
+        );
+        assert_eq!(
+            check(format2(&[[30, 31, 0], [5, 7, 2]])),
+            Err(CoverageError::UnsortedRanges(1))
+        );
+        assert_eq!(
+            check(format2(&[[5, 7, 0], [30, 31, 2]])),
+            Err(CoverageError::InconsistentCoverageIndex(1))
+        );
+        assert_eq!(
+            check(format2(&[[7, 5, 0]])),
+            Err(CoverageError::InvalidRange(0))
+        );
+        assert_eq!(
+            check(BeBuffer::new().extend([1u16, 3, 5, 9, 9])),
+            Err(CoverageError::UnsortedGlyphs(2))
+        );
+    }
+
+    #[test]
+    fn coverage_complement() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        let universe = (0..10).map(GlyphId16::new).collect::<IntSet<_>>();
+        let complement = |words: &[u16]| {
+            let buf = BeBuffer::new().extend(words.iter().copied());
+            CoverageTable::read(buf.data().into())
+                .unwrap()
+                .complement(&universe)
+                .iter()
+                .map(|gid| gid.to_u16())
+                .collect::<Vec<_>>()
+        };
+        // glyph 12 is outside the universe
+        assert_eq!(complement(&[1, 4, 1, 3, 5, 12]), [0, 2, 4, 6, 7, 8, 9]);
+        assert_eq!(complement(&[2, 2, 2, 4, 0, 8, 12, 3]), [0, 1, 5, 6, 7]);
+        assert_eq!(complement(&[1, 0]), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn coverage_has_duplicates() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        let read = |words: &[u16]| {
+            let buf = BeBuffer::new().extend(words.iter().copied());
+            CoverageTable::read(buf.data().into())
+                .unwrap()
+                .has_duplicates()
+        };
+        assert!(!read(&[1, 3, 5, 9, 12]));
+        assert!(read(&[1, 3, 5, 9, 5]));
+        assert!(read(&[1, 3, 5, 9, 9]));
+        // format 2, with one glyph in two ranges
+        assert!(!read(&[2, 2, 5, 7, 0, 8, 9, 3]));
+        assert!(read(&[2, 2, 5, 7, 0, 7, 9, 3]));
+    }
+
     #[test]
     fn classdef_get_format2() {
         let classdef = ClassDef::read(FontData::new(
@@ -553,6 +789,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn classdef_class_glyphs() {
+        let classdef = ClassDef::read(FontData::new(
+            font_test_data::gdef::MARKATTACHCLASSDEF_TABLE,
+        ))
+        .unwrap();
+        let class_glyphs = classdef.class_glyphs();
+        assert_eq!(class_glyphs.keys().copied().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(
+            class_glyphs[&1],
+            [616, 617, 618, 624, 625, 626]
+                .into_iter()
+                .map(GlyphId16::new)
+                .collect()
+        );
+        assert_eq!(
+            class_glyphs[&2],
+            [652, 653, 654, 655, 661]
+                .into_iter()
+                .map(GlyphId16::new)
+                .collect()
+        );
+        assert_eq!(
+            class_glyphs.values().map(|set| set.len()).sum::<u64>() as usize,
+            classdef.population()
+        );
+    }
+
     #[test]
     fn delta_decode() {
         // these examples come from the spec
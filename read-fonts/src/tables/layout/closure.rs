@@ -0,0 +1,1609 @@
+//! Computing the features and lookups reachable in a layout table
+//!
+//! Features are reachable from the language systems of the selected scripts.
+//! Lookups are reachable either directly, from a feature, or indirectly, via
+//! the lookup records of a (chained) contextual lookup.
+
+use types::{GlyphId16, Tag};
+
+use crate::{collections::IntSet, ReadError};
+
+use super::{
+    ChainedSequenceContext, CoverageTable, FeatureList, LangSys, LangSysRecord, Script, ScriptList,
+    ScriptRecord, SequenceContext,
+};
+use crate::tables::{
+    gpos::{Gpos, PositionSubtables},
+    gsub::{Gsub, SubstitutionLookupList, SubstitutionSubtables},
+};
+
+// limits to guard against malicious fonts; these match HarfBuzz
+const MAX_SCRIPTS: u16 = 500;
+const MAX_LANGSYS: u16 = 2000;
+const MAX_FEATURE_INDICES: u16 = 1500;
+
+const NO_REQUIRED_FEATURE: u16 = 0xFFFF;
+
+// the tag used to select the default language system of a script
+const DEFAULT_LANGUAGE: Tag = Tag::new(b"dflt");
+
+/// Counters describing the work done while collecting features.
+///
+/// These are intended for diagnosing slow closures on unusual fonts, and do
+/// not affect the result.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClosureStats {
+    /// The number of distinct scripts whose language systems were walked
+    pub scripts_visited: u32,
+    /// The number of distinct language systems whose features were walked
+    pub langsys_visited: u32,
+    /// The number of distinct feature indices that were collected
+    pub features_collected: u32,
+    /// The number of feature index references rejected by the feature filter
+    pub filter_rejections: u32,
+    /// The number of times a script or language system was skipped because
+    /// it had already been visited
+    ///
+    /// This is distinct from records skipped because a limit was reached,
+    /// and counts the records shared by (or cyclically referenced from)
+    /// several places in the script list.
+    pub revisit_count: u32,
+}
+
+/// A script, and the features reachable from each of its language systems.
+///
+/// See [`ScriptList::feature_tree`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptNode {
+    /// The script tag
+    pub tag: Tag,
+    /// The language systems of this script, starting with the default
+    /// language system (if present) which is given the tag `dflt`.
+    pub languages: Vec<LangSysNode>,
+}
+
+/// A language system, and the features it references.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LangSysNode {
+    /// The language system tag
+    pub tag: Tag,
+    /// The index of the required feature, if there is one
+    pub required: Option<u16>,
+    /// The indices of the other features, in the order they appear in the font
+    pub features: Vec<u16>,
+}
+
+/// State used while collecting the features reachable from a set of scripts
+/// and languages.
+///
+/// This mirrors `hb_collect_features_context_t` in HarfBuzz. Scripts and
+/// language systems are identified by their byte offset from the start of
+/// the script list, so that records sharing the same data are only visited
+/// once. (HarfBuzz compares addresses instead; offsets are computed from the
+/// offsets stored in the font, and so do not depend on how the font data is
+/// laid out in memory.)
+///
+/// This is the low-level API used by [`Gsub::collect_features`] and
+/// [`Gpos::collect_features`], for callers that need to drive the traversal
+/// themselves. Before walking a script or language system, the caller should
+/// check [`script_visited`](Self::script_visited) or
+/// [`langsys_visited`](Self::langsys_visited), and before adding the features
+/// of a language system it should check
+/// [`feature_indices_limit_exceeded`](Self::feature_indices_limit_exceeded).
+///
+/// # Examples
+///
+/// Collecting only the first feature of the default language system of each
+/// script:
+///
+/// ```
+/// # use font_test_data::closure::FEATURE_CLOSURE;
+/// use read_fonts::{
+///     collections::IntSet, tables::layout::CollectFeaturesContext, FontRef, TableProvider,
+/// };
+///
+/// let gsub = FontRef::new(FEATURE_CLOSURE)?.gsub()?;
+/// let script_list = gsub.script_list()?;
+/// let feature_list = gsub.feature_list()?;
+/// let mut on_feature = |_: u16| ();
+/// let mut c = CollectFeaturesContext::new(None, &feature_list, &mut on_feature);
+/// for record in script_list.script_records() {
+///     let script_offset = record.script_offset().to_u32();
+///     if c.script_visited(script_offset) {
+///         continue;
+///     }
+///     let script = record.script(script_list.offset_data())?;
+///     let Some(lang_sys) = script.default_lang_sys().transpose()? else {
+///         continue;
+///     };
+///     let offset = script_offset + script.default_lang_sys_offset().offset().to_u32();
+///     if c.langsys_visited(offset) || c.feature_indices_limit_exceeded(1) {
+///         continue;
+///     }
+///     if let Some(first) = lang_sys.feature_indices().first() {
+///         c.add_feature_index(first.get());
+///     }
+/// }
+/// assert_eq!(c.feature_indices(), &IntSet::from([0u16]));
+/// assert_eq!(c.stats().scripts_visited, 2);
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct CollectFeaturesContext<'a> {
+    script_count: u16,
+    langsys_count: u16,
+    feature_index_count: u16,
+    visited_script: IntSet<u32>,
+    visited_langsys: IntSet<u32>,
+    feature_indices: IntSet<u16>,
+    on_feature: &'a mut dyn FnMut(u16),
+    feature_indices_filter: Option<IntSet<u16>>,
+    stats: ClosureStats,
+}
+
+impl<'a> CollectFeaturesContext<'a> {
+    /// Create a new context.
+    ///
+    /// If `features` is `None` all features are collected; otherwise only
+    /// features with one of the given tags are collected.
+    ///
+    /// `on_feature` is called once for each collected feature index, when it
+    /// is first found.
+    pub fn new(
+        features: Option<&IntSet<Tag>>,
+        feature_list: &FeatureList,
+        on_feature: &'a mut dyn FnMut(u16),
+    ) -> Self {
+        let filter =
+            features.map(|tags| compute_feature_filter(feature_list, |tag| tags.contains(tag)));
+        Self::with_feature_filter(filter, on_feature)
+    }
+
+    /// Create a new context that only collects the features whose tag
+    /// matches `filter`.
+    ///
+    /// `on_feature` is called once for each collected feature index, when it
+    /// is first found.
+    pub fn new_matching(
+        filter: impl Fn(Tag) -> bool,
+        feature_list: &FeatureList,
+        on_feature: &'a mut dyn FnMut(u16),
+    ) -> Self {
+        Self::with_feature_filter(
+            Some(compute_feature_filter(feature_list, filter)),
+            on_feature,
+        )
+    }
+
+    // `filter` contains the feature indices to collect, or `None` for all
+    fn with_feature_filter(
+        filter: Option<IntSet<u16>>,
+        on_feature: &'a mut dyn FnMut(u16),
+    ) -> Self {
+        Self {
+            script_count: 0,
+            langsys_count: 0,
+            feature_index_count: 0,
+            visited_script: IntSet::empty(),
+            visited_langsys: IntSet::empty(),
+            feature_indices: IntSet::empty(),
+            on_feature,
+            feature_indices_filter: filter,
+            stats: ClosureStats::default(),
+        }
+    }
+
+    /// Returns `true` if the script at this offset from the start of the
+    /// script list should be skipped, either because it was already visited
+    /// or because too many scripts have been visited.
+    pub fn script_visited(&mut self, offset: u32) -> bool {
+        if self.script_count >= MAX_SCRIPTS {
+            return true;
+        }
+        self.script_count += 1;
+        if !self.visited_script.insert(offset) {
+            self.stats.revisit_count += 1;
+            return true;
+        }
+        self.stats.scripts_visited += 1;
+        false
+    }
+
+    /// Returns `true` if the language system at this offset from the start
+    /// of the script list should be skipped, either because it was already
+    /// visited or because too many language systems have been visited.
+    pub fn langsys_visited(&mut self, offset: u32) -> bool {
+        if self.langsys_count >= MAX_LANGSYS {
+            return true;
+        }
+        self.langsys_count += 1;
+        if !self.visited_langsys.insert(offset) {
+            self.stats.revisit_count += 1;
+            return true;
+        }
+        self.stats.langsys_visited += 1;
+        false
+    }
+
+    /// Record that `count` more feature indices are being visited, returning
+    /// `true` if this exceeds the limit.
+    pub fn feature_indices_limit_exceeded(&mut self, count: u16) -> bool {
+        self.feature_index_count = self.feature_index_count.saturating_add(count);
+        self.feature_index_count > MAX_FEATURE_INDICES
+    }
+
+    /// Add a feature index to the result, unless it is excluded by the
+    /// feature filter.
+    ///
+    /// `on_feature` is called if the index was not already collected.
+    pub fn add_feature_index(&mut self, index: u16) {
+        if self
+            .feature_indices_filter
+            .as_ref()
+            .is_none_or(|filter| filter.contains(index))
+        {
+            if self.feature_indices.insert(index) {
+                self.stats.features_collected += 1;
+                (self.on_feature)(index);
+            }
+        } else {
+            self.stats.filter_rejections += 1;
+        }
+    }
+
+    /// The feature indices collected so far.
+    pub fn feature_indices(&self) -> &IntSet<u16> {
+        &self.feature_indices
+    }
+
+    /// Counters describing the work done so far.
+    pub fn stats(&self) -> ClosureStats {
+        self.stats
+    }
+}
+
+// the indices of the features whose tag matches the filter
+fn compute_feature_filter(feature_list: &FeatureList, filter: impl Fn(Tag) -> bool) -> IntSet<u16> {
+    feature_list
+        .feature_records()
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| filter(record.feature_tag()))
+        .map(|(i, _)| i as u16)
+        .collect()
+}
+
+impl ScriptList<'_> {
+    /// Collect the indices of the features reachable from the given scripts
+    /// and languages, passing each to `on_feature` as it is found, and
+    /// returning statistics about the walk.
+    ///
+    /// For each of the filters, `None` means that everything is included.
+    pub(crate) fn collect_features(
+        &self,
+        mut c: CollectFeaturesContext,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+    ) -> Result<ClosureStats, ReadError> {
+        match scripts {
+            None => {
+                for record in self.script_records() {
+                    self.collect_script_features(&mut c, record, languages)?;
+                }
+            }
+            Some(scripts) => {
+                for tag in scripts.iter() {
+                    let Some(index) = self.index_for_tag(tag) else {
+                        continue;
+                    };
+                    let record = &self.script_records()[index as usize];
+                    self.collect_script_features(&mut c, record, languages)?;
+                }
+            }
+        }
+        Ok(c.stats)
+    }
+
+    /// Return the scripts in this list, along with the features referenced by
+    /// each of their language systems.
+    ///
+    /// Unlike feature collection, language systems that are shared by several
+    /// scripts appear under each of them.
+    pub fn feature_tree(&self) -> Result<Vec<ScriptNode>, ReadError> {
+        self.script_records()
+            .iter()
+            .map(|record| {
+                let script = record.script(self.offset_data())?;
+                let mut languages = Vec::with_capacity(script.lang_sys_count() as usize + 1);
+                if let Some(lang_sys) = script.default_lang_sys().transpose()? {
+                    languages.push(lang_sys.feature_node(DEFAULT_LANGUAGE));
+                }
+                for record in script.lang_sys_records() {
+                    let lang_sys = record.lang_sys(script.offset_data())?;
+                    languages.push(lang_sys.feature_node(record.lang_sys_tag()));
+                }
+                Ok(ScriptNode {
+                    tag: record.script_tag(),
+                    languages,
+                })
+            })
+            .collect()
+    }
+
+    fn collect_script_features(
+        &self,
+        c: &mut CollectFeaturesContext,
+        record: &ScriptRecord,
+        languages: Option<&IntSet<Tag>>,
+    ) -> Result<(), ReadError> {
+        let offset = record.script_offset().to_u32();
+        if c.script_visited(offset) {
+            return Ok(());
+        }
+        record
+            .script(self.offset_data())?
+            .collect_features(c, offset, languages)
+    }
+}
+
+impl Script<'_> {
+    // `offset` is the offset of this script from the start of the script list
+    fn collect_features(
+        &self,
+        c: &mut CollectFeaturesContext,
+        offset: u32,
+        languages: Option<&IntSet<Tag>>,
+    ) -> Result<(), ReadError> {
+        match languages {
+            None => {
+                self.collect_default_lang_sys_features(c, offset)?;
+                for record in self.lang_sys_records() {
+                    self.collect_lang_sys_features(c, offset, record)?;
+                }
+            }
+            Some(languages) => {
+                for tag in languages.iter() {
+                    // the default language system has no record of its own;
+                    // fall back to a record in case a font tags one 'dflt'
+                    if tag == DEFAULT_LANGUAGE
+                        && self.collect_default_lang_sys_features(c, offset)?
+                    {
+                        continue;
+                    }
+                    let Some(index) = self.lang_sys_index_for_tag(tag) else {
+                        continue;
+                    };
+                    let record = &self.lang_sys_records()[index as usize];
+                    self.collect_lang_sys_features(c, offset, record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // returns `true` if the script has a default language system
+    fn collect_default_lang_sys_features(
+        &self,
+        c: &mut CollectFeaturesContext,
+        script_offset: u32,
+    ) -> Result<bool, ReadError> {
+        let Some(lang_sys) = self.default_lang_sys().transpose()? else {
+            return Ok(false);
+        };
+        let offset = script_offset + self.default_lang_sys_offset().offset().to_u32();
+        lang_sys.collect_features(c, offset);
+        Ok(true)
+    }
+
+    fn collect_lang_sys_features(
+        &self,
+        c: &mut CollectFeaturesContext,
+        script_offset: u32,
+        record: &LangSysRecord,
+    ) -> Result<(), ReadError> {
+        let offset = script_offset + record.lang_sys_offset().to_u32();
+        record
+            .lang_sys(self.offset_data())?
+            .collect_features(c, offset);
+        Ok(())
+    }
+}
+
+impl LangSys<'_> {
+    /// Return the indices of all the features of this language system,
+    /// including the required feature, if there is one.
+    pub fn all_feature_indices(&self) -> IntSet<u16> {
+        let mut indices = self
+            .feature_indices()
+            .iter()
+            .map(|idx| idx.get())
+            .collect::<IntSet<_>>();
+        let required = self.required_feature_index();
+        if required != NO_REQUIRED_FEATURE {
+            indices.insert(required);
+        }
+        indices
+    }
+
+    fn feature_node(&self, tag: Tag) -> LangSysNode {
+        let required = self.required_feature_index();
+        LangSysNode {
+            tag,
+            required: (required != NO_REQUIRED_FEATURE).then_some(required),
+            features: self.feature_indices().iter().map(|idx| idx.get()).collect(),
+        }
+    }
+
+    // `offset` is the offset of this language system from the start of the
+    // script list
+    fn collect_features(&self, c: &mut CollectFeaturesContext, offset: u32) {
+        // a language system without features contributes nothing, so it
+        // shouldn't count against the limit on language systems
+        if self.feature_index_count() == 0 && self.required_feature_index() == NO_REQUIRED_FEATURE {
+            return;
+        }
+        if c.langsys_visited(offset) {
+            return;
+        }
+        if c.feature_indices_limit_exceeded(self.feature_index_count()) {
+            return;
+        }
+        let required = self.required_feature_index();
+        if required != NO_REQUIRED_FEATURE {
+            c.add_feature_index(required);
+        }
+        for index in self.feature_indices() {
+            c.add_feature_index(index.get());
+        }
+    }
+}
+
+impl Gsub<'_> {
+    /// Return the indices of the features reachable from the given scripts
+    /// and languages, limited to the given feature tags.
+    ///
+    /// For each of the filters, `None` means that everything is included.
+    /// When languages are provided, the default language system of each
+    /// script is only included if the languages contain the `dflt` tag.
+    ///
+    /// Scripts are matched by tag only: `DFLT` selects a script record with
+    /// that tag, and there is no fallback to it when a requested script is
+    /// missing.
+    pub fn collect_features(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+    ) -> Result<IntSet<u16>, ReadError> {
+        self.feature_tables()?
+            .collect_features(scripts, languages, features)
+    }
+
+    /// Like [`Self::collect_features`], but also return counters describing the work
+    /// done, for profiling.
+    pub fn collect_features_with_stats(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+    ) -> Result<(IntSet<u16>, ClosureStats), ReadError> {
+        self.feature_tables()?
+            .collect_features_with_stats(scripts, languages, features)
+    }
+
+    /// Like [`Self::collect_features`], but call `on_feature` with each
+    /// feature index as soon as it is found, instead of returning a set.
+    ///
+    /// Each index is reported once. The same limits apply, so the reported
+    /// indices are exactly those that `collect_features` would return.
+    pub fn collect_features_with(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+        on_feature: impl FnMut(u16),
+    ) -> Result<ClosureStats, ReadError> {
+        self.feature_tables()?
+            .collect_features_with(scripts, languages, features, on_feature)
+    }
+
+    /// Like [`Self::collect_features`], but only collect the features whose
+    /// tag matches `filter`.
+    ///
+    /// This can be used to select a family of features, such as all of the
+    /// stylistic sets.
+    pub fn collect_features_matching(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        filter: impl Fn(Tag) -> bool,
+    ) -> Result<IntSet<u16>, ReadError> {
+        self.feature_tables()?
+            .collect_features_matching(scripts, languages, filter)
+    }
+
+    fn feature_tables(&self) -> Result<FeatureTables<'_>, ReadError> {
+        Ok(FeatureTables {
+            script_list: self.script_list()?,
+            feature_list: self.feature_list()?,
+        })
+    }
+
+    /// Returns `true` if the given lookup is reachable from the given features.
+    ///
+    /// The features are indices into the feature list; indices that are out
+    /// of range are ignored. A lookup is reachable if it is referenced by one
+    /// of the features, or by the lookup records of a (chained) contextual
+    /// lookup that is itself reachable.
+    ///
+    /// This searches only until the lookup is found, which is cheaper than
+    /// computing the full set of reachable lookups with
+    /// [`closure_lookups_cross_table`] when only a few lookups are of interest.
+    pub fn is_lookup_reachable(
+        &self,
+        lookup: u16,
+        from_features: &IntSet<u16>,
+    ) -> Result<bool, ReadError> {
+        let lookups = self.lookup_list()?;
+        let lookup_count = lookups.lookup_count();
+        if lookup >= lookup_count {
+            return Ok(false);
+        }
+        let mut todo = feature_lookups(&self.feature_list()?, from_features)?;
+        let mut visited = IntSet::empty();
+        while let Some(next) = todo.pop() {
+            if next == lookup {
+                return Ok(true);
+            }
+            if next >= lookup_count || !visited.insert(next) {
+                continue;
+            }
+            add_nested_gsub_lookups(&lookups, next, &mut todo)?;
+        }
+        Ok(false)
+    }
+}
+
+impl Gpos<'_> {
+    /// Return the indices of the features reachable from the given scripts
+    /// and languages, limited to the given feature tags.
+    ///
+    /// See [`Gsub::collect_features`] for details.
+    pub fn collect_features(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+    ) -> Result<IntSet<u16>, ReadError> {
+        self.feature_tables()?
+            .collect_features(scripts, languages, features)
+    }
+
+    /// Like [`Self::collect_features`], but also return counters describing the work
+    /// done, for profiling.
+    pub fn collect_features_with_stats(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+    ) -> Result<(IntSet<u16>, ClosureStats), ReadError> {
+        self.feature_tables()?
+            .collect_features_with_stats(scripts, languages, features)
+    }
+
+    /// Like [`Self::collect_features`], but call `on_feature` with each
+    /// feature index as soon as it is found, instead of returning a set.
+    ///
+    /// Each index is reported once. The same limits apply, so the reported
+    /// indices are exactly those that `collect_features` would return.
+    pub fn collect_features_with(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+        on_feature: impl FnMut(u16),
+    ) -> Result<ClosureStats, ReadError> {
+        self.feature_tables()?
+            .collect_features_with(scripts, languages, features, on_feature)
+    }
+
+    /// Like [`Self::collect_features`], but only collect the features whose
+    /// tag matches `filter`.
+    ///
+    /// This can be used to select a family of features, such as all of the
+    /// stylistic sets.
+    pub fn collect_features_matching(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        filter: impl Fn(Tag) -> bool,
+    ) -> Result<IntSet<u16>, ReadError> {
+        self.feature_tables()?
+            .collect_features_matching(scripts, languages, filter)
+    }
+
+    fn feature_tables(&self) -> Result<FeatureTables<'_>, ReadError> {
+        Ok(FeatureTables {
+            script_list: self.script_list()?,
+            feature_list: self.feature_list()?,
+        })
+    }
+}
+
+// the tables used to collect features, which GSUB and GPOS have in common
+struct FeatureTables<'a> {
+    script_list: ScriptList<'a>,
+    feature_list: FeatureList<'a>,
+}
+
+impl FeatureTables<'_> {
+    fn collect_features(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+    ) -> Result<IntSet<u16>, ReadError> {
+        self.collect_features_with_stats(scripts, languages, features)
+            .map(|(features, _)| features)
+    }
+
+    fn collect_features_with_stats(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+    ) -> Result<(IntSet<u16>, ClosureStats), ReadError> {
+        let mut indices = IntSet::empty();
+        let stats = self.collect_features_with(scripts, languages, features, |idx| {
+            indices.insert(idx);
+        })?;
+        Ok((indices, stats))
+    }
+
+    fn collect_features_with(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        features: Option<&IntSet<Tag>>,
+        mut on_feature: impl FnMut(u16),
+    ) -> Result<ClosureStats, ReadError> {
+        let c = CollectFeaturesContext::new(features, &self.feature_list, &mut on_feature);
+        self.script_list.collect_features(c, scripts, languages)
+    }
+
+    fn collect_features_matching(
+        &self,
+        scripts: Option<&IntSet<Tag>>,
+        languages: Option<&IntSet<Tag>>,
+        filter: impl Fn(Tag) -> bool,
+    ) -> Result<IntSet<u16>, ReadError> {
+        let mut indices = IntSet::empty();
+        let mut on_feature = |idx| {
+            indices.insert(idx);
+        };
+        let c = CollectFeaturesContext::new_matching(filter, &self.feature_list, &mut on_feature);
+        self.script_list.collect_features(c, scripts, languages)?;
+        Ok(indices)
+    }
+}
+
+impl FeatureList<'_> {
+    /// Return the indices of the features that are not referenced by any
+    /// language system in the provided script list.
+    ///
+    /// These features can never be applied, and are candidates for removal.
+    ///
+    /// Every language system is checked; unlike feature collection, this does
+    /// not stop after visiting some maximum number of records, so a feature
+    /// is never reported as unreachable just because the font is large.
+    pub fn unreachable_features(&self, script_list: &ScriptList) -> Result<IntSet<u16>, ReadError> {
+        let mut result = IntSet::empty();
+        if let Some(last) = self.feature_count().checked_sub(1) {
+            result.insert_range(0..=last);
+        }
+        for record in script_list.script_records() {
+            let script = record.script(script_list.offset_data())?;
+            if let Some(lang_sys) = script.default_lang_sys().transpose()? {
+                result.subtract(&lang_sys.all_feature_indices());
+            }
+            for record in script.lang_sys_records() {
+                let lang_sys = record.lang_sys(script.offset_data())?;
+                result.subtract(&lang_sys.all_feature_indices());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Return the indices of the features that reference the given lookup.
+    ///
+    /// This only considers the lookup indices of each feature record, and
+    /// not lookups that are reachable indirectly, via contextual lookups or
+    /// feature variations.
+    pub fn features_for_lookup(&self, lookup_index: u16) -> Result<IntSet<u16>, ReadError> {
+        let mut result = IntSet::empty();
+        for (i, record) in self.feature_records().iter().enumerate() {
+            let feature = record.feature(self.offset_data())?;
+            if feature
+                .lookup_list_indices()
+                .iter()
+                .any(|idx| idx.get() == lookup_index)
+            {
+                result.insert(i as u16);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// One of the two tables that contain layout lookups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LayoutTable {
+    /// The glyph substitution table
+    Gsub,
+    /// The glyph positioning table
+    Gpos,
+}
+
+/// A lookup index that could not be resolved in the table that referenced it.
+///
+/// These are skipped during closure, instead of producing an error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupReferenceWarning {
+    /// The table containing the reference
+    pub table: LayoutTable,
+    /// The lookup containing the reference, or `None` if it is referenced by a feature
+    pub from_lookup: Option<u16>,
+    /// The index of the referenced lookup
+    pub lookup_index: u16,
+    /// `true` if the index would be valid in the other table.
+    ///
+    /// This usually indicates that a GSUB lookup mistakenly references a GPOS
+    /// lookup, or vice versa.
+    pub valid_in_other_table: bool,
+}
+
+/// The lookups reachable from a set of features in GSUB and GPOS.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CrossTableLookups {
+    /// The reachable GSUB lookups
+    pub gsub: IntSet<u16>,
+    /// The reachable GPOS lookups
+    pub gpos: IntSet<u16>,
+    /// Any lookup references that could not be resolved
+    pub warnings: Vec<LookupReferenceWarning>,
+}
+
+/// Compute the lookups reachable from the given features in both GSUB and GPOS.
+///
+/// The seed features are indices into each table's feature list; indices
+/// that are out of range are ignored. Lookups are followed recursively through
+/// the lookup records of contextual lookups. A lookup index that is out of
+/// range for the table containing it is not followed; it is instead reported
+/// as a [`LookupReferenceWarning`], so that it cannot produce a dangling
+/// reference in a subset font.
+pub fn closure_lookups_cross_table(
+    gsub: Option<&Gsub>,
+    gpos: Option<&Gpos>,
+    gsub_features: &IntSet<u16>,
+    gpos_features: &IntSet<u16>,
+) -> Result<CrossTableLookups, ReadError> {
+    let gsub_lookups = gsub.map(|gsub| gsub.lookup_list()).transpose()?;
+    let gpos_lookups = gpos.map(|gpos| gpos.lookup_list()).transpose()?;
+    let gsub_count = gsub_lookups.as_ref().map_or(0, |l| l.lookup_count());
+    let gpos_count = gpos_lookups.as_ref().map_or(0, |l| l.lookup_count());
+
+    let mut result = CrossTableLookups::default();
+    if let (Some(gsub), Some(lookups)) = (gsub, gsub_lookups) {
+        let seeds = feature_lookups(&gsub.feature_list()?, gsub_features)?;
+        result.gsub = close_over_lookups(
+            LayoutTable::Gsub,
+            seeds,
+            (gsub_count, gpos_count),
+            &mut result.warnings,
+            |idx, out| add_nested_gsub_lookups(&lookups, idx, out),
+        )?;
+    }
+    if let (Some(gpos), Some(lookups)) = (gpos, gpos_lookups) {
+        let seeds = feature_lookups(&gpos.feature_list()?, gpos_features)?;
+        result.gpos = close_over_lookups(
+            LayoutTable::Gpos,
+            seeds,
+            (gpos_count, gsub_count),
+            &mut result.warnings,
+            |idx, out| match lookups.lookups().get(idx as usize)?.subtables()? {
+                PositionSubtables::Contextual(subtables) => subtables
+                    .iter()
+                    .try_for_each(|sub| sub?.add_nested_lookups(out)),
+                PositionSubtables::ChainContextual(subtables) => subtables
+                    .iter()
+                    .try_for_each(|sub| sub?.add_nested_lookups(out)),
+                _ => Ok(()),
+            },
+        )?;
+    }
+    Ok(result)
+}
+
+/// Return the glyphs that must be retained to render the given glyphs.
+///
+/// This is the closure of the glyphs under every GSUB substitution, as in
+/// [`Gsub::closure_glyphs`]: substitutions are applied repeatedly, so that the
+/// output of one substitution (such as a ligature) can be the input to
+/// another, until no new glyphs are found.
+///
+/// GPOS does not produce new glyphs, but a (chained) contextual lookup whose
+/// input coverage includes a glyph in the closure may match any glyph in that
+/// coverage, so those glyphs are also retained. Only GPOS lookups reachable
+/// from some feature (directly, or via a contextual lookup) are considered,
+/// and the glyphs they add are not fed back into GSUB.
+///
+/// This is a deliberate over-approximation: the other glyphs in such a
+/// coverage are retained even though they cannot be produced from the input,
+/// so that the contextual lookup can be kept without rewriting its coverage.
+/// A subsetter that prunes the coverage tables of GPOS lookups does not need
+/// these glyphs, and can use the GSUB closure
+/// ([`Gsub::closure_glyphs`]) alone.
+pub fn closure_glyphs(
+    gsub: Option<&Gsub>,
+    gpos: Option<&Gpos>,
+    glyphs: IntSet<GlyphId16>,
+) -> Result<IntSet<GlyphId16>, ReadError> {
+    let mut glyphs = match gsub {
+        Some(gsub) => gsub.closure_glyphs(glyphs)?,
+        None => glyphs,
+    };
+    let Some(gpos) = gpos else {
+        return Ok(glyphs);
+    };
+
+    let all_features = (0..gpos.feature_list()?.feature_count()).collect();
+    let reachable = closure_lookups_cross_table(None, Some(gpos), &IntSet::empty(), &all_features)?;
+    let lookup_list = gpos.lookup_list()?;
+    let mut retained = IntSet::empty();
+    let mut retain_if_matched = |coverage: CoverageTable| {
+        if coverage.iter().any(|gid| glyphs.contains(gid)) {
+            retained.extend(coverage.iter());
+        }
+    };
+    for idx in reachable.gpos.iter() {
+        match lookup_list.lookups().get(idx as usize)?.subtables()? {
+            PositionSubtables::Contextual(subtables) => {
+                for sub in subtables.iter() {
+                    retain_if_matched(sub?.input_coverage()?);
+                }
+            }
+            PositionSubtables::ChainContextual(subtables) => {
+                for sub in subtables.iter() {
+                    retain_if_matched(sub?.input_coverage()?);
+                }
+            }
+            _ => (),
+        }
+    }
+    glyphs.union(&retained);
+    Ok(glyphs)
+}
+
+/// Return the tags of the given features in both GSUB and GPOS.
+///
+/// The features are indices into each table's feature list, as returned by
+/// [`Gsub::collect_features`] and [`Gpos::collect_features`]; indices that
+/// are out of range are ignored. A tag that is present in both tables is
+/// only included once.
+pub fn merged_feature_tags(
+    gsub_features: &IntSet<u16>,
+    gsub: &Gsub,
+    gpos_features: &IntSet<u16>,
+    gpos: &Gpos,
+) -> Result<IntSet<Tag>, ReadError> {
+    let mut tags = IntSet::empty();
+    for (features, feature_list) in [
+        (gsub_features, gsub.feature_list()?),
+        (gpos_features, gpos.feature_list()?),
+    ] {
+        let records = feature_list.feature_records();
+        tags.extend(
+            features
+                .iter()
+                .filter_map(|idx| records.get(idx as usize))
+                .map(|record| record.feature_tag()),
+        );
+    }
+    Ok(tags)
+}
+
+// the lookups referenced directly by the given features
+fn feature_lookups(
+    feature_list: &FeatureList,
+    features: &IntSet<u16>,
+) -> Result<Vec<u16>, ReadError> {
+    let mut lookups = Vec::new();
+    for idx in features.iter() {
+        let Some(record) = feature_list.feature_records().get(idx as usize) else {
+            continue;
+        };
+        let feature = record.feature(feature_list.offset_data())?;
+        lookups.extend(feature.lookup_list_indices().iter().map(|idx| idx.get()));
+    }
+    Ok(lookups)
+}
+
+// the lookups referenced by the lookup records of a GSUB contextual lookup
+fn add_nested_gsub_lookups(
+    lookups: &SubstitutionLookupList,
+    lookup_index: u16,
+    out: &mut Vec<u16>,
+) -> Result<(), ReadError> {
+    match lookups.lookups().get(lookup_index as usize)?.subtables()? {
+        SubstitutionSubtables::Contextual(subtables) => subtables
+            .iter()
+            .try_for_each(|sub| sub?.add_nested_lookups(out)),
+        SubstitutionSubtables::ChainContextual(subtables) => subtables
+            .iter()
+            .try_for_each(|sub| sub?.add_nested_lookups(out)),
+        _ => Ok(()),
+    }
+}
+
+/// Follow nested lookup references, starting from the seed lookups.
+///
+/// The counts are the number of lookups in this table and in the other table.
+fn close_over_lookups(
+    table: LayoutTable,
+    seeds: Vec<u16>,
+    (lookup_count, other_count): (u16, u16),
+    warnings: &mut Vec<LookupReferenceWarning>,
+    mut add_nested: impl FnMut(u16, &mut Vec<u16>) -> Result<(), ReadError>,
+) -> Result<IntSet<u16>, ReadError> {
+    let mut visited = IntSet::empty();
+    let mut todo = seeds.into_iter().map(|idx| (None, idx)).collect::<Vec<_>>();
+    let mut nested = Vec::new();
+    while let Some((from_lookup, lookup_index)) = todo.pop() {
+        if lookup_index >= lookup_count {
+            warnings.push(LookupReferenceWarning {
+                table,
+                from_lookup,
+                lookup_index,
+                valid_in_other_table: lookup_index < other_count,
+            });
+            continue;
+        }
+        if !visited.insert(lookup_index) {
+            continue;
+        }
+        add_nested(lookup_index, &mut nested)?;
+        todo.extend(nested.drain(..).map(|idx| (Some(lookup_index), idx)));
+    }
+    Ok(visited)
+}
+
+impl SequenceContext<'_> {
+    // the coverage of the first input glyph
+    fn input_coverage(&self) -> Result<CoverageTable<'_>, ReadError> {
+        match self {
+            SequenceContext::Format1(table) => table.coverage(),
+            SequenceContext::Format2(table) => table.coverage(),
+            SequenceContext::Format3(table) => table.coverages().get(0),
+        }
+    }
+
+    fn add_nested_lookups(&self, out: &mut Vec<u16>) -> Result<(), ReadError> {
+        match self {
+            SequenceContext::Format1(table) => {
+                for set in table.seq_rule_sets().iter().flatten() {
+                    for rule in set?.seq_rules().iter() {
+                        out.extend(
+                            rule?
+                                .seq_lookup_records()
+                                .iter()
+                                .map(|r| r.lookup_list_index()),
+                        );
+                    }
+                }
+            }
+            SequenceContext::Format2(table) => {
+                for set in table.class_seq_rule_sets().iter().flatten() {
+                    for rule in set?.class_seq_rules().iter() {
+                        out.extend(
+                            rule?
+                                .seq_lookup_records()
+                                .iter()
+                                .map(|r| r.lookup_list_index()),
+                        );
+                    }
+                }
+            }
+            SequenceContext::Format3(table) => {
+                out.extend(
+                    table
+                        .seq_lookup_records()
+                        .iter()
+                        .map(|r| r.lookup_list_index()),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChainedSequenceContext<'_> {
+    // the coverage of the first input glyph
+    fn input_coverage(&self) -> Result<CoverageTable<'_>, ReadError> {
+        match self {
+            ChainedSequenceContext::Format1(table) => table.coverage(),
+            ChainedSequenceContext::Format2(table) => table.coverage(),
+            ChainedSequenceContext::Format3(table) => table.input_coverages().get(0),
+        }
+    }
+
+    fn add_nested_lookups(&self, out: &mut Vec<u16>) -> Result<(), ReadError> {
+        match self {
+            ChainedSequenceContext::Format1(table) => {
+                for set in table.chained_seq_rule_sets().iter().flatten() {
+                    for rule in set?.chained_seq_rules().iter() {
+                        out.extend(
+                            rule?
+                                .seq_lookup_records()
+                                .iter()
+                                .map(|r| r.lookup_list_index()),
+                        );
+                    }
+                }
+            }
+            ChainedSequenceContext::Format2(table) => {
+                for set in table.chained_class_seq_rule_sets().iter().flatten() {
+                    for rule in set?.chained_class_seq_rules().iter() {
+                        out.extend(
+                            rule?
+                                .seq_lookup_records()
+                                .iter()
+                                .map(|r| r.lookup_list_index()),
+                        );
+                    }
+                }
+            }
+            ChainedSequenceContext::Format3(table) => {
+                out.extend(
+                    table
+                        .seq_lookup_records()
+                        .iter()
+                        .map(|r| r.lookup_list_index()),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FontData, FontRead, FontRef, TableProvider};
+
+    fn closure(font_data: &[u8]) -> CrossTableLookups {
+        let font = FontRef::new(font_data).unwrap();
+        let gsub = font.gsub().unwrap();
+        let gpos = font.gpos().unwrap();
+        closure_lookups_cross_table(
+            Some(&gsub),
+            Some(&gpos),
+            &IntSet::from([0u16]),
+            &IntSet::from([0u16]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn collect_all_features() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let features = gsub.collect_features(None, None, None).unwrap();
+        assert_eq!(features, IntSet::from([0u16, 1, 2, 3]));
+    }
+
+    #[test]
+    fn collect_features_filtered() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let cyrl = IntSet::from([Tag::new(b"cyrl")]);
+        let srb = IntSet::from([Tag::new(b"SRB ")]);
+        assert_eq!(
+            gsub.collect_features(Some(&cyrl), None, None).unwrap(),
+            IntSet::from([0u16, 1, 2])
+        );
+        // the required feature is included
+        assert_eq!(
+            gsub.collect_features(Some(&cyrl), Some(&srb), None)
+                .unwrap(),
+            IntSet::from([0u16, 1, 2])
+        );
+        let ss_features = IntSet::from([Tag::new(b"ss01"), Tag::new(b"ss02")]);
+        assert_eq!(
+            gsub.collect_features(None, None, Some(&ss_features))
+                .unwrap(),
+            IntSet::from([2u16, 3])
+        );
+    }
+
+    #[test]
+    fn collect_features_matching() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let stylistic_sets = |tag: Tag| tag.to_be_bytes().starts_with(b"ss");
+        assert_eq!(
+            gsub.collect_features_matching(None, None, stylistic_sets)
+                .unwrap(),
+            IntSet::from([2u16, 3])
+        );
+        let cyrl = IntSet::from([Tag::new(b"cyrl")]);
+        assert_eq!(
+            gsub.collect_features_matching(Some(&cyrl), None, stylistic_sets)
+                .unwrap(),
+            IntSet::from([2u16])
+        );
+        assert_eq!(
+            gsub.collect_features_matching(None, None, |_| true)
+                .unwrap(),
+            gsub.collect_features(None, None, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_langsys_do_not_count_against_limit() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        // one script, with more empty language systems than the limit,
+        // followed by one with a feature
+        let n_empty = MAX_LANGSYS as usize + 1;
+        let n_records = n_empty + 1;
+        let first_langsys = 4 + 6 * n_records;
+        let mut buf = BeBuffer::new()
+            .push(1u16)
+            .push(Tag::new(b"latn"))
+            .push(8u16)
+            .extend([0u16, n_records as u16]);
+        for i in 0..n_records {
+            buf = buf
+                .push(Tag::new(b"LNG "))
+                .push((first_langsys + 6 * i) as u16);
+        }
+        for _ in 0..n_empty {
+            buf = buf.extend([0u16, NO_REQUIRED_FEATURE, 0]);
+        }
+        buf = buf.extend([0u16, NO_REQUIRED_FEATURE, 1, 3]);
+        let script_list = ScriptList::read(buf.data().into()).unwrap();
+        let feature_list_buf = BeBuffer::new().push(0u16);
+        let feature_list = FeatureList::read(feature_list_buf.data().into()).unwrap();
+
+        let mut found = Vec::new();
+        let mut on_feature = |idx| found.push(idx);
+        let c = CollectFeaturesContext::new(None, &feature_list, &mut on_feature);
+        let stats = script_list.collect_features(c, None, None).unwrap();
+        assert_eq!(found, [3]);
+        assert_eq!(stats.langsys_visited, 1);
+    }
+
+    #[test]
+    fn revisited_records_are_counted() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        // two script records pointing at the same script, whose default
+        // language system is also referenced by a language system record
+        let script_list_buf = BeBuffer::new()
+            .push(2u16)
+            .push(Tag::new(b"latn"))
+            .push(14u16)
+            .push(Tag::new(b"cyrl"))
+            .push(14u16)
+            // script
+            .push(10u16)
+            .push(1u16)
+            .push(Tag::new(b"TRK "))
+            .push(10u16)
+            // language system
+            .extend([0u16, NO_REQUIRED_FEATURE, 1, 0]);
+        let script_list = ScriptList::read(script_list_buf.data().into()).unwrap();
+        let feature_list_buf = BeBuffer::new().push(0u16);
+        let feature_list = FeatureList::read(feature_list_buf.data().into()).unwrap();
+
+        let mut found = Vec::new();
+        let mut on_feature = |idx| found.push(idx);
+        let c = CollectFeaturesContext::new(None, &feature_list, &mut on_feature);
+        let stats = script_list.collect_features(c, None, None).unwrap();
+        assert_eq!(found, [0]);
+        assert_eq!(
+            stats,
+            ClosureStats {
+                scripts_visited: 1,
+                langsys_visited: 1,
+                features_collected: 1,
+                filter_rejections: 0,
+                // the second script record, and the 'TRK ' language system
+                revisit_count: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn collect_default_language_features() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let latn = IntSet::from([Tag::new(b"latn")]);
+        let dflt = IntSet::from([Tag::new(b"dflt")]);
+        assert_eq!(
+            gsub.collect_features(Some(&latn), Some(&dflt), None)
+                .unwrap(),
+            IntSet::from([0u16, 2, 3])
+        );
+        let dflt_and_srb = IntSet::from([Tag::new(b"dflt"), Tag::new(b"SRB ")]);
+        assert_eq!(
+            gsub.collect_features(None, Some(&dflt_and_srb), None)
+                .unwrap(),
+            IntSet::from([0u16, 1, 2, 3])
+        );
+        // there is no DFLT script in this font
+        let default_script = IntSet::from([Tag::new(b"DFLT")]);
+        assert!(gsub
+            .collect_features(Some(&default_script), None, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn collect_features_stats() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let (_, stats) = gsub.collect_features_with_stats(None, None, None).unwrap();
+        assert_eq!(
+            stats,
+            ClosureStats {
+                scripts_visited: 2,
+                langsys_visited: 4,
+                features_collected: 4,
+                filter_rejections: 0,
+                revisit_count: 0,
+            }
+        );
+        let ss_features = IntSet::from([Tag::new(b"ss01"), Tag::new(b"ss02")]);
+        let (features, stats) = gsub
+            .collect_features_with_stats(None, None, Some(&ss_features))
+            .unwrap();
+        assert_eq!(features, IntSet::from([2u16, 3]));
+        assert_eq!(
+            stats,
+            ClosureStats {
+                scripts_visited: 2,
+                langsys_visited: 4,
+                features_collected: 2,
+                // liga everywhere, and the required locl in SRB and TRK
+                filter_rejections: 6,
+                revisit_count: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn visited_records_use_offsets() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let script_list = gsub.script_list().unwrap();
+        let feature_list = gsub.feature_list().unwrap();
+        // a copy of the script list, at an unrelated address. The address of a
+        // record in the copy, relative to the original, is arbitrary, and
+        // could alias some other record.
+        let copy = script_list.offset_data().as_bytes().to_vec();
+        let copied_list = ScriptList::read(FontData::new(&copy)).unwrap();
+
+        let mut found = Vec::new();
+        let mut on_feature = |idx| found.push(idx);
+        let mut c = CollectFeaturesContext::new(None, &feature_list, &mut on_feature);
+        let cyrl = &script_list.script_records()[0];
+        script_list
+            .collect_script_features(&mut c, cyrl, None)
+            .unwrap();
+        // the same records, reached through the copy, are skipped
+        for record in copied_list.script_records() {
+            copied_list
+                .collect_script_features(&mut c, record, None)
+                .unwrap();
+        }
+        assert_eq!(c.stats.scripts_visited, 2);
+        assert_eq!(c.stats.langsys_visited, 4);
+        assert_eq!(found, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn collect_features_with_callback() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let srb = IntSet::from([Tag::new(b"SRB ")]);
+        let mut found = Vec::new();
+        gsub.collect_features_with(None, Some(&srb), None, |idx| found.push(idx))
+            .unwrap();
+        // each index is only reported once, in the order it is found
+        assert_eq!(found, [1, 0, 2]);
+        assert_eq!(
+            found.into_iter().collect::<IntSet<_>>(),
+            gsub.collect_features(None, Some(&srb), None).unwrap()
+        );
+    }
+
+    #[test]
+    fn feature_tree() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let tree = gsub.script_list().unwrap().feature_tree().unwrap();
+        let lang_sys = |tag, required, features: &[u16]| LangSysNode {
+            tag: Tag::new(tag),
+            required,
+            features: features.to_vec(),
+        };
+        assert_eq!(
+            tree,
+            [
+                ScriptNode {
+                    tag: Tag::new(b"cyrl"),
+                    languages: vec![
+                        lang_sys(b"dflt", None, &[0]),
+                        lang_sys(b"SRB ", Some(1), &[0, 2]),
+                    ],
+                },
+                ScriptNode {
+                    tag: Tag::new(b"latn"),
+                    languages: vec![
+                        lang_sys(b"dflt", None, &[0, 2, 3]),
+                        lang_sys(b"TRK ", Some(1), &[0, 3]),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_features() {
+        let font = FontRef::new(font_test_data::closure::FEATURE_CLOSURE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let feature_list = gsub.feature_list().unwrap();
+        let unreachable = feature_list
+            .unreachable_features(&gsub.script_list().unwrap())
+            .unwrap();
+        assert_eq!(unreachable, IntSet::from([4u16]));
+        assert_eq!(feature_list.get(4).unwrap().tag, Tag::new(b"zero"));
+    }
+
+    #[test]
+    fn unreachable_features_ignores_closure_limits() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        // two language systems whose features together exceed the limit on
+        // the number of feature indices visited during closure
+        const N_FEATURES: u16 = 2000;
+        let half = N_FEATURES / 2;
+        let mut feature_list_buf = BeBuffer::new().push(N_FEATURES);
+        for _ in 0..N_FEATURES {
+            feature_list_buf = feature_list_buf.push(Tag::new(b"liga")).push(0u16);
+        }
+        let feature_list = FeatureList::read(feature_list_buf.data().into()).unwrap();
+
+        let first_langsys = 24u16;
+        let second_langsys = first_langsys + 6 + 2 * half;
+        let script_list_buf = BeBuffer::new()
+            .push(1u16)
+            .push(Tag::new(b"latn"))
+            .push(8u16)
+            // script
+            .extend([0u16, 2])
+            .push(Tag::new(b"AAA "))
+            .push(first_langsys - 8)
+            .push(Tag::new(b"BBB "))
+            .push(second_langsys - 8)
+            // language systems
+            .extend([0u16, NO_REQUIRED_FEATURE, half])
+            .extend(0..half)
+            .extend([0u16, NO_REQUIRED_FEATURE, half])
+            .extend(half..N_FEATURES);
+        let script_list = ScriptList::read(script_list_buf.data().into()).unwrap();
+
+        // the closure gives up before the second language system...
+        let mut on_feature = |_| ();
+        let c = CollectFeaturesContext::new(None, &feature_list, &mut on_feature);
+        let stats = script_list.collect_features(c, None, None).unwrap();
+        assert_eq!(stats.features_collected, half as u32);
+        // ...but its features are still reachable
+        assert!(feature_list
+            .unreachable_features(&script_list)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn langsys_all_feature_indices() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        // lookup order, required feature, feature count, feature indices
+        let buf = BeBuffer::new().extend([0u16, 5, 2, 1, 3]);
+        let langsys = LangSys::read(buf.data().into()).unwrap();
+        assert_eq!(langsys.all_feature_indices(), IntSet::from([1u16, 3, 5]));
+
+        let buf = BeBuffer::new().extend([0u16, NO_REQUIRED_FEATURE, 2, 1, 3]);
+        let langsys = LangSys::read(buf.data().into()).unwrap();
+        assert_eq!(langsys.all_feature_indices(), IntSet::from([1u16, 3]));
+    }
+
+    #[test]
+    fn features_for_lookup() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        let buf = BeBuffer::new()
+            .push(3u16) // feature count
+            .push(Tag::new(b"kern"))
+            .push(20u16)
+            .push(Tag::new(b"liga"))
+            .push(28u16)
+            .push(Tag::new(b"smcp"))
+            .push(34u16)
+            // kern: lookups 0 and 1
+            .extend([0u16, 2, 0, 1])
+            // liga: lookup 1
+            .extend([0u16, 1, 1])
+            // smcp: lookup 2
+            .extend([0u16, 1, 2]);
+        let feature_list = FeatureList::read(buf.data().into()).unwrap();
+
+        assert_eq!(
+            feature_list.features_for_lookup(1).unwrap(),
+            IntSet::from([0u16, 1])
+        );
+        assert_eq!(
+            feature_list.features_for_lookup(2).unwrap(),
+            IntSet::from([2u16])
+        );
+        assert!(feature_list.features_for_lookup(3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn merged_tags() {
+        let font = FontRef::new(font_test_data::closure::CROSS_TABLE).unwrap();
+        let gsub = font.gsub().unwrap();
+        let gpos = font.gpos().unwrap();
+        let gsub_features = gsub.collect_features(None, None, None).unwrap();
+        let gpos_features = gpos.collect_features(None, None, None).unwrap();
+        // 'test' is only in GSUB, 'kern' and 'mark' are only in GPOS
+        assert_eq!(
+            merged_feature_tags(&gsub_features, &gsub, &gpos_features, &gpos).unwrap(),
+            IntSet::from([Tag::new(b"test"), Tag::new(b"kern"), Tag::new(b"mark")])
+        );
+        // out of range indices are ignored
+        assert_eq!(
+            merged_feature_tags(&IntSet::from([7u16]), &gsub, &IntSet::from([0u16]), &gpos)
+                .unwrap(),
+            IntSet::from([gpos.feature_list().unwrap().get(0).unwrap().tag])
+        );
+    }
+
+    #[test]
+    fn cross_table_closure() {
+        let result = closure(font_test_data::closure::CROSS_TABLE);
+        assert_eq!(result.gsub, IntSet::from([0u16, 1]));
+        assert_eq!(result.gpos, IntSet::from([0u16, 1]));
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn closure_glyphs_gsub_and_gpos() {
+        use font_test_data::bebuffer::BeBuffer;
+
+        // glyphs: 1 f, 2 i, 3 f_i, 4 f_i.alt, 5 and 6 other glyphs
+        #[rustfmt::skip]
+        let gsub = [
+            1_u16, 0, 10, 12, 28, // header
+            0, // script list
+            1, 0x7465, 0x7374, 8, // feature list: 'test'
+            0, 2, 0, 1, // feature: lookups 0 and 1
+            2, 6, 28, // lookup list
+            // lookup 0: sub f_i by f_i.alt;
+            1, 0, 1, 8,
+            2, 8, 1, 4,
+            1, 1, 3,
+            // lookup 1: sub f i by f_i;
+            4, 0, 1, 8,
+            1, 8, 1, 14,
+            1, 1, 1,
+            1, 4,
+            3, 2, 2,
+        ];
+        #[rustfmt::skip]
+        let gpos = [
+            1_u16, 0, 10, 12, 28, // header
+            0, // script list
+            1, 0x6b65, 0x726e, 8, // feature list: 'kern'
+            0, 2, 0, 1, // feature: lookups 0 and 1
+            2, 6, 34, // lookup list
+            // lookup 0: chained context with input [f_i.alt 5]
+            8, 0, 1, 8,
+            3, 0, 1, 12, 0, 0,
+            1, 2, 4, 5,
+            // lookup 1: chained context with input [6]
+            8, 0, 1, 8,
+            3, 0, 1, 12, 0, 0,
+            1, 1, 6,
+        ];
+        let gsub_buf = BeBuffer::new().extend(gsub);
+        let gpos_buf = BeBuffer::new().extend(gpos);
+        let gsub = Gsub::read(gsub_buf.data().into()).unwrap();
+        let gpos = Gpos::read(gpos_buf.data().into()).unwrap();
+        let closure = |gsub, gpos, input: &[u16]| {
+            let glyphs = input.iter().copied().map(GlyphId16::new).collect();
+            closure_glyphs(gsub, gpos, glyphs)
+                .unwrap()
+                .iter()
+                .map(|gid| gid.to_u16())
+                .collect::<Vec<_>>()
+        };
+
+        // the ligature is produced after the lookup that substitutes it, so
+        // this requires a second pass over the lookups
+        assert_eq!(closure(Some(&gsub), None, &[1, 2]), [1, 2, 3, 4]);
+        // f_i.alt matches the first GPOS contextual lookup, which retains the
+        // rest of its input coverage (glyph 5) as well
+        assert_eq!(closure(Some(&gsub), Some(&gpos), &[1, 2]), [1, 2, 3, 4, 5]);
+        assert_eq!(closure(Some(&gsub), Some(&gpos), &[2]), [2]);
+        assert_eq!(closure(None, Some(&gpos), &[6]), [6]);
+    }
+
+    #[test]
+    fn lookup_reachability_matches_closure() {
+        for font_data in [
+            font_test_data::closure::CROSS_TABLE,
+            font_test_data::closure::CROSS_TABLE_MALFORMED,
+            font_test_data::closure::FEATURE_CLOSURE,
+        ] {
+            let font = FontRef::new(font_data).unwrap();
+            let gsub = font.gsub().unwrap();
+            let feature_count = gsub.feature_list().unwrap().feature_count();
+            let lookup_count = gsub.lookup_list().unwrap().lookup_count();
+            let mut feature_sets = (0..feature_count)
+                .map(|idx| IntSet::from([idx]))
+                .collect::<Vec<_>>();
+            feature_sets.push(IntSet::empty());
+            feature_sets.push((0..feature_count).collect());
+
+            for features in &feature_sets {
+                let closure =
+                    closure_lookups_cross_table(Some(&gsub), None, features, &IntSet::empty())
+                        .unwrap();
+                // include an out-of-range lookup index
+                for lookup in 0..=lookup_count {
+                    assert_eq!(
+                        gsub.is_lookup_reachable(lookup, features).unwrap(),
+                        closure.gsub.contains(lookup),
+                        "lookup {lookup} from features {features:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cross_table_closure_malformed() {
+        let result = closure(font_test_data::closure::CROSS_TABLE_MALFORMED);
+        assert_eq!(result.gsub, IntSet::from([0u16]));
+        assert_eq!(result.gpos, IntSet::from([0u16, 1]));
+        assert_eq!(
+            result.warnings,
+            [
+                LookupReferenceWarning {
+                    table: LayoutTable::Gsub,
+                    from_lookup: Some(0),
+                    lookup_index: 2,
+                    valid_in_other_table: true,
+                },
+                LookupReferenceWarning {
+                    table: LayoutTable::Gpos,
+                    from_lookup: Some(0),
+                    lookup_index: 7,
+                    valid_in_other_table: false,
+                },
+            ]
+        );
+    }
+}
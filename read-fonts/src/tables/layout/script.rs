@@ -1,6 +1,8 @@
 //! Additional support for working with OpenType scripts and language systems.
 
 use super::{FeatureList, LangSys, ReadError, Script, ScriptList, Tag, TaggedElement};
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
 use std::ops::Deref;
 
 /// A script chosen from a set of candidate tags.
@@ -102,6 +104,29 @@ impl<'a> Script<'a> {
                 ))
             })
     }
+
+    /// Returns a map from language system tag to the index of that language
+    /// system's required feature, for each language system that has one.
+    ///
+    /// The default language system, if present, uses the tag `dflt`.
+    #[cfg(feature = "std")]
+    pub fn required_features(&self) -> Result<BTreeMap<Tag, u16>, ReadError> {
+        const NO_REQUIRED_FEATURE: u16 = 0xFFFF;
+        let mut result = BTreeMap::new();
+        if let Some(lang_sys) = self.default_lang_sys().transpose()? {
+            let index = lang_sys.required_feature_index();
+            if index != NO_REQUIRED_FEATURE {
+                result.insert(Tag::new(b"dflt"), index);
+            }
+        }
+        for rec in self.lang_sys_records() {
+            let index = rec.lang_sys(self.offset_data())?.required_feature_index();
+            if index != NO_REQUIRED_FEATURE {
+                result.insert(rec.lang_sys_tag(), index);
+            }
+        }
+        Ok(result)
+    }
 }
 
 impl LangSys<'_> {
@@ -234,7 +259,7 @@ fn new_tag_from_unicode(unicode_script: Tag) -> Option<Tag> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{FontRef, TableProvider};
+    use crate::{FontRead, FontRef, TableProvider};
 
     #[test]
     fn script_index_for_tag() {
@@ -355,6 +380,22 @@ mod tests {
         assert_eq!(script.lang_sys(lang_sys_index).unwrap().tag, TRK);
     }
 
+    #[test]
+    fn required_features() {
+        // default has no required feature, URD has feature 3
+        let script = Script::read(font_test_data::layout::SCRIPTS_AND_LANGUAGES.into()).unwrap();
+        let required = script.required_features().unwrap();
+        assert_eq!(
+            required.into_iter().collect::<Vec<_>>(),
+            [(Tag::new(b"URD "), 3)]
+        );
+
+        let font = FontRef::new(font_test_data::CANTARELL_VF_TRIMMED).unwrap();
+        let script_list = font.gsub().unwrap().script_list().unwrap();
+        let script = script_list.get(1).unwrap();
+        assert!(script.required_features().unwrap().is_empty());
+    }
+
     #[test]
     fn feature_index_for_tag() {
         let font = FontRef::new(font_test_data::MATERIAL_SYMBOLS_SUBSET).unwrap();
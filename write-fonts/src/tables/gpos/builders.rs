@@ -1,13 +1,16 @@
 //! GPOS subtable builders
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use read_fonts::collections::IntSet;
-use types::GlyphId16;
+use types::{FixedSize, GlyphId16, Offset16};
 
 use crate::tables::{
     layout::{
-        builders::{Builder, ClassDefBuilder, DeviceOrDeltas, Metric},
+        builders::{
+            max_class_def_size, max_coverage_size, Builder, ClassDefBuilder, DeviceOrDeltas,
+            EstimateSize, LayoutBuildError, Metric,
+        },
         CoverageTable,
     },
     variations::ivs_builder::VariationStoreBuilder,
@@ -189,6 +192,38 @@ impl ValueRecordBuilder {
             .all(|v| v.as_ref().map(|v| v.is_zero()).unwrap_or(true))
     }
 
+    /// `true` if any field of this record has deltas.
+    pub fn has_deltas(&self) -> bool {
+        [
+            &self.x_advance,
+            &self.y_advance,
+            &self.x_placement,
+            &self.y_placement,
+        ]
+        .into_iter()
+        .flatten()
+        .any(Metric::has_deltas)
+    }
+
+    // the number of bytes this record takes up in a subtable
+    fn encoded_size(&self) -> usize {
+        self.format().bits().count_ones() as usize * u16::RAW_BYTE_LEN
+    }
+
+    // an upper bound on the size of the device tables of this record
+    fn max_device_size(&self) -> usize {
+        [
+            &self.x_advance,
+            &self.y_advance,
+            &self.x_placement,
+            &self.y_placement,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|metric| metric.device_or_deltas.max_table_size())
+        .sum()
+    }
+
     /// Build the final [`ValueRecord`], compiling deltas if needed.
     pub fn build(self, var_store: &mut VariationStoreBuilder) -> ValueRecord {
         let mut result = ValueRecord::new();
@@ -252,6 +287,19 @@ impl AnchorBuilder {
         self
     }
 
+    /// `true` if either coordinate has deltas.
+    pub fn has_deltas(&self) -> bool {
+        self.x.has_deltas() || self.y.has_deltas()
+    }
+
+    // an upper bound on the size of this anchor and its device tables
+    fn max_size(&self) -> usize {
+        // format, x, y, and two device offsets (or a contour point)
+        u16::RAW_BYTE_LEN * 5
+            + self.x.device_or_deltas.max_table_size()
+            + self.y.device_or_deltas.max_table_size()
+    }
+
     /// Build the final [`AnchorTable`], adding deltas to the varstore if needed.
     pub fn build(self, var_store: &mut VariationStoreBuilder) -> AnchorTable {
         let x = self.x.default;
@@ -293,6 +341,10 @@ impl SinglePosBuilder {
 impl Builder for SinglePosBuilder {
     type Output = Vec<SinglePos>;
 
+    fn uses_variations(&self) -> bool {
+        self.items.values().any(ValueRecordBuilder::has_deltas)
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
         fn build_subtable(items: BTreeMap<GlyphId16, &ValueRecord>) -> SinglePos {
             let first = *items.values().next().unwrap();
@@ -356,6 +408,31 @@ impl Builder for SinglePosBuilder {
     }
 }
 
+impl EstimateSize for SinglePosBuilder {
+    fn estimated_size(&self) -> usize {
+        // `build` makes a format 1 subtable for some distinct records, and a
+        // format 2 subtable for each value format of the rest
+        let record_count = self.items.values().collect::<BTreeSet<_>>().len();
+        let format_count = self
+            .items
+            .values()
+            .map(ValueRecordBuilder::format)
+            .collect::<BTreeSet<_>>()
+            .len();
+        // the lookup's offset, format, coverage offset, value format, value
+        // count, and the coverage header
+        let subtable_size = Offset16::RAW_BYTE_LEN + u16::RAW_BYTE_LEN * 4 + max_coverage_size(0);
+        (record_count + format_count) * subtable_size
+            + self
+                .items
+                .values()
+                .map(|record| {
+                    GlyphId16::RAW_BYTE_LEN + record.encoded_size() + record.max_device_size()
+                })
+                .sum::<usize>()
+    }
+}
+
 fn cmp_coverage_key(coverage: &CoverageTable) -> impl Ord {
     (std::cmp::Reverse(coverage.len()), coverage.iter().next())
 }
@@ -496,6 +573,13 @@ impl PairPosBuilder {
     }
 
     /// Insert a new class-based kerning rule.
+    ///
+    /// Classes are arbitrary glyph sets; the [`ClassDef`] tables for both
+    /// members of the pair, and the class ids used to index the value matrix,
+    /// are assigned when the subtable is built. A new subtable is started
+    /// whenever a class would overlap one already in the current subtable.
+    ///
+    /// [`ClassDef`]: crate::tables::layout::ClassDef
     pub fn insert_classes(
         &mut self,
         class1: GlyphSet,
@@ -507,19 +591,105 @@ impl PairPosBuilder {
     }
 }
 
+impl
+    FromIterator<(
+        (GlyphSet, GlyphSet),
+        (ValueRecordBuilder, ValueRecordBuilder),
+    )> for PairPosBuilder
+{
+    /// Create a builder from class-based kerning rules.
+    ///
+    /// Each item is a pair of classes and the pair of values applied to them;
+    /// see [`PairPosBuilder::insert_classes`].
+    fn from_iter<
+        T: IntoIterator<
+            Item = (
+                (GlyphSet, GlyphSet),
+                (ValueRecordBuilder, ValueRecordBuilder),
+            ),
+        >,
+    >(
+        iter: T,
+    ) -> Self {
+        let mut builder = Self::default();
+        for ((class1, class2), (record1, record2)) in iter {
+            builder.insert_classes(class1, record1, class2, record2);
+        }
+        builder
+    }
+}
+
 impl Builder for PairPosBuilder {
     type Output = Vec<PairPos>;
 
+    fn uses_variations(&self) -> bool {
+        self.pairs.uses_variations() || self.classes.uses_variations()
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
+        self.try_build(var_store).unwrap()
+    }
+
+    fn try_build(
+        self,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Result<Self::Output, LayoutBuildError> {
         let mut out = self.pairs.build(var_store);
-        out.extend(self.classes.build(var_store));
-        out
+        out.extend(self.classes.try_build(var_store)?);
+        Ok(out)
+    }
+}
+
+impl EstimateSize for PairPosBuilder {
+    fn estimated_size(&self) -> usize {
+        self.pairs.estimated_size()
+            + self
+                .classes
+                .0
+                .iter()
+                .map(EstimateSize::estimated_size)
+                .sum::<usize>()
+    }
+}
+
+impl EstimateSize for GlyphPairPosBuilder {
+    fn estimated_size(&self) -> usize {
+        // `build` makes a subtable for each pair of value formats
+        let mut subtables = HashSet::new();
+        let mut pair_sets = HashSet::new();
+        let mut records_size = 0;
+        for (glyph1, pairs) in &self.0 {
+            for (v1, v2) in pairs.values() {
+                let formats = (v1.format(), v2.format());
+                subtables.insert(formats);
+                pair_sets.insert((formats, *glyph1));
+                // secondGlyph, and both value records
+                records_size += GlyphId16::RAW_BYTE_LEN
+                    + v1.encoded_size()
+                    + v2.encoded_size()
+                    + v1.max_device_size()
+                    + v2.max_device_size();
+            }
+        }
+        // the lookup's offset, format, coverage offset, both value formats,
+        // pair set count, and the coverage header
+        let subtable_size = Offset16::RAW_BYTE_LEN + u16::RAW_BYTE_LEN * 5 + max_coverage_size(0);
+        // the pair set's offset, pair value count, and its glyph in the coverage
+        let pair_set_size = Offset16::RAW_BYTE_LEN + u16::RAW_BYTE_LEN + GlyphId16::RAW_BYTE_LEN;
+        subtables.len() * subtable_size + pair_sets.len() * pair_set_size + records_size
     }
 }
 
 impl Builder for GlyphPairPosBuilder {
     type Output = Vec<PairPos>;
 
+    fn uses_variations(&self) -> bool {
+        self.0
+            .values()
+            .flat_map(BTreeMap::values)
+            .any(|(v1, v2)| v1.has_deltas() || v2.has_deltas())
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
         let mut split_by_format = BTreeMap::<_, BTreeMap<_, Vec<_>>>::default();
         for (g1, map) in self.0 {
@@ -551,15 +721,74 @@ impl Builder for GlyphPairPosBuilder {
 impl Builder for ClassPairPosBuilder {
     type Output = Vec<PairPos>;
 
+    fn uses_variations(&self) -> bool {
+        self.0.iter().any(Builder::uses_variations)
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
-        self.0.into_iter().map(|sub| sub.build(var_store)).collect()
+        self.try_build(var_store).unwrap()
+    }
+
+    fn try_build(
+        self,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Result<Self::Output, LayoutBuildError> {
+        self.0
+            .into_iter()
+            .map(|sub| sub.try_build(var_store))
+            .collect()
+    }
+}
+
+impl EstimateSize for ClassPairPosSubtable {
+    fn estimated_size(&self) -> usize {
+        let (format1, format2) = self.compute_value_formats();
+        let record_size = (format1.bits().count_ones() + format2.bits().count_ones()) as usize
+            * u16::RAW_BYTE_LEN;
+        let classes2 = self
+            .items
+            .values()
+            .flat_map(BTreeMap::keys)
+            .collect::<BTreeSet<_>>();
+        let glyph_count1 = self.items.keys().map(|cls| cls.len() as usize).sum();
+        let glyph_count2 = classes2.iter().map(|cls| cls.len() as usize).sum();
+        let devices_size = self
+            .items
+            .values()
+            .flat_map(BTreeMap::values)
+            .map(|(v1, v2)| v1.max_device_size() + v2.max_device_size())
+            .sum::<usize>();
+        // the lookup's offset, format, coverage offset, both value formats,
+        // both classdef offsets, and both class counts
+        Offset16::RAW_BYTE_LEN
+            + u16::RAW_BYTE_LEN * 8
+            + max_coverage_size(glyph_count1)
+            + max_class_def_size(glyph_count1)
+            + max_class_def_size(glyph_count2)
+            // class 0 of the second classdef is also a column
+            + self.items.len() * (classes2.len() + 1) * record_size
+            + devices_size
     }
 }
 
 impl Builder for ClassPairPosSubtable {
     type Output = PairPos;
 
+    fn uses_variations(&self) -> bool {
+        self.items
+            .values()
+            .flat_map(BTreeMap::values)
+            .any(|(v1, v2)| v1.has_deltas() || v2.has_deltas())
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
+        self.try_build(var_store).unwrap()
+    }
+
+    fn try_build(
+        self,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Result<Self::Output, LayoutBuildError> {
         assert!(!self.items.is_empty(), "filter before here");
         let (format1, format2) = self.compute_value_formats();
         // we have a set of classes/values with a single valueformat
@@ -570,8 +799,8 @@ impl Builder for ClassPairPosSubtable {
             ValueRecord::new().with_explicit_value_format(format2),
         );
 
-        let (class1def, class1map) = self.classdef_1.build_with_mapping();
-        let (class2def, class2map) = self.classdef_2.build_with_mapping();
+        let (class1def, class1map) = self.classdef_1.try_build_with_mapping()?;
+        let (class2def, class2map) = self.classdef_2.try_build_with_mapping()?;
 
         let coverage = self.items.keys().flat_map(GlyphSet::iter).collect();
 
@@ -588,7 +817,7 @@ impl Builder for ClassPairPosSubtable {
             }
             out[*idx as usize] = Class1Record::new(records);
         }
-        PairPos::format_2(coverage, class1def, class2def, out)
+        Ok(PairPos::format_2(coverage, class1def, class2def, out))
     }
 }
 
@@ -615,6 +844,13 @@ impl CursivePosBuilder {
 impl Builder for CursivePosBuilder {
     type Output = Vec<CursivePosFormat1>;
 
+    fn uses_variations(&self) -> bool {
+        self.items
+            .values()
+            .flat_map(|(entry, exit)| entry.iter().chain(exit))
+            .any(AnchorBuilder::has_deltas)
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
         let coverage = self.items.keys().copied().collect();
         let records = self
@@ -631,6 +867,27 @@ impl Builder for CursivePosBuilder {
     }
 }
 
+impl EstimateSize for CursivePosBuilder {
+    fn estimated_size(&self) -> usize {
+        if self.items.is_empty() {
+            return 0;
+        }
+        let anchors_size = self
+            .items
+            .values()
+            .flat_map(|(entry, exit)| entry.iter().chain(exit))
+            .map(AnchorBuilder::max_size)
+            .sum::<usize>();
+        // the lookup's offset, format, coverage offset, record count, and
+        // both anchor offsets for each glyph
+        Offset16::RAW_BYTE_LEN
+            + u16::RAW_BYTE_LEN * 3
+            + self.items.len() * Offset16::RAW_BYTE_LEN * 2
+            + max_coverage_size(self.items.len())
+            + anchors_size
+    }
+}
+
 // shared between several tables
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -688,9 +945,48 @@ impl MarkList {
     }
 }
 
+impl MarkList {
+    // an upper bound on the size of the mark coverage and mark array
+    fn estimated_size(&self) -> usize {
+        // markCount, and the class and anchor offset of each mark
+        max_coverage_size(self.glyphs.len())
+            + u16::RAW_BYTE_LEN
+            + self.glyphs.len() * (u16::RAW_BYTE_LEN + Offset16::RAW_BYTE_LEN)
+            + self
+                .glyphs
+                .values()
+                .map(|(_, anchor)| anchor.max_size())
+                .sum::<usize>()
+    }
+}
+
+// the lookup's offset, and the format, offsets and class count shared by the
+// mark attachment subtables
+const MARK_ATTACHMENT_HEADER_SIZE: usize = Offset16::RAW_BYTE_LEN + u16::RAW_BYTE_LEN * 6;
+
+// an upper bound on the size of the base (or mark2) coverage and array, with
+// an anchor offset for each class
+fn max_base_array_size(
+    class_count: usize,
+    bases: &BTreeMap<GlyphId16, Vec<(u16, AnchorBuilder)>>,
+) -> usize {
+    max_coverage_size(bases.len())
+        + u16::RAW_BYTE_LEN
+        + bases.len() * class_count * Offset16::RAW_BYTE_LEN
+        + bases
+            .values()
+            .flatten()
+            .map(|(_, anchor)| anchor.max_size())
+            .sum::<usize>()
+}
+
 impl Builder for MarkList {
     type Output = (CoverageTable, MarkArray);
 
+    fn uses_variations(&self) -> bool {
+        self.glyphs.values().any(|(_, anchor)| anchor.has_deltas())
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
         let coverage = self.glyphs().collect();
         let array = MarkArray::new(
@@ -767,6 +1063,15 @@ impl MarkToBaseBuilder {
 impl Builder for MarkToBaseBuilder {
     type Output = Vec<MarkBasePosFormat1>;
 
+    fn uses_variations(&self) -> bool {
+        self.marks.uses_variations()
+            || self
+                .bases
+                .values()
+                .flatten()
+                .any(|(_, anchor)| anchor.has_deltas())
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
         let MarkToBaseBuilder { marks, bases } = self;
         let n_classes = marks.classes.len();
@@ -793,6 +1098,14 @@ impl Builder for MarkToBaseBuilder {
     }
 }
 
+impl EstimateSize for MarkToBaseBuilder {
+    fn estimated_size(&self) -> usize {
+        MARK_ATTACHMENT_HEADER_SIZE
+            + self.marks.estimated_size()
+            + max_base_array_size(self.marks.classes.len(), &self.bases)
+    }
+}
+
 /// A builder for GPOS Lookup Type 5, Mark-to-Ligature
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -881,6 +1194,16 @@ impl MarkToLigBuilder {
 impl Builder for MarkToLigBuilder {
     type Output = Vec<MarkLigPosFormat1>;
 
+    fn uses_variations(&self) -> bool {
+        self.marks.uses_variations()
+            || self
+                .ligatures
+                .values()
+                .flatten()
+                .flat_map(BTreeMap::values)
+                .any(AnchorBuilder::has_deltas)
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
         let MarkToLigBuilder { marks, ligatures } = self;
         let n_classes = marks.classes.len();
@@ -918,6 +1241,33 @@ impl Builder for MarkToLigBuilder {
     }
 }
 
+impl EstimateSize for MarkToLigBuilder {
+    fn estimated_size(&self) -> usize {
+        let class_count = self.marks.classes.len();
+        // ligatureCount, and the offset to each ligature attach table
+        let ligature_array_size = u16::RAW_BYTE_LEN
+            + self
+                .ligatures
+                .values()
+                .map(|components| {
+                    // componentCount, and an anchor offset for each class
+                    Offset16::RAW_BYTE_LEN
+                        + u16::RAW_BYTE_LEN
+                        + components.len() * class_count * Offset16::RAW_BYTE_LEN
+                        + components
+                            .iter()
+                            .flat_map(BTreeMap::values)
+                            .map(AnchorBuilder::max_size)
+                            .sum::<usize>()
+                })
+                .sum::<usize>();
+        MARK_ATTACHMENT_HEADER_SIZE
+            + self.marks.estimated_size()
+            + max_coverage_size(self.ligatures.len())
+            + ligature_array_size
+    }
+}
+
 /// A builder for GPOS Type 6 (Mark-to-Mark)
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -960,6 +1310,15 @@ impl MarkToMarkBuilder {
 impl Builder for MarkToMarkBuilder {
     type Output = Vec<MarkMarkPosFormat1>;
 
+    fn uses_variations(&self) -> bool {
+        self.attaching_marks.uses_variations()
+            || self
+                .base_marks
+                .values()
+                .flatten()
+                .any(|(_, anchor)| anchor.has_deltas())
+    }
+
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
         let MarkToMarkBuilder {
             attaching_marks,
@@ -988,3 +1347,202 @@ impl Builder for MarkToMarkBuilder {
         )]
     }
 }
+
+impl EstimateSize for MarkToMarkBuilder {
+    fn estimated_size(&self) -> usize {
+        MARK_ATTACHMENT_HEADER_SIZE
+            + self.attaching_marks.estimated_size()
+            + max_base_array_size(self.attaching_marks.classes.len(), &self.base_marks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use types::F2Dot14;
+
+    use crate::tables::{
+        layout::{builders::LookupBuilder, LookupFlag},
+        variations::{ivs_builder::RemapVariationIndices, RegionAxisCoordinates, VariationRegion},
+    };
+
+    use super::*;
+
+    fn glyph_set<const N: usize>(ids: [u16; N]) -> GlyphSet {
+        ids.into_iter().map(GlyphId16::new).collect()
+    }
+
+    fn kern(val: i16) -> (ValueRecordBuilder, ValueRecordBuilder) {
+        (
+            ValueRecordBuilder::new().with_x_advance(val),
+            ValueRecordBuilder::new(),
+        )
+    }
+
+    #[test]
+    fn pair_pos_from_classes() {
+        let first_a = glyph_set([1, 2, 3]);
+        let first_b = glyph_set([4]);
+        let second_c = glyph_set([10]);
+        let second_d = glyph_set([11, 12]);
+
+        let builder: PairPosBuilder = [
+            ((first_a.clone(), second_c.clone()), kern(-10)),
+            ((first_a.clone(), second_d.clone()), kern(-20)),
+            ((first_b.clone(), second_c.clone()), kern(-30)),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut var_store = VariationStoreBuilder::new(0);
+        let mut subtables = builder.build(&mut var_store);
+        assert_eq!(subtables.len(), 1);
+        let PairPos::Format2(table) = subtables.pop().unwrap() else {
+            panic!("expected class-based subtable");
+        };
+
+        let class_def1 = &table.class_def1;
+        let class_def2 = &table.class_def2;
+        // the first classdef may use class 0, the second may not
+        let mut first_ids = [1, 4].map(|gid| class_def1.get(GlyphId16::new(gid)));
+        first_ids.sort();
+        assert_eq!(first_ids, [0, 1]);
+        assert!([10, 11]
+            .iter()
+            .all(|gid| class_def2.get(GlyphId16::new(*gid)) != 0));
+        assert_eq!(table.class1_records.len(), 2);
+        assert!(table
+            .class1_records
+            .iter()
+            .all(|rec| rec.class2_records.len() == 3));
+
+        let x_advance = |g1: u16, g2: u16| {
+            let c1 = class_def1.get(GlyphId16::new(g1));
+            let c2 = class_def2.get(GlyphId16::new(g2));
+            table.class1_records[c1 as usize].class2_records[c2 as usize]
+                .value_record1
+                .x_advance
+        };
+        assert_eq!(x_advance(2, 10), Some(-10));
+        assert_eq!(x_advance(3, 12), Some(-20));
+        assert_eq!(x_advance(4, 10), Some(-30));
+        // a pair with no rule gets an empty record in the matrix
+        assert_eq!(x_advance(4, 11), None);
+        assert!(table.coverage.iter().eq([1, 2, 3, 4].map(GlyphId16::new)));
+    }
+
+    // the estimated size of a lookup should be at least its compiled size
+    fn assert_estimate_is_upper_bound<T, U>(subtable: T)
+    where
+        T: Builder<Output = Vec<U>> + EstimateSize + Default,
+        U: Default
+            + crate::tables::layout::LookupSubtable
+            + crate::FontWrite
+            + crate::validate::Validate
+            + RemapVariationIndices,
+    {
+        let lookup = LookupBuilder::new_with_lookups(LookupFlag::empty(), None, vec![subtable]);
+        let estimate = lookup.estimated_size();
+        let mut var_store = VariationStoreBuilder::new(1);
+        let mut lookup = lookup.build(&mut var_store);
+        let (_, key_map) = var_store.build();
+        lookup.remap_variation_indices(&key_map);
+        let actual = crate::dump_table(&lookup).unwrap().len();
+        assert!(estimate >= actual, "{estimate} < {actual}");
+    }
+
+    fn make_region(peak: f32) -> VariationRegion {
+        VariationRegion::new(vec![RegionAxisCoordinates {
+            start_coord: F2Dot14::from_f32(peak.min(0.0)),
+            peak_coord: F2Dot14::from_f32(peak),
+            end_coord: F2Dot14::from_f32(peak.max(0.0)),
+        }])
+    }
+
+    #[test]
+    fn estimated_sizes() {
+        let varied = |val: i16| {
+            ValueRecordBuilder::new()
+                .with_x_advance(val)
+                .with_x_advance_device(vec![(make_region(1.0), val / 2)])
+        };
+        let anchor = |x: i16| AnchorBuilder::new(x, 500);
+        let varied_anchor = |x: i16| anchor(x).with_y_device(vec![(make_region(1.0), 20)]);
+
+        let mut single = SinglePosBuilder::default();
+        for gid in 1..20 {
+            let record = match gid % 3 {
+                0 => ValueRecordBuilder::new().with_x_advance(-5),
+                1 => ValueRecordBuilder::new().with_y_placement(gid as i16),
+                _ => varied(gid as i16),
+            };
+            single.insert(GlyphId16::new(gid), record);
+        }
+        assert_estimate_is_upper_bound(single);
+
+        let mut pairs = PairPosBuilder::default();
+        for left in 1..10u16 {
+            for right in 20..25u16 {
+                let record = if (left + right) % 4 == 0 {
+                    varied(-10)
+                } else {
+                    ValueRecordBuilder::new().with_x_advance(-(left as i16))
+                };
+                pairs.insert_pair(
+                    GlyphId16::new(left),
+                    record,
+                    GlyphId16::new(right),
+                    ValueRecordBuilder::new(),
+                );
+            }
+        }
+        pairs.insert_classes(
+            glyph_set([30, 31, 35]),
+            varied(4),
+            glyph_set([40, 42]),
+            ValueRecordBuilder::new(),
+        );
+        let (v1, v2) = kern(-7);
+        pairs.insert_classes(glyph_set([32]), v1, glyph_set([44, 50, 60]), v2);
+        assert_estimate_is_upper_bound(pairs);
+
+        let mut cursive = CursivePosBuilder::default();
+        cursive.insert(GlyphId16::new(3), Some(anchor(10)), None);
+        cursive.insert(GlyphId16::new(4), Some(varied_anchor(0)), Some(anchor(100)));
+        assert_estimate_is_upper_bound(cursive);
+
+        let mut mark_base = MarkToBaseBuilder::default();
+        mark_base
+            .insert_mark(GlyphId16::new(50), "top", anchor(0))
+            .unwrap();
+        mark_base
+            .insert_mark(GlyphId16::new(51), "bottom", varied_anchor(0))
+            .unwrap();
+        mark_base.insert_base(GlyphId16::new(5), "top", anchor(200));
+        mark_base.insert_base(GlyphId16::new(5), "bottom", varied_anchor(200));
+        mark_base.insert_base(GlyphId16::new(9), "top", anchor(300));
+        assert_estimate_is_upper_bound(mark_base);
+
+        let mut mark_lig = MarkToLigBuilder::default();
+        mark_lig
+            .insert_mark(GlyphId16::new(50), "top", anchor(0))
+            .unwrap();
+        mark_lig
+            .insert_mark(GlyphId16::new(52), "bottom", anchor(0))
+            .unwrap();
+        mark_lig.insert_ligature(
+            GlyphId16::new(70),
+            "top",
+            vec![Some(anchor(100)), None, Some(varied_anchor(600))],
+        );
+        mark_lig.insert_ligature(GlyphId16::new(71), "bottom", vec![Some(anchor(20))]);
+        assert_estimate_is_upper_bound(mark_lig);
+
+        let mut mark_mark = MarkToMarkBuilder::default();
+        mark_mark
+            .insert_mark1(GlyphId16::new(50), "top", anchor(0))
+            .unwrap();
+        mark_mark.insert_mark2(GlyphId16::new(51), "top", varied_anchor(0));
+        mark_mark.insert_mark2(GlyphId16::new(53), "top", anchor(5));
+        assert_estimate_is_upper_bound(mark_mark);
+    }
+}
@@ -6,7 +6,10 @@ use types::{FixedSize, GlyphId16, Offset16};
 
 use crate::{
     tables::{
-        layout::{builders::Builder, CoverageTable},
+        layout::{
+            builders::{max_coverage_size, Builder, EstimateSize},
+            CoverageTable,
+        },
         variations::ivs_builder::VariationStoreBuilder,
     },
     FontWrite,
@@ -141,6 +144,12 @@ impl SingleSubBuilder {
     }
 }
 
+impl From<BTreeMap<GlyphId16, GlyphId16>> for SingleSubBuilder {
+    fn from(items: BTreeMap<GlyphId16, GlyphId16>) -> Self {
+        SingleSubBuilder { items }
+    }
+}
+
 impl Builder for SingleSubBuilder {
     type Output = Vec<super::SingleSubst>;
 
@@ -167,12 +176,32 @@ impl Builder for SingleSubBuilder {
     }
 }
 
+impl EstimateSize for SingleSubBuilder {
+    fn estimated_size(&self) -> usize {
+        if self.items.is_empty() {
+            return 0;
+        }
+        // the lookup's offset, plus format 2, which is never smaller than
+        // format 1: format, coverage offset, count, and the substitutes
+        Offset16::RAW_BYTE_LEN
+            + u16::RAW_BYTE_LEN * (3 + self.items.len())
+            + max_coverage_size(self.items.len())
+    }
+}
+
 /// A builder for [`MultipleSubstFormat1`](super::MultipleSubstFormat1) subtables.
 #[derive(Clone, Debug, Default)]
 pub struct MultipleSubBuilder {
     items: BTreeMap<GlyphId16, Vec<GlyphId16>>,
 }
 
+/// An empty sequence is a valid replacement, and deletes the target glyph.
+impl From<BTreeMap<GlyphId16, Vec<GlyphId16>>> for MultipleSubBuilder {
+    fn from(items: BTreeMap<GlyphId16, Vec<GlyphId16>>) -> Self {
+        MultipleSubBuilder { items }
+    }
+}
+
 impl Builder for MultipleSubBuilder {
     type Output = Vec<super::MultipleSubstFormat1>;
 
@@ -183,6 +212,12 @@ impl Builder for MultipleSubBuilder {
     }
 }
 
+impl EstimateSize for MultipleSubBuilder {
+    fn estimated_size(&self) -> usize {
+        Offset16::RAW_BYTE_LEN + sequence_table_size(&self.items)
+    }
+}
+
 impl MultipleSubBuilder {
     /// Add a new substitution to this builder.
     ///
@@ -228,6 +263,12 @@ impl AlternateSubBuilder {
     }
 }
 
+impl From<BTreeMap<GlyphId16, Vec<GlyphId16>>> for AlternateSubBuilder {
+    fn from(items: BTreeMap<GlyphId16, Vec<GlyphId16>>) -> Self {
+        AlternateSubBuilder { items }
+    }
+}
+
 impl Builder for AlternateSubBuilder {
     type Output = Vec<super::AlternateSubstFormat1>;
 
@@ -242,6 +283,23 @@ impl Builder for AlternateSubBuilder {
     }
 }
 
+impl EstimateSize for AlternateSubBuilder {
+    fn estimated_size(&self) -> usize {
+        Offset16::RAW_BYTE_LEN + sequence_table_size(&self.items)
+    }
+}
+
+// the size of a multiple or alternate substitution subtable, which have the
+// same layout: format, coverage offset, count, and offsets to glyph arrays
+fn sequence_table_size(items: &BTreeMap<GlyphId16, Vec<GlyphId16>>) -> usize {
+    u16::RAW_BYTE_LEN * 3
+        + items
+            .values()
+            .map(|glyphs| Offset16::RAW_BYTE_LEN + u16::RAW_BYTE_LEN * (1 + glyphs.len()))
+            .sum::<usize>()
+        + max_coverage_size(items.len())
+}
+
 /// A builder for [`LigatureSubstFormat1`](super::LigatureSubstFormat1) subtables.
 #[derive(Clone, Debug, Default)]
 pub struct LigatureSubBuilder {
@@ -296,6 +354,39 @@ impl Builder for LigatureSubBuilder {
     }
 }
 
+impl EstimateSize for LigatureSubBuilder {
+    fn estimated_size(&self) -> usize {
+        // count the subtables the same way as the TableSplitter used by
+        // `build`, using the size of each ligature set
+        let mut subtable_count = 0;
+        let mut current_size = 0;
+        let mut sets_size = 0;
+        for ligs in self.items.values() {
+            // ligatureCount, and the offset to and size of each ligature
+            let set_size = u16::RAW_BYTE_LEN
+                + ligs
+                    .iter()
+                    .map(|(components, _)| {
+                        Offset16::RAW_BYTE_LEN
+                            + u16::RAW_BYTE_LEN * 2
+                            + u16::RAW_BYTE_LEN * components.len()
+                    })
+                    .sum::<usize>();
+            if current_size == 0 || set_size + current_size > u16::MAX as usize {
+                subtable_count += 1;
+                current_size = u16::RAW_BYTE_LEN * 4;
+            }
+            current_size += set_size + GlyphId16::RAW_BYTE_LEN;
+            // the set, its offset, and its glyph in the coverage table
+            sets_size += set_size + Offset16::RAW_BYTE_LEN + GlyphId16::RAW_BYTE_LEN;
+        }
+        // the lookup's offset, format, coverage offset, set count, and the
+        // coverage header
+        subtable_count * (Offset16::RAW_BYTE_LEN + u16::RAW_BYTE_LEN * 3 + max_coverage_size(0))
+            + sets_size
+    }
+}
+
 impl SplitTable for super::LigatureSubstFormat1 {
     type Component = super::LigatureSet;
 
@@ -371,6 +462,91 @@ mod tests {
         }
     }
 
+    fn build_single_sub(pairs: &[(u16, u16)]) -> Vec<super::super::SingleSubst> {
+        let items = pairs
+            .iter()
+            .map(|(target, replacement)| (GlyphId16::new(*target), GlyphId16::new(*replacement)))
+            .collect::<BTreeMap<_, _>>();
+        SingleSubBuilder::from(items).build(&mut VariationStoreBuilder::new(0))
+    }
+
+    #[test]
+    fn single_sub_constant_delta() {
+        let built = build_single_sub(&[(5, 15), (6, 16), (20, 30)]);
+        let [super::super::SingleSubst::Format1(table)] = built.as_slice() else {
+            panic!("expected a single format 1 subtable, got {built:?}");
+        };
+        assert_eq!(table.delta_glyph_id, 10);
+        assert_eq!(
+            table.coverage.iter().collect::<Vec<_>>(),
+            [5, 6, 20].map(GlyphId16::new)
+        );
+    }
+
+    #[test]
+    fn single_sub_irregular() {
+        let built = build_single_sub(&[(5, 15), (6, 2), (20, 30)]);
+        let [super::super::SingleSubst::Format2(table)] = built.as_slice() else {
+            panic!("expected a single format 2 subtable, got {built:?}");
+        };
+        assert_eq!(table.substitute_glyph_ids, [15, 2, 30].map(GlyphId16::new));
+        assert_eq!(
+            table.coverage.iter().collect::<Vec<_>>(),
+            [5, 6, 20].map(GlyphId16::new)
+        );
+    }
+
+    fn glyph_map(items: &[(u16, &[u16])]) -> BTreeMap<GlyphId16, Vec<GlyphId16>> {
+        items
+            .iter()
+            .map(|(target, glyphs)| {
+                let glyphs = glyphs.iter().copied().map(GlyphId16::new).collect();
+                (GlyphId16::new(*target), glyphs)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn multiple_sub_from_map() {
+        // glyph 9 is deleted
+        let items = glyph_map(&[(9, &[]), (4, &[1, 2, 3])]);
+        let built = MultipleSubBuilder::from(items).build(&mut VariationStoreBuilder::new(0));
+        let [table] = built.as_slice() else {
+            panic!("expected a single subtable, got {built:?}");
+        };
+        assert_eq!(
+            table.coverage.iter().collect::<Vec<_>>(),
+            [4, 9].map(GlyphId16::new)
+        );
+        assert_eq!(
+            table.sequences[0].substitute_glyph_ids,
+            [1, 2, 3].map(GlyphId16::new)
+        );
+        assert!(table.sequences[1].substitute_glyph_ids.is_empty());
+        assert!(crate::dump_table(table).is_ok());
+    }
+
+    #[test]
+    fn alternate_sub_from_map() {
+        let items = glyph_map(&[(7, &[8, 9]), (2, &[3])]);
+        let built = AlternateSubBuilder::from(items).build(&mut VariationStoreBuilder::new(0));
+        let [table] = built.as_slice() else {
+            panic!("expected a single subtable, got {built:?}");
+        };
+        assert_eq!(
+            table.coverage.iter().collect::<Vec<_>>(),
+            [2, 7].map(GlyphId16::new)
+        );
+        assert_eq!(
+            table.alternate_sets[0].alternate_glyph_ids,
+            [GlyphId16::new(3)]
+        );
+        assert_eq!(
+            table.alternate_sets[1].alternate_glyph_ids,
+            [8, 9].map(GlyphId16::new)
+        );
+    }
+
     #[test]
     fn splitting_ligature_subs() {
         let mut splitter = TableSplitter::<LigatureSubstFormat1>::new();
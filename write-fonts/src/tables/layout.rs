@@ -1,6 +1,6 @@
 //! OpenType layout.
 
-use std::{collections::HashSet, hash::Hash};
+use std::{collections::HashSet, hash::Hash, ops::RangeInclusive};
 
 pub use read_fonts::tables::layout::LookupFlag;
 use read_fonts::FontRead;
@@ -286,6 +286,26 @@ impl ClassDefFormat2 {
     }
 }
 
+/// The differences between two [`ClassDef`]s.
+///
+/// See [`ClassDef::diff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClassDefDiff {
+    /// Glyphs with a different class in each table, as `(glyph, old, new)`
+    pub changed: Vec<(GlyphId16, u16, u16)>,
+    /// Glyphs that only have a class in the new table, with that class
+    pub added: Vec<(GlyphId16, u16)>,
+    /// Glyphs that only have a class in the old table, with that class
+    pub removed: Vec<(GlyphId16, u16)>,
+}
+
+impl ClassDefDiff {
+    /// Returns `true` if the two tables assign the same classes.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
 impl ClassDef {
     pub fn iter(&self) -> impl Iterator<Item = (GlyphId16, u16)> + '_ {
         let (one, two) = match self {
@@ -296,6 +316,73 @@ impl ClassDef {
         one.into_iter().flatten().chain(two.into_iter().flatten())
     }
 
+    /// Iterate over the class ranges in this table, regardless of format.
+    ///
+    /// Sequential glyphs with the same class are merged into a single record,
+    /// and glyphs explicitly assigned to class 0 are skipped.
+    pub fn iter_ranges(&self) -> impl Iterator<Item = ClassRangeRecord> + '_ {
+        let items = self
+            .iter()
+            .filter(|(_, cls)| *cls != 0)
+            .collect::<std::collections::BTreeMap<_, _>>();
+        builders::iter_class_ranges(items)
+    }
+
+    /// Compare the classes in this table (the old table) with those in `other`
+    /// (the new table).
+    ///
+    /// Only the assignment of glyphs to classes is compared, so tables that
+    /// differ only in format or in how their ranges are split are considered
+    /// equal. A glyph with class 0 is treated as having no class. Each list
+    /// in the result is sorted by glyph id.
+    pub fn diff(&self, other: &ClassDef) -> ClassDefDiff {
+        let classes = |class_def: &ClassDef| {
+            class_def
+                .iter()
+                .filter(|(_, cls)| *cls != 0)
+                .collect::<std::collections::BTreeMap<_, _>>()
+        };
+        let old = classes(self);
+        let mut new = classes(other);
+        let mut diff = ClassDefDiff::default();
+        for (glyph, old_class) in old {
+            match new.remove(&glyph) {
+                Some(new_class) if new_class != old_class => {
+                    diff.changed.push((glyph, old_class, new_class))
+                }
+                Some(_) => (),
+                None => diff.removed.push((glyph, old_class)),
+            }
+        }
+        diff.added = new.into_iter().collect();
+        diff
+    }
+
+    /// Render the classes in this table as a human-readable string.
+    ///
+    /// Classes are listed in order of class id, with runs of sequential glyphs
+    /// written as ranges: for example, `class 1: 3-5, 9; class 2: 10-12`.
+    /// Class 0 is omitted.
+    ///
+    /// This is intended for debugging and diffing; the format is not stable.
+    pub fn to_debug_string(&self) -> String {
+        let mut classes = std::collections::BTreeMap::<u16, Vec<String>>::new();
+        for range in self.iter_ranges() {
+            let (start, end) = (range.start_glyph_id.to_u16(), range.end_glyph_id.to_u16());
+            let rendered = if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            };
+            classes.entry(range.class).or_default().push(rendered);
+        }
+        classes
+            .into_iter()
+            .map(|(cls, ranges)| format!("class {cls}: {}", ranges.join(", ")))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
     /// Return the glyph class for the provided glyph.
     ///
     /// Glyphs which have not been assigned a class are given class 0
@@ -318,6 +405,72 @@ impl ClassDef {
         }
     }
 
+    /// Create a classdef from a list of glyph ranges and their classes.
+    ///
+    /// The ranges may be in any order. Ranges that overlap or abut are
+    /// combined if they have the same class; if overlapping ranges have
+    /// different classes, this returns a [`ConflictingAssignment`] error for
+    /// the first glyph in the overlap. Empty ranges and ranges assigned to
+    /// class 0 are ignored.
+    ///
+    /// This uses format 2 unless format 1 would be smaller.
+    ///
+    /// [`ConflictingAssignment`]: builders::LayoutBuildError::ConflictingAssignment
+    pub fn from_ranges(
+        ranges: &[(RangeInclusive<GlyphId16>, u16)],
+    ) -> Result<ClassDef, builders::LayoutBuildError> {
+        let mut sorted = ranges
+            .iter()
+            .filter(|(range, class)| *class != 0 && !range.is_empty())
+            .map(|(range, class)| ClassRangeRecord::new(*range.start(), *range.end(), *class))
+            .collect::<Vec<_>>();
+        sorted.sort_unstable_by_key(|rec| (rec.start_glyph_id, rec.end_glyph_id));
+
+        let mut records: Vec<ClassRangeRecord> = Vec::with_capacity(sorted.len());
+        for rec in sorted {
+            match records.last_mut() {
+                Some(prev) if prev.end_glyph_id >= rec.start_glyph_id => {
+                    if prev.class != rec.class {
+                        return Err(builders::LayoutBuildError::ConflictingAssignment {
+                            glyph: rec.start_glyph_id,
+                        });
+                    }
+                    prev.end_glyph_id = prev.end_glyph_id.max(rec.end_glyph_id);
+                }
+                Some(prev)
+                    if prev.class == rec.class
+                        && are_sequential(prev.end_glyph_id, rec.start_glyph_id) =>
+                {
+                    prev.end_glyph_id = rec.end_glyph_id;
+                }
+                _ => records.push(rec),
+            }
+        }
+
+        let glyph_span = records
+            .first()
+            .zip(records.last())
+            .map(|(first, last)| (first.start_glyph_id, last.end_glyph_id));
+        match glyph_span {
+            Some((first, last))
+                if builders::ClassDefBuilderImpl::prefer_format_1(glyph_span, records.len()) =>
+            {
+                let (first, last) = (first.to_u16(), last.to_u16());
+                let mut class_value_array = vec![0; (last - first) as usize + 1];
+                for rec in &records {
+                    let start = (rec.start_glyph_id.to_u16() - first) as usize;
+                    let end = (rec.end_glyph_id.to_u16() - first) as usize;
+                    class_value_array[start..=end].fill(rec.class);
+                }
+                Ok(ClassDef::Format1(ClassDefFormat1::new(
+                    GlyphId16::new(first),
+                    class_value_array,
+                )))
+            }
+            _ => Ok(ClassDef::Format2(ClassDefFormat2::new(records))),
+        }
+    }
+
     pub fn class_count(&self) -> u16 {
         //TODO: implement a good integer set!!
         self.iter()
@@ -401,6 +554,12 @@ impl FromIterator<(GlyphId16, u16)> for ClassDef {
     }
 }
 
+impl<const N: usize> From<[(GlyphId16, u16); N]> for ClassDef {
+    fn from(src: [(GlyphId16, u16); N]) -> ClassDef {
+        src.into_iter().collect()
+    }
+}
+
 impl RangeRecord {
     /// An iterator over records for this array of glyphs.
     ///
@@ -445,7 +604,12 @@ fn iter_gids(gid1: GlyphId16, gid2: GlyphId16) -> impl Iterator<Item = GlyphId16
     (gid1.to_u16()..=gid2.to_u16()).map(GlyphId16::new)
 }
 
-fn are_sequential(gid1: GlyphId16, gid2: GlyphId16) -> bool {
+/// Returns `true` if `gid2` immediately follows `gid1`, with no overflow.
+///
+/// This is the test used to decide whether glyphs can share a range when
+/// building [`CoverageTable`]s and [`ClassDef`]s; there is no glyph after
+/// `u16::MAX`, so nothing follows it.
+pub fn are_sequential(gid1: GlyphId16, gid2: GlyphId16) -> bool {
     gid2.to_u16().saturating_sub(gid1.to_u16()) == 1
 }
 
@@ -456,15 +620,7 @@ impl Device {
             values.len(),
             "device range and values must match"
         );
-        let delta_format: DeltaFormat = values
-            .iter()
-            .map(|val| match val {
-                -2..=1 => DeltaFormat::Local2BitDeltas,
-                -8..=7 => DeltaFormat::Local4BitDeltas,
-                _ => DeltaFormat::Local8BitDeltas,
-            })
-            .max()
-            .unwrap_or_default();
+        let delta_format = choose_delta_format(values);
         let delta_value = encode_delta(delta_format, values);
 
         Device {
@@ -493,6 +649,25 @@ impl FontWrite for PendingVariationIndex {
     }
 }
 
+/// Return the smallest [`DeltaFormat`] that can represent all of the given
+/// per-ppem deltas in a [`Device`] table.
+///
+/// Every `i8` fits in [`DeltaFormat::Local8BitDeltas`]; values outside of
+/// that range cannot be stored in a device table at all, which is why the
+/// deltas are taken as `i8`. Callers with wider values should convert them
+/// with `i8::try_from` first. An empty slice uses the default format.
+pub fn choose_delta_format(deltas: &[i8]) -> DeltaFormat {
+    deltas
+        .iter()
+        .map(|val| match val {
+            -2..=1 => DeltaFormat::Local2BitDeltas,
+            -8..=7 => DeltaFormat::Local4BitDeltas,
+            _ => DeltaFormat::Local8BitDeltas,
+        })
+        .max()
+        .unwrap_or_default()
+}
+
 fn encode_delta(format: DeltaFormat, values: &[i8]) -> Vec<u16> {
     let (chunk_size, mask, bits) = match format {
         DeltaFormat::Local2BitDeltas => (8, 0b11, 2),
@@ -524,6 +699,105 @@ impl From<VariationIndex> for u32 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn class_def_diff() {
+        let make = |pairs: &[(u16, u16)]| {
+            pairs
+                .iter()
+                .map(|(gid, cls)| (GlyphId16::new(*gid), *cls))
+                .collect::<ClassDef>()
+        };
+        let reference = make(&[(3, 1), (4, 1), (5, 1), (9, 2)]);
+        let output = make(&[(3, 1), (4, 2), (5, 1), (9, 2)]);
+        assert_eq!(
+            reference.diff(&output),
+            ClassDefDiff {
+                changed: vec![(GlyphId16::new(4), 1, 2)],
+                ..Default::default()
+            }
+        );
+
+        let output = make(&[(3, 1), (4, 1), (10, 2)]);
+        let diff = reference.diff(&output);
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.added, [(GlyphId16::new(10), 2)]);
+        assert_eq!(
+            diff.removed,
+            [(GlyphId16::new(5), 1), (GlyphId16::new(9), 2)]
+        );
+        assert!(reference.diff(&reference).is_empty());
+    }
+
+    #[test]
+    fn class_def_debug_string() {
+        let class_def = ClassDef::Format1(ClassDefFormat1::new(
+            GlyphId16::new(3),
+            vec![1, 1, 1, 0, 0, 0, 1, 2, 2, 2],
+        ));
+        assert_eq!(
+            class_def.to_debug_string(),
+            "class 1: 3-5, 9; class 2: 10-12"
+        );
+
+        let class_def =
+            ClassDef::from([(100, 1), (500, 1)].map(|(gid, cls)| (GlyphId16::new(gid), cls)));
+        assert!(matches!(class_def, ClassDef::Format2(_)));
+        assert_eq!(class_def.to_debug_string(), "class 1: 100, 500");
+    }
+
+    #[test]
+    fn class_def_from_ranges() {
+        let range = |start: u16, end: u16| GlyphId16::new(start)..=GlyphId16::new(end);
+
+        let class_def =
+            ClassDef::from_ranges(&[(range(20, 29), 2), (range(3, 5), 1), (range(100, 120), 3)])
+                .unwrap();
+        assert!(matches!(class_def, ClassDef::Format2(_)));
+        assert_eq!(
+            class_def.to_debug_string(),
+            "class 1: 3-5; class 2: 20-29; class 3: 100-120"
+        );
+
+        // overlapping and adjacent ranges of the same class are combined
+        let class_def = ClassDef::from_ranges(&[
+            (range(3, 6), 1),
+            (range(5, 8), 1),
+            (range(9, 9), 1),
+            (range(10, 11), 2),
+        ])
+        .unwrap();
+        assert_eq!(
+            class_def,
+            ClassDef::Format2(ClassDefFormat2::new(vec![
+                ClassRangeRecord::new(GlyphId16::new(3), GlyphId16::new(9), 1),
+                ClassRangeRecord::new(GlyphId16::new(10), GlyphId16::new(11), 2),
+            ]))
+        );
+
+        // many short ranges are smaller as format 1
+        let class_def = ClassDef::from_ranges(&[
+            (range(3, 4), 1),
+            (range(5, 5), 2),
+            (range(6, 7), 1),
+            (range(8, 8), 3),
+        ])
+        .unwrap();
+        assert_eq!(
+            class_def,
+            ClassDef::Format1(ClassDefFormat1::new(
+                GlyphId16::new(3),
+                vec![1, 1, 2, 1, 1, 3]
+            ))
+        );
+
+        assert_eq!(
+            ClassDef::from_ranges(&[(range(3, 10), 1), (range(12, 15), 2), (range(8, 12), 1)]),
+            Err(builders::LayoutBuildError::ConflictingAssignment {
+                glyph: GlyphId16::new(12)
+            })
+        );
+    }
+
     #[test]
     #[should_panic(expected = "array exceeds max length")]
     fn array_len_smoke_test() {
@@ -558,6 +832,36 @@ mod tests {
         classdef.validate().unwrap();
     }
 
+    #[test]
+    fn sequential_glyphs() {
+        let gid = GlyphId16::new;
+        assert!(are_sequential(gid(0), gid(1)));
+        assert!(are_sequential(gid(u16::MAX - 1), gid(u16::MAX)));
+        assert!(!are_sequential(gid(u16::MAX), gid(u16::MAX)));
+        assert!(!are_sequential(gid(u16::MAX), gid(0)));
+        assert!(!are_sequential(gid(1), gid(0)));
+        assert!(!are_sequential(gid(5), gid(7)));
+    }
+
+    #[test]
+    fn delta_format_boundaries() {
+        assert_eq!(choose_delta_format(&[]), DeltaFormat::Local2BitDeltas);
+        assert_eq!(choose_delta_format(&[-2, 1]), DeltaFormat::Local2BitDeltas);
+        assert_eq!(choose_delta_format(&[0, 2]), DeltaFormat::Local4BitDeltas);
+        assert_eq!(choose_delta_format(&[-3]), DeltaFormat::Local4BitDeltas);
+        assert_eq!(choose_delta_format(&[-8, 7]), DeltaFormat::Local4BitDeltas);
+        assert_eq!(choose_delta_format(&[1, 8]), DeltaFormat::Local8BitDeltas);
+        assert_eq!(choose_delta_format(&[-9]), DeltaFormat::Local8BitDeltas);
+        assert_eq!(
+            choose_delta_format(&[i8::MIN, i8::MAX]),
+            DeltaFormat::Local8BitDeltas
+        );
+        assert_eq!(
+            Device::new(10, 12, &[0, -3, 1]).delta_format,
+            choose_delta_format(&[0, -3, 1])
+        );
+    }
+
     #[test]
     fn delta_encode() {
         let inp = [1i8, 2, 3, -1];
@@ -1,18 +1,28 @@
 //! Common utilities and helpers for constructing layout tables
 
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::{Range, RangeInclusive},
+};
 
-use read_fonts::collections::IntSet;
-use types::GlyphId16;
+use read_fonts::{collections::IntSet, FontData, ReadError};
+use types::{F2Dot14, FixedSize, GlyphId, GlyphId16};
 
 use super::{
     ClassDef, ClassDefFormat1, ClassDefFormat2, ClassRangeRecord, CoverageFormat1, CoverageFormat2,
     CoverageTable, Device, DeviceOrVariationIndex, Lookup, LookupFlag, PendingVariationIndex,
     RangeRecord,
 };
-use crate::tables::{
-    gdef::CaretValue,
-    variations::{ivs_builder::VariationStoreBuilder, VariationRegion},
+use crate::{
+    from_obj::FromObjRef,
+    tables::{
+        gdef::{CaretValue, GlyphClassDef, LigCaretList, LigGlyph, MarkGlyphSets},
+        variations::{
+            ivs_builder::VariationStoreBuilder, model::VariationModel, InstanceLocation,
+            VariationRegion,
+        },
+    },
+    FontWrite, TableWriter,
 };
 
 /// A simple trait for building GPOS/GSUB lookups and subtables.
@@ -37,10 +47,74 @@ pub trait Builder {
     /// annoying to work with, as Option<&mut _> doesn't impl Copy, so you need
     /// to do a dance anytime you use it.
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output;
+
+    /// Finalize the builder, returning an error if the output cannot be represented.
+    ///
+    /// This is the fallible version of [`build`](Self::build); builders that
+    /// can fail (for instance if a coverage table or class definition would
+    /// overflow) should override it, and `build` will then panic in those cases.
+    fn try_build(
+        self,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Result<Self::Output, LayoutBuildError>
+    where
+        Self: Sized,
+    {
+        Ok(self.build(var_store))
+    }
+
+    /// Build this builder twice, returning both outputs.
+    ///
+    /// Because `build` consumes the builder its output cannot be cached; this
+    /// builds a clone first, so that callers that need to compile the same
+    /// builder more than once (for instance to compare the size of
+    /// speculative compilations) don't need to clone it themselves.
+    ///
+    /// Both builds use the same `var_store`; any deltas are deduplicated
+    /// (unless the store was created without deduplication), so the two
+    /// outputs refer to the same variation indices.
+    fn build_twice(&self, var_store: &mut VariationStoreBuilder) -> (Self::Output, Self::Output)
+    where
+        Self: Clone + Sized,
+    {
+        let first = self.clone().build(var_store);
+        (first, self.clone().build(var_store))
+    }
+
+    /// `true` if building would add any deltas to the `VariationStoreBuilder`.
+    ///
+    /// This can be used to skip creating an [`ItemVariationStore`] for static
+    /// fonts. GSUB builders never use variations, so the default returns `false`.
+    ///
+    /// [`ItemVariationStore`]: crate::tables::variations::ItemVariationStore
+    fn uses_variations(&self) -> bool {
+        false
+    }
+}
+
+/// A builder that can estimate the compiled size of its output.
+///
+/// This is required by [`LookupBuilder::estimated_size`].
+pub trait EstimateSize {
+    /// An upper bound on the number of bytes in the compiled output.
+    ///
+    /// Since a subtable builder may produce more than one subtable, this also
+    /// includes the offset to each subtable from the enclosing lookup.
+    fn estimated_size(&self) -> usize;
 }
 
 pub(crate) type FilterSetId = u16;
 
+/// A builder for [`LookupFlag`]s.
+///
+/// This provides named setters for each flag, and checks that the flags are
+/// consistent when building.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LookupFlagBuilder {
+    flags: LookupFlag,
+    mark_attachment_class: u8,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct LookupBuilder<T> {
     pub flags: LookupFlag,
@@ -48,17 +122,44 @@ pub struct LookupBuilder<T> {
     pub subtables: Vec<T>,
 }
 
+/// A subtable builder, along with a label describing where it came from.
+///
+/// The label (for instance, the location of the source rule) is included in
+/// any error produced while building the subtable. Since the label is stored
+/// with the subtable, it stays correct if the subtables of a lookup are
+/// reordered or removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Labeled<T> {
+    /// The label, or `None` if this subtable has no label
+    pub label: Option<String>,
+    /// The subtable builder
+    pub subtable: T,
+}
+
+/// A subtable builder that produces no subtables.
+///
+/// This can be used as a placeholder in code that is generic over
+/// [`Builder`], for instance when a feature is disabled; a
+/// `LookupBuilder<EmptySubtable<T>>` compiles to a lookup with no subtables.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EmptySubtable<T>(std::marker::PhantomData<T>);
+
 /// An opinionated builder for `ClassDef`s.
 ///
 /// This ensures that class ids are assigned based on the size of the class.
 ///
+/// Class definitions can only refer to 16-bit glyph ids; use
+/// [`try_add_glyph_ids`](Self::try_add_glyph_ids) to add glyphs from a font
+/// that uses 32-bit ids.
+///
 /// If you need to know the values assigned to particular classes, call the
 /// [`ClassDefBuilder::build_with_mapping`] method, which will build the final
 /// [`ClassDef`] table, and will also return a map from the original class sets
 /// to the final assigned class id values.
 ///
 /// If you don't care about this, you can also construct a `ClassDef` from any
-/// iterator over `(GlyphId16, u16)` tuples, using collect:
+/// iterator over `(GlyphId16, u16)` tuples, using collect, or directly from
+/// an array:
 ///
 /// ```
 /// # use write_fonts::{types::GlyphId16, tables::layout::ClassDef};
@@ -66,6 +167,8 @@ pub struct LookupBuilder<T> {
 /// let gid2 = GlyphId16::new(2);
 /// let gid3 = GlyphId16::new(2);
 /// let my_class: ClassDef = [(gid1, 2), (gid2, 3), (gid3, 4)].into_iter().collect();
+/// let same_class = ClassDef::from([(gid1, 2), (gid2, 3), (gid3, 4)]);
+/// assert_eq!(my_class, same_class);
 /// ```
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -73,15 +176,86 @@ pub struct ClassDefBuilder {
     classes: HashSet<IntSet<GlyphId16>>,
     all_glyphs: IntSet<GlyphId16>,
     use_class_0: bool,
+    // ids requested via `try_add_with_id`
+    fixed_ids: BTreeMap<u16, IntSet<GlyphId16>>,
+    sort_strategy: SortStrategy,
+    merge_class_0_gaps: bool,
+}
+
+/// The order in which a [`ClassDefBuilder`] assigns ids to classes.
+///
+/// In each case, ties are broken by the lowest glyph id in each class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SortStrategy {
+    /// Larger classes are assigned lower ids.
+    ///
+    /// This is the default, and matches fonttools.
+    #[default]
+    LargestFirst,
+    /// Smaller classes are assigned lower ids.
+    SmallestFirst,
+    /// Classes are ordered by their lowest glyph id.
+    ByFirstGlyph,
+}
+
+/// A [`ClassDef`], along with a map from each class to its assigned id.
+pub type ClassDefWithMapping = (ClassDef, HashMap<IntSet<GlyphId16>, u16>);
+
+/// A [`ClassDef`] and its class mapping, along with how class 0 is used.
+///
+/// See [`ClassDefBuilder::try_build_with_class_0`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassDefWithClass0 {
+    /// The compiled classdef
+    pub class_def: ClassDef,
+    /// A map from each class to its assigned id
+    pub mapping: HashMap<IntSet<GlyphId16>, u16>,
+    /// `true` if no class in `mapping` was assigned id 0.
+    ///
+    /// In this case class 0 implicitly contains every glyph that is not in
+    /// some class in `mapping`. Otherwise class 0 is an ordinary class, and
+    /// glyphs not in the mapping also have class 0.
+    pub implicit_class_0: bool,
+}
+
+/// A pair of [`ClassDefBuilder`]s that are compiled together.
+///
+/// This is intended for cases (such as class-based pair positioning) where
+/// two `ClassDef`s are built over the same set of glyphs. Any glyph set that
+/// was added to both builders is ordered before the sets unique to either
+/// builder, so if both builders use the same class 0 policy, a shared set
+/// is assigned the same class id in each table.
+///
+/// As with [`ClassDefBuilder`], the result depends only on the classes that
+/// were added and not on the order in which they were added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClassDefBuilderPair {
+    /// The builder for the first `ClassDef`
+    pub first: ClassDefBuilder,
+    /// The builder for the second `ClassDef`
+    pub second: ClassDefBuilder,
 }
 
 /// A builder for [CoverageTable] tables.
 ///
 /// This will choose the best format based for the included glyphs.
-#[derive(Debug, Default, PartialEq, Eq)]
+///
+/// Coverage tables can only refer to 16-bit glyph ids; use
+/// [`try_add_glyph_id`](Self::try_add_glyph_id) to add glyphs from a font
+/// that uses 32-bit ids.
+///
+/// Two builders are equal if they contain the same glyphs, regardless of
+/// whether they are [append-only](Self::new_append_only) or remember the
+/// [format](Self::source_format) of the table they were created from.
+#[derive(Debug, Default)]
 pub struct CoverageTableBuilder {
     // invariant: is always sorted
     glyphs: Vec<GlyphId16>,
+    // if set, glyphs can only be added after the current last glyph
+    append_only: bool,
+    // the format of the table this was created from, if it should be kept
+    source_format: Option<u16>,
 }
 
 /// A value with a default position and optionally variations or a device table.
@@ -100,6 +274,19 @@ pub struct Metric {
     pub device_or_deltas: DeviceOrDeltas,
 }
 
+/// How fractional values are rounded when constructing a [`Metric`] or deltas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoundingMode {
+    /// Round to the nearest integer, with ties going to the even integer.
+    #[default]
+    HalfEven,
+    /// Round to the nearest integer, with ties going away from zero.
+    HalfAwayFromZero,
+    /// Round towards zero.
+    Truncate,
+}
+
 /// Either a `Device` table or a set of deltas.
 ///
 /// This stores deltas directly; during compilation, the deltas are bundled
@@ -113,10 +300,148 @@ pub struct Metric {
 pub enum DeviceOrDeltas {
     Device(Device),
     Deltas(Vec<(VariationRegion, i16)>),
+    /// Deltas where each region is identified by an index returned from
+    /// [`VariationStoreBuilder::add_region`].
+    ///
+    /// These are added to the store without hashing their regions again.
+    /// See [`DeviceOrDeltas::from_region_indices`].
+    IndexedDeltas(Vec<(u16, i16)>),
     #[default]
     None,
 }
 
+/// The source of a set of deltas, before they are lowered to regions.
+///
+/// Deltas keyed by instance are lowered using a variation model built from
+/// their locations; see [`DeltaSource::lower`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeltaSource {
+    /// Deltas that are already associated with a region.
+    Regions(Vec<(VariationRegion, i16)>),
+    /// Deltas from the default, at named instance (or master) locations.
+    Instance(Vec<(InstanceLocation, i16)>),
+}
+
+/// An error that occurs while building a layout table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayoutBuildError {
+    /// A format 1 coverage table would contain more glyphs than can be
+    /// counted by a `u16`.
+    ///
+    /// This only happens if every glyph id is added to a builder that keeps
+    /// the format 1 of the table it was created from.
+    CoverageOverflow {
+        /// The number of glyphs in the table
+        glyph_count: usize,
+    },
+    /// A classdef would require more class ids than can be stored in a `u16`.
+    ClassDefOverflow {
+        /// The number of class ids required, including class 0 if it is reserved
+        class_count: usize,
+    },
+    /// A glyph was assigned to more than one class.
+    ConflictingAssignment {
+        /// The first glyph found to be in conflict
+        glyph: GlyphId16,
+    },
+    /// A lookup ignores all marks, but also sets a mark attachment class.
+    ConflictingLookupFlags {
+        /// The mark attachment class that was set
+        mark_attachment_class: u8,
+    },
+    /// A glyph id does not fit in the 16 bits available in layout tables.
+    GlyphIdTooLarge {
+        /// The glyph that could not be added
+        glyph: GlyphId,
+    },
+    /// A lookup's mark filtering set does not agree with its
+    /// `USE_MARK_FILTERING_SET` flag.
+    MismatchedMarkFilteringSet {
+        /// The mark filtering set, or `None` if the flag is set without one
+        mark_set: Option<u16>,
+    },
+    /// A class id was requested for more than one class, or a class was
+    /// given more than one id.
+    ConflictingClassId {
+        /// The requested class id
+        class_id: u16,
+    },
+    /// Deltas could not be combined, because at least one of them is a
+    /// compiled device table, or because one identifies its regions by
+    /// index and the other does not.
+    IncompatibleDeltas,
+    /// A glyph was added to an append-only coverage table before one of its
+    /// existing glyphs.
+    GlyphOutOfOrder {
+        /// The glyph that could not be added
+        glyph: GlyphId16,
+    },
+    /// Building a [`Labeled`] subtable failed.
+    InSubtable {
+        /// The label of the subtable
+        label: String,
+        /// The error produced by the subtable
+        error: Box<LayoutBuildError>,
+    },
+    /// Summing two metrics or deltas produced a value outside the range of
+    /// an `i16`.
+    DeltaOverflow,
+}
+
+impl std::fmt::Display for LayoutBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutBuildError::CoverageOverflow { glyph_count } => {
+                write!(f, "coverage table has too many glyphs ({glyph_count})")
+            }
+            LayoutBuildError::ClassDefOverflow { class_count } => {
+                write!(f, "classdef has too many classes ({class_count})")
+            }
+            LayoutBuildError::ConflictingAssignment { glyph } => {
+                write!(f, "glyph {glyph} assigned to multiple classes")
+            }
+            LayoutBuildError::ConflictingLookupFlags {
+                mark_attachment_class,
+            } => write!(
+                f,
+                "lookup ignores marks but sets mark attachment class {mark_attachment_class}"
+            ),
+            LayoutBuildError::GlyphIdTooLarge { glyph } => {
+                write!(
+                    f,
+                    "glyph {glyph} cannot be represented by a 16-bit glyph id"
+                )
+            }
+            LayoutBuildError::MismatchedMarkFilteringSet {
+                mark_set: Some(set),
+            } => write!(
+                f,
+                "lookup has mark filtering set {set} but USE_MARK_FILTERING_SET is not set"
+            ),
+            LayoutBuildError::MismatchedMarkFilteringSet { mark_set: None } => write!(
+                f,
+                "lookup sets USE_MARK_FILTERING_SET but has no mark filtering set"
+            ),
+            LayoutBuildError::ConflictingClassId { class_id } => {
+                write!(f, "class id {class_id} conflicts with an existing class")
+            }
+            LayoutBuildError::IncompatibleDeltas => {
+                write!(f, "device tables and deltas cannot be accumulated")
+            }
+            LayoutBuildError::DeltaOverflow => write!(f, "accumulated value overflows i16"),
+            LayoutBuildError::GlyphOutOfOrder { glyph } => write!(
+                f,
+                "glyph {glyph} cannot be added before existing glyphs in an append-only coverage table"
+            ),
+            LayoutBuildError::InSubtable { label, error } => {
+                write!(f, "error building subtable '{label}': {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutBuildError {}
+
 /// A value in the GDEF ligature caret list
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -134,6 +459,51 @@ pub enum CaretValueBuilder {
     PointIndex(u16),
 }
 
+/// An error indicating that a caret has deltas for a region with the wrong
+/// number of axes.
+///
+/// See [`CaretValueBuilder::validate_regions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaretError {
+    /// The number of axes in the font
+    pub expected: usize,
+    /// The number of axes in the region
+    pub found: usize,
+}
+
+/// A builder for the GDEF [`LigCaretList`].
+///
+/// This collects the carets for each ligature glyph; the coverage table and
+/// the `LigGlyph` tables are generated in glyph id order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LigCaretListBuilder {
+    ligatures: BTreeMap<GlyphId16, Vec<CaretValueBuilder>>,
+}
+
+/// A builder for the GDEF glyph class definition.
+///
+/// This assigns each glyph one of the four classes defined for GDEF, so that
+/// callers don't need to remember their class ids.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GlyphClassDefBuilder {
+    classes: BTreeMap<GlyphId16, GlyphClassDef>,
+}
+
+/// A builder for the GDEF [`MarkGlyphSets`] table.
+///
+/// Each distinct set of glyphs is assigned an id, in the order the sets are
+/// first added; this is the id that lookups use as their mark filtering set
+/// (see [`LookupBuilder::mark_set`]).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MarkGlyphSetsBuilder {
+    // in id order
+    sets: Vec<IntSet<GlyphId16>>,
+    ids: HashMap<IntSet<GlyphId16>, FilterSetId>,
+}
+
 impl ClassDefBuilder {
     /// Create a new `ClassDefBuilder`.
     pub fn new() -> Self {
@@ -154,6 +524,101 @@ impl ClassDefBuilder {
         }
     }
 
+    /// Set the order in which class ids are assigned when the builder is
+    /// compiled.
+    ///
+    /// This can be used to match the class ids produced by another compiler.
+    /// Ids requested with [`try_add_with_id`](Self::try_add_with_id) are
+    /// unaffected.
+    pub fn with_sort_strategy(mut self, strategy: SortStrategy) -> Self {
+        self.sort_strategy = strategy;
+        self
+    }
+
+    /// Set whether format 2 ranges are merged across unassigned glyphs when
+    /// the builder is compiled.
+    ///
+    /// If `true`, adjacent ranges with the same class that are separated only
+    /// by glyphs in no class are written as a single range, which can make
+    /// the table smaller. The glyphs in the gap are then reported as
+    /// belonging to that class instead of class 0, so this should only be
+    /// used when those glyphs are never looked up in this classdef, such as
+    /// when it is paired with a coverage table that does not include them
+    /// (as in GPOS class-based pair positioning).
+    pub fn with_class_0_gap_merging(mut self, merge_class_0_gaps: bool) -> Self {
+        self.merge_class_0_gaps = merge_class_0_gaps;
+        self
+    }
+
+    /// Create a builder from the classes in an existing [`ClassDef`].
+    ///
+    /// Each distinct class in the table becomes a class in the builder; the
+    /// final class ids may differ from the original ids, since they are
+    /// reassigned when the builder is compiled.
+    ///
+    /// Glyphs with class 0 are ignored, unless `keep_zero` is `true`, in which
+    /// case any glyphs explicitly assigned class 0 by the table (including the
+    /// glyphs that fill gaps in a format 1 table) are kept as a class. The
+    /// builder does not use class 0 (see [`new`](Self::new)), so these glyphs
+    /// are assigned a non-zero id when the builder is compiled, like any other
+    /// class.
+    ///
+    /// To change the classes before rebuilding the table, use
+    /// [`class_containing`](Self::class_containing) and
+    /// [`remove_class`](Self::remove_class), and then add the modified class.
+    ///
+    /// [`ClassDef`]: read_fonts::tables::layout::ClassDef
+    pub fn from_class_def(
+        class_def: &read_fonts::tables::layout::ClassDef<'_>,
+        keep_zero: bool,
+    ) -> Self {
+        let mut classes = BTreeMap::<u16, IntSet<GlyphId16>>::new();
+        let mut seen = IntSet::empty();
+        for (gid, cls) in class_def.iter() {
+            // in a malformed format 2 table a glyph may be in multiple ranges;
+            // the first one wins, which matches the behaviour of lookup.
+            if (cls != 0 || keep_zero) && seen.insert(gid) {
+                classes.entry(cls).or_default().insert(gid);
+            }
+        }
+        let mut builder = Self::new();
+        for cls in classes.into_values() {
+            builder.all_glyphs.extend(cls.iter());
+            builder.classes.insert(cls);
+        }
+        builder
+    }
+
+    /// Create a builder from an existing assignment of glyphs to class ids.
+    ///
+    /// Glyphs with the same class id are grouped into a class; as with
+    /// [`from_class_def`](Self::from_class_def), the class ids may be
+    /// reassigned when the builder is compiled. Any glyph with class 0 is
+    /// treated as belonging to an ordinary class.
+    ///
+    /// This accepts any iterator of pairs (including a `BTreeMap`), and
+    /// returns an error if some glyph is assigned more than one class.
+    pub fn try_from_map(
+        map: impl IntoIterator<Item = (GlyphId16, u16)>,
+    ) -> Result<Self, LayoutBuildError> {
+        let mut assigned = BTreeMap::new();
+        let mut classes = BTreeMap::<u16, IntSet<GlyphId16>>::new();
+        for (glyph, class) in map {
+            if assigned
+                .insert(glyph, class)
+                .is_some_and(|prev| prev != class)
+            {
+                return Err(LayoutBuildError::ConflictingAssignment { glyph });
+            }
+            classes.entry(class).or_default().insert(glyph);
+        }
+        let mut builder = Self::new();
+        for cls in classes.into_values() {
+            builder.try_add(cls)?;
+        }
+        Ok(builder)
+    }
+
     pub(crate) fn can_add(&self, cls: &IntSet<GlyphId16>) -> bool {
         self.classes.contains(cls) || cls.iter().all(|gid| !self.all_glyphs.contains(gid))
     }
@@ -162,51 +627,358 @@ impl ClassDefBuilder {
     ///
     /// returns `true` if the class is added, and `false` otherwise.
     pub fn checked_add(&mut self, cls: IntSet<GlyphId16>) -> bool {
-        if self.can_add(&cls) {
-            self.all_glyphs.extend(cls.iter());
-            self.classes.insert(cls);
-            true
-        } else {
-            false
+        self.try_add(cls).is_ok()
+    }
+
+    /// Add each of these classes with [`checked_add`](Self::checked_add).
+    ///
+    /// Returns the classes that could not be added because they conflict with
+    /// a class that was already added, in the order they were provided.
+    pub fn checked_add_all(
+        &mut self,
+        classes: impl IntoIterator<Item = IntSet<GlyphId16>>,
+    ) -> Vec<IntSet<GlyphId16>> {
+        let mut rejected = Vec::new();
+        for cls in classes {
+            if self.can_add(&cls) {
+                self.checked_add(cls);
+            } else {
+                rejected.push(cls);
+            }
+        }
+        rejected
+    }
+
+    /// Add this class to this classdef, or return an error if it conflicts
+    /// with a class that has already been added.
+    ///
+    /// A class conflicts if it is not identical to an existing class, but
+    /// shares at least one glyph with some existing class.
+    pub fn try_add(&mut self, cls: IntSet<GlyphId16>) -> Result<(), LayoutBuildError> {
+        if !self.classes.contains(&cls) {
+            if let Some(glyph) = cls.iter().find(|gid| self.all_glyphs.contains(*gid)) {
+                return Err(LayoutBuildError::ConflictingAssignment { glyph });
+            }
+        }
+        self.all_glyphs.extend(cls.iter());
+        self.classes.insert(cls);
+        Ok(())
+    }
+
+    /// Add this class to this classdef, requesting a specific class id.
+    ///
+    /// The id is only used by [`build_with_mapping_preserving_ids`]; the other
+    /// build methods assign ids by class size, as usual.
+    ///
+    /// Returns an error if the class conflicts with an existing class (as in
+    /// [`try_add`](Self::try_add)), if the id was already requested for a
+    /// different class, if a different id was already requested for this
+    /// class, or if the id is 0 and this builder does not use class 0 (see
+    /// [`new_using_class_0`](Self::new_using_class_0)). If an error is
+    /// returned, the builder is unchanged.
+    ///
+    /// [`build_with_mapping_preserving_ids`]: Self::build_with_mapping_preserving_ids
+    pub fn try_add_with_id(
+        &mut self,
+        cls: IntSet<GlyphId16>,
+        id: u16,
+    ) -> Result<(), LayoutBuildError> {
+        let id_taken = self
+            .fixed_ids
+            .get(&id)
+            .is_some_and(|existing| *existing != cls);
+        let class_has_other_id = self
+            .fixed_ids
+            .iter()
+            .any(|(existing_id, existing)| *existing == cls && *existing_id != id);
+        let reserved_class_0 = id == 0 && !self.use_class_0;
+        if id_taken || class_has_other_id || reserved_class_0 {
+            return Err(LayoutBuildError::ConflictingClassId { class_id: id });
+        }
+        self.try_add(cls.clone())?;
+        self.fixed_ids.insert(id, cls);
+        Ok(())
+    }
+
+    /// Returns the class containing `glyph`, if it has been added to a class.
+    pub fn class_containing(&self, glyph: GlyphId16) -> Option<&IntSet<GlyphId16>> {
+        if !self.all_glyphs.contains(glyph) {
+            return None;
+        }
+        self.classes.iter().find(|cls| cls.contains(glyph))
+    }
+
+    /// Remove a class from this classdef.
+    ///
+    /// The glyphs in the class are no longer assigned to any class, and can
+    /// be added to a new class. Any id requested for this class with
+    /// [`try_add_with_id`](Self::try_add_with_id) is also removed.
+    ///
+    /// Returns `false` if the class was not in this classdef.
+    pub fn remove_class(&mut self, cls: &IntSet<GlyphId16>) -> bool {
+        if !self.classes.remove(cls) {
+            return false;
+        }
+        self.all_glyphs.subtract(cls);
+        self.fixed_ids.retain(|_, existing| existing != cls);
+        true
+    }
+
+    /// Add a class of (possibly 32-bit) glyph ids to this classdef.
+    ///
+    /// Returns an error if any glyph does not fit in a [`GlyphId16`], or if
+    /// the class conflicts with an existing class, as in
+    /// [`try_add`](Self::try_add). If an error is returned, the builder is
+    /// unchanged.
+    pub fn try_add_glyph_ids(
+        &mut self,
+        cls: impl IntoIterator<Item = GlyphId>,
+    ) -> Result<(), LayoutBuildError> {
+        let cls = cls
+            .into_iter()
+            .map(narrow_glyph_id)
+            .collect::<Result<IntSet<_>, _>>()?;
+        self.try_add(cls)
+    }
+
+    /// Returns any glyphs in the coverage table that have not been added to
+    /// any class in this builder.
+    ///
+    /// When a `ClassDef` is paired with a [`CoverageTable`] (such as in GPOS
+    /// class-based pair positioning) every covered glyph is generally expected
+    /// to also be assigned a class; a glyph present in the coverage but missing
+    /// here is likely a bug.
+    ///
+    /// (Glyphs added to a builder created with [`new_using_class_0`] are
+    /// considered to be explicitly classed.)
+    ///
+    /// [`new_using_class_0`]: ClassDefBuilder::new_using_class_0
+    pub fn verify_against_coverage(&self, coverage: &CoverageTableBuilder) -> Vec<GlyphId16> {
+        coverage
+            .glyphs
+            .iter()
+            .copied()
+            .filter(|gid| !self.all_glyphs.contains(*gid))
+            .collect()
+    }
+
+    /// Returns the largest number of consecutive unused glyph ids that fall
+    /// between two glyphs in this builder.
+    ///
+    /// A format 1 `ClassDef` stores an entry for every glyph in its range, so
+    /// a large gap (for instance, caused by a single outlying glyph) suggests
+    /// that something may be wrong with the input.
+    pub fn max_gap(&self) -> u16 {
+        max_glyph_gap(self.all_glyphs.iter())
+    }
+
+    /// Returns the number of classes that will be assigned an id when built.
+    ///
+    /// This is the number of entries in the mapping returned by
+    /// [`build_with_mapping`](Self::build_with_mapping). Adding a class that
+    /// is identical to an existing class does not add another class, so this
+    /// never overcounts. It does not include the implicit class 0.
+    pub fn class_count(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// Returns the fraction of the glyph ids between the first and last glyph
+    /// in this builder that are assigned to some class.
+    ///
+    /// A format 1 `ClassDef` stores an entry for every glyph in this range,
+    /// so a low density means that most of those entries are wasted, even if
+    /// format 1 is chosen because it is marginally smaller. Returns `0.0` if
+    /// the builder is empty.
+    pub fn density(&self) -> f32 {
+        match (self.all_glyphs.first(), self.all_glyphs.last()) {
+            (Some(first), Some(last)) => {
+                let span = last.to_u32() - first.to_u32() + 1;
+                self.all_glyphs.len() as f32 / span as f32
+            }
+            _ => 0.0,
         }
     }
 
     /// Returns a compiled [`ClassDef`], as well as a mapping from our glyph sets
     /// to the final class ids.
     ///
-    /// This sorts the classes, ensuring that larger classes are first.
+    /// This sorts the classes, ensuring that larger classes are first, unless
+    /// a different order was requested with [`with_sort_strategy`].
     ///
     /// (This is needed when subsequent structures are ordered based on the
     /// final order of class assignments.)
+    ///
+    /// # Panics
+    ///
+    /// This panics if there are more classes than can be represented by a
+    /// `u16`. Use [`try_build_with_mapping`] to handle this case.
+    ///
+    /// [`try_build_with_mapping`]: ClassDefBuilder::try_build_with_mapping
+    /// [`with_sort_strategy`]: ClassDefBuilder::with_sort_strategy
     pub fn build_with_mapping(self) -> (ClassDef, HashMap<IntSet<GlyphId16>, u16>) {
+        self.try_build_with_mapping().unwrap()
+    }
+
+    /// Like [`build_with_mapping`], but returns an error if there are more
+    /// classes than can be represented by a `u16`.
+    ///
+    /// [`build_with_mapping`]: ClassDefBuilder::build_with_mapping
+    pub fn try_build_with_mapping(
+        self,
+    ) -> Result<(ClassDef, HashMap<IntSet<GlyphId16>, u16>), LayoutBuildError> {
         let mut classes = self.classes.into_iter().collect::<Vec<_>>();
-        // we match the sort order used by fonttools, see:
-        // <https://github.com/fonttools/fonttools/blob/9a46f9d3ab01e3/Lib/fontTools/otlLib/builder.py#L2677>
-        classes.sort_unstable_by_key(|cls| {
-            (
-                std::cmp::Reverse(cls.len()),
-                cls.iter().next().unwrap_or_default().to_u16(),
-            )
-        });
-        classes.dedup();
-        let add_one = u16::from(!self.use_class_0);
-        let mapping = classes
+        self.sort_strategy.sort(&mut classes);
+        assign_class_ids(classes, self.use_class_0, self.merge_class_0_gaps)
+    }
+
+    /// Like [`try_build_with_mapping`], but also report whether class 0 was
+    /// assigned to one of the classes.
+    ///
+    /// The mapping never contains an entry for glyphs that are in no class;
+    /// callers that need to account for those glyphs (for instance, to size a
+    /// class-pair matrix) can use [`ClassDefWithClass0::implicit_class_0`].
+    ///
+    /// [`try_build_with_mapping`]: Self::try_build_with_mapping
+    pub fn try_build_with_class_0(self) -> Result<ClassDefWithClass0, LayoutBuildError> {
+        let (class_def, mapping) = self.try_build_with_mapping()?;
+        let implicit_class_0 = !mapping.values().any(|id| *id == 0);
+        Ok(ClassDefWithClass0 {
+            class_def,
+            mapping,
+            implicit_class_0,
+        })
+    }
+
+    /// Returns a compiled [`ClassDef`] and the class mapping, keeping the ids
+    /// requested with [`try_add_with_id`].
+    ///
+    /// Classes added without an id are sorted as in [`build_with_mapping`],
+    /// and are assigned the ids following the largest requested id.
+    ///
+    /// This is intended for round-tripping existing tables without renumbering
+    /// their classes. Because the ids are not ordered by class size, and may
+    /// have gaps, this can produce larger tables than [`build_with_mapping`],
+    /// both here and in any structures indexed by class.
+    ///
+    /// # Panics
+    ///
+    /// This panics if there are more classes than can be represented by a
+    /// `u16`.
+    ///
+    /// [`try_add_with_id`]: Self::try_add_with_id
+    /// [`build_with_mapping`]: Self::build_with_mapping
+    pub fn build_with_mapping_preserving_ids(self) -> ClassDefWithMapping {
+        self.try_build_with_mapping_preserving_ids().unwrap()
+    }
+
+    /// Like [`build_with_mapping_preserving_ids`], but returns an error if
+    /// there are more classes than can be represented by a `u16`, or if class
+    /// 0 was requested by a builder that does not use class 0.
+    ///
+    /// [`build_with_mapping_preserving_ids`]: Self::build_with_mapping_preserving_ids
+    pub fn try_build_with_mapping_preserving_ids(
+        self,
+    ) -> Result<ClassDefWithMapping, LayoutBuildError> {
+        let ClassDefBuilder {
+            classes,
+            use_class_0,
+            fixed_ids,
+            sort_strategy,
+            merge_class_0_gaps,
+            ..
+        } = self;
+        if !use_class_0 && fixed_ids.contains_key(&0) {
+            return Err(LayoutBuildError::ConflictingClassId { class_id: 0 });
+        }
+        let first_free = match fixed_ids.keys().next_back() {
+            Some(last) => *last as usize + 1,
+            None => usize::from(!use_class_0),
+        };
+        let fixed = fixed_ids.values().collect::<HashSet<_>>();
+        let mut rest = classes
+            .iter()
+            .filter(|cls| !fixed.contains(cls))
+            .cloned()
+            .collect::<Vec<_>>();
+        sort_strategy.sort(&mut rest);
+        let class_count = first_free + rest.len();
+        if class_count > MAX_CLASS_COUNT {
+            return Err(LayoutBuildError::ClassDefOverflow { class_count });
+        }
+        let mapping = rest
             .into_iter()
             .enumerate()
-            .map(|(i, cls)| (cls, i as u16 + add_one))
+            .map(|(i, cls)| (cls, (first_free + i) as u16))
+            .chain(fixed_ids.into_iter().map(|(id, cls)| (cls, id)))
             .collect::<HashMap<_, _>>();
-        let class_def = mapping
-            .iter()
-            .flat_map(|(cls, id)| cls.iter().map(move |gid| (gid, *id)))
-            .collect();
+        Ok((class_def_for_mapping(&mapping, merge_class_0_gaps), mapping))
+    }
+
+    /// Returns a compiled [`ClassDef`], as well as a map from each glyph to
+    /// its final class id.
+    ///
+    /// Classes are assigned the same ids as in [`build_with_mapping`], but
+    /// the ids are recorded per glyph, so the glyph sets are never used as
+    /// hash keys.
+    ///
+    /// # Panics
+    ///
+    /// This panics if there are more classes than can be represented by a
+    /// `u16`.
+    ///
+    /// [`build_with_mapping`]: ClassDefBuilder::build_with_mapping
+    pub fn build_with_glyph_mapping(self) -> (ClassDef, BTreeMap<GlyphId16, u16>) {
+        let mut classes = self.classes.into_iter().collect::<Vec<_>>();
+        self.sort_strategy.sort(&mut classes);
+        assign_glyph_class_ids(classes, self.use_class_0, self.merge_class_0_gaps).unwrap()
+    }
 
-        (class_def, mapping)
+    /// Returns a compiled [`ClassDef`] and the class mapping, with every glyph
+    /// in `all_glyphs` that is not in some class assigned to `catchall_id`.
+    ///
+    /// The other classes are assigned ids as in [`build_with_mapping`]. This
+    /// is useful when a lookup needs to match "any other glyph" explicitly,
+    /// and cannot rely on class 0.
+    ///
+    /// Returns an error if `catchall_id` is also assigned to one of the other
+    /// classes, or if there are more classes than can be represented by a
+    /// `u16`.
+    ///
+    /// [`build_with_mapping`]: Self::build_with_mapping
+    pub fn with_catchall_class(
+        self,
+        all_glyphs: &IntSet<GlyphId16>,
+        catchall_id: u16,
+    ) -> Result<ClassDefWithMapping, LayoutBuildError> {
+        let mut catchall = all_glyphs.clone();
+        catchall.subtract(&self.all_glyphs);
+        let merge_class_0_gaps = self.merge_class_0_gaps;
+        let (_, mut mapping) = self.try_build_with_mapping()?;
+        if mapping.values().any(|id| *id == catchall_id) {
+            return Err(LayoutBuildError::ConflictingClassId {
+                class_id: catchall_id,
+            });
+        }
+        if !catchall.is_empty() {
+            mapping.insert(catchall, catchall_id);
+        }
+        Ok((class_def_for_mapping(&mapping, merge_class_0_gaps), mapping))
     }
 
     /// Build a final [`ClassDef`] table.
+    ///
+    /// If no classes have been added, this produces an empty format 2 table,
+    /// which is the smallest possible encoding.
     pub fn build(self) -> ClassDef {
         self.build_with_mapping().0
     }
+
+    /// Build a final [`ClassDef`] table, or return an error if there are more
+    /// classes than can be represented by a `u16`.
+    pub fn try_build(self) -> Result<ClassDef, LayoutBuildError> {
+        self.try_build_with_mapping()
+            .map(|(class_def, _)| class_def)
+    }
 }
 
 /// Builder logic for classdefs.
@@ -221,28 +993,72 @@ pub(super) struct ClassDefBuilderImpl {
 }
 
 impl ClassDefBuilderImpl {
-    fn prefer_format_1(&self) -> bool {
+    fn iter_ranges(&self) -> impl Iterator<Item = ClassRangeRecord> + '_ {
+        iter_class_ranges(self.items.iter().map(|(gid, cls)| (*gid, *cls)))
+    }
+
+    // merge records of the same class that are separated only by unassigned
+    // (i.e. class 0) glyphs.
+    fn iter_merged_ranges(&self) -> impl Iterator<Item = ClassRangeRecord> + '_ {
+        let mut iter = self.iter_ranges().peekable();
+        std::iter::from_fn(move || {
+            let mut current = iter.next()?;
+            while let Some(next) = iter.next_if(|next| next.class == current.class) {
+                current.end_glyph_id = next.end_glyph_id;
+            }
+            Some(current)
+        })
+    }
+
+    // the first and last glyphs in the classdef, if it is not empty
+    fn glyph_span(&self) -> Option<(GlyphId16, GlyphId16)> {
+        let (first, _) = self.items.first_key_value()?;
+        let (last, _) = self.items.last_key_value()?;
+        Some((*first, *last))
+    }
+
+    /// `true` if format 1 is smaller than format 2 with the given number of
+    /// ranges, for a classdef whose first and last glyphs are `glyph_span`.
+    pub(super) fn prefer_format_1(
+        glyph_span: Option<(GlyphId16, GlyphId16)>,
+        format2_range_count: usize,
+    ) -> bool {
         const U16_LEN: usize = std::mem::size_of::<u16>();
         const FORMAT1_HEADER_LEN: usize = U16_LEN * 3;
         const FORMAT2_HEADER_LEN: usize = U16_LEN * 2;
         const CLASS_RANGE_RECORD_LEN: usize = U16_LEN * 3;
         // format 2 is the most efficient way to represent an empty classdef
-        if self.items.is_empty() {
+        let Some((first, last)) = glyph_span else {
             return false;
-        }
+        };
         // calculate our format2 size:
-        let first = self.items.keys().next().map(|g| g.to_u16()).unwrap();
-        let last = self.items.keys().next_back().map(|g| g.to_u16()).unwrap();
-        let format1_array_len = (last - first) as usize + 1;
+        let format1_array_len = (last.to_u16() - first.to_u16()) as usize + 1;
         let len_format1 = FORMAT1_HEADER_LEN + format1_array_len * U16_LEN;
-        let len_format2 =
-            FORMAT2_HEADER_LEN + iter_class_ranges(&self.items).count() * CLASS_RANGE_RECORD_LEN;
+        let len_format2 = FORMAT2_HEADER_LEN + format2_range_count * CLASS_RANGE_RECORD_LEN;
 
         len_format1 < len_format2
     }
 
     pub fn build(&self) -> ClassDef {
-        if self.prefer_format_1() {
+        self.build_with_gap_merging(false)
+    }
+
+    /// Build the classdef, optionally merging format 2 ranges across gaps.
+    ///
+    /// If `merge_class_0_gaps` is `true`, then when format 2 is chosen,
+    /// adjacent ranges with the same class that are separated only by
+    /// unassigned glyphs are written as a single range. Each merge saves one
+    /// six-byte `ClassRangeRecord`, and may make format 2 smaller than
+    /// format 1. See [`ClassDefBuilder::with_class_0_gap_merging`] for when
+    /// this is correct.
+    pub fn build_with_gap_merging(&self, merge_class_0_gaps: bool) -> ClassDef {
+        // we walk the glyphs once, and reuse the ranges if we pick format 2
+        let class_range_records: Vec<_> = if merge_class_0_gaps {
+            self.iter_merged_ranges().collect()
+        } else {
+            self.iter_ranges().collect()
+        };
+        if Self::prefer_format_1(self.glyph_span(), class_range_records.len()) {
             let first = self.items.keys().next().map(|g| g.to_u16()).unwrap_or(0);
             let last = self.items.keys().next_back().map(|g| g.to_u16());
             let class_value_array = (first..=last.unwrap_or_default())
@@ -259,18 +1075,63 @@ impl ClassDefBuilderImpl {
             })
         } else {
             ClassDef::Format2(ClassDefFormat2 {
-                class_range_records: iter_class_ranges(&self.items).collect(),
+                class_range_records,
             })
         }
     }
 }
 
 impl CoverageTableBuilder {
+    /// Returns a coverage table that contains no glyphs.
+    ///
+    /// This is the same table produced by building an empty builder: both
+    /// formats have the same size when empty, and we use format 1.
+    pub fn empty() -> CoverageTable {
+        CoverageTable::Format1(CoverageFormat1 {
+            glyph_array: Vec::new(),
+        })
+    }
+
     /// Create a new builder from a vec of `GlyphId`.
     pub fn from_glyphs(mut glyphs: Vec<GlyphId16>) -> Self {
         glyphs.sort_unstable();
         glyphs.dedup();
-        CoverageTableBuilder { glyphs }
+        CoverageTableBuilder {
+            glyphs,
+            append_only: false,
+            source_format: None,
+        }
+    }
+
+    /// Create a new, empty builder that only accepts glyphs in increasing order.
+    ///
+    /// Normally adding a glyph shifts the coverage index of every larger glyph.
+    /// In this mode, adding a glyph smaller than the current largest glyph is
+    /// an error, so that a returned coverage index never changes. Adding a
+    /// glyph that is already present is allowed, and returns its index.
+    pub fn new_append_only() -> Self {
+        CoverageTableBuilder {
+            glyphs: Vec::new(),
+            append_only: true,
+            source_format: None,
+        }
+    }
+
+    /// Create a new builder from the ranges of an existing coverage table,
+    /// remapping each glyph.
+    ///
+    /// Glyphs for which `remap` returns `None` (for instance because they
+    /// have been removed during subsetting) are dropped. The remapped glyphs
+    /// do not need to be contiguous or in order.
+    pub fn from_remapped_ranges(
+        ranges: impl IntoIterator<Item = RangeRecord>,
+        remap: impl Fn(GlyphId16) -> Option<GlyphId16>,
+    ) -> Self {
+        ranges
+            .into_iter()
+            .flat_map(|range| range.start_glyph_id.to_u16()..=range.end_glyph_id.to_u16())
+            .filter_map(|gid| remap(GlyphId16::new(gid)))
+            .collect()
     }
 
     /// Add a `GlyphId` to this coverage table.
@@ -278,47 +1139,397 @@ impl CoverageTableBuilder {
     /// Returns the coverage index of the added glyph.
     ///
     /// If the glyph already exists, this returns its current index.
+    ///
+    /// # Panics
+    ///
+    /// This panics if this builder is [append-only](Self::new_append_only) and
+    /// the glyph is smaller than the largest glyph already added; use
+    /// [`try_add`](Self::try_add) to handle this case.
     pub fn add(&mut self, glyph: GlyphId16) -> u16 {
-        match self.glyphs.binary_search(&glyph) {
-            Ok(ix) => ix as u16,
+        self.try_add(glyph).unwrap()
+    }
+
+    /// Add a `GlyphId` to this coverage table, returning its coverage index.
+    ///
+    /// Returns an error if this builder is [append-only](Self::new_append_only)
+    /// and the glyph would be inserted before an existing glyph.
+    pub fn try_add(&mut self, glyph: GlyphId16) -> Result<u16, LayoutBuildError> {
+        let ix = match self.glyphs.binary_search(&glyph) {
+            Ok(ix) => ix,
+            Err(ix) if self.append_only && ix < self.glyphs.len() => {
+                return Err(LayoutBuildError::GlyphOutOfOrder { glyph });
+            }
             Err(ix) => {
                 self.glyphs.insert(ix, glyph);
-                // if we're over u16::MAX glyphs, crash
-                ix.try_into().unwrap()
+                ix
             }
-        }
+        };
+        // the glyphs are distinct 16-bit ids, so the index always fits
+        Ok(ix as u16)
     }
 
-    //NOTE: it would be nice if we didn't do this intermediate step and instead
-    //wrote out bytes directly, but the current approach is simpler.
-    /// Convert this builder into the appropriate [CoverageTable] variant.
-    pub fn build(self) -> CoverageTable {
-        if should_choose_coverage_format_2(&self.glyphs) {
-            CoverageTable::Format2(CoverageFormat2 {
-                range_records: RangeRecord::iter_for_glyphs(&self.glyphs).collect(),
-            })
-        } else {
-            CoverageTable::Format1(CoverageFormat1 {
-                glyph_array: self.glyphs,
-            })
-        }
+    /// Add a (possibly 32-bit) glyph id to this coverage table, returning its
+    /// coverage index.
+    ///
+    /// Returns an error if the glyph does not fit in a [`GlyphId16`], or if
+    /// it cannot be added as in [`try_add`](Self::try_add).
+    pub fn try_add_glyph_id(&mut self, glyph: GlyphId) -> Result<u16, LayoutBuildError> {
+        self.try_add(narrow_glyph_id(glyph)?)
     }
-}
 
-impl<T: Default> LookupBuilder<T> {
-    pub fn new(flags: LookupFlag, mark_set: Option<FilterSetId>) -> Self {
-        LookupBuilder {
-            flags,
-            mark_set,
-            subtables: vec![Default::default()],
+    /// Returns the coverage index of this glyph, if it is present.
+    ///
+    /// Unlike [`add`](Self::add), this does not modify the builder.
+    pub fn index_of(&self, glyph: GlyphId16) -> Option<u16> {
+        self.glyphs
+            .binary_search(&glyph)
+            .ok()
+            .and_then(|ix| ix.try_into().ok())
+    }
+
+    /// Returns the smallest glyph in the coverage, if any.
+    pub fn first(&self) -> Option<GlyphId16> {
+        self.glyphs.first().copied()
+    }
+
+    /// Returns the largest glyph in the coverage, if any.
+    pub fn last(&self) -> Option<GlyphId16> {
+        self.glyphs.last().copied()
+    }
+
+    /// The format that will be used when building, if this builder was
+    /// created from an existing table.
+    ///
+    /// See [`reoptimize`](Self::reoptimize).
+    pub fn source_format(&self) -> Option<u16> {
+        self.source_format
+    }
+
+    /// Choose the smallest format when building, instead of the format of
+    /// the table this builder was created from.
+    pub fn reoptimize(&mut self) {
+        self.source_format = None;
+    }
+
+    /// Convert this builder into the appropriate [CoverageTable] variant.
+    ///
+    /// An empty builder produces the table returned by [`empty`](Self::empty).
+    /// A builder created from an existing table uses the same format as that
+    /// table, unless [`reoptimize`](Self::reoptimize) has been called.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the table would be too large to represent; use
+    /// [`try_build`](Self::try_build) to handle this case.
+    pub fn build(self) -> CoverageTable {
+        self.try_build().unwrap()
+    }
+
+    /// Convert this builder into the appropriate [CoverageTable] variant, or
+    /// return an error if the table would be too large to represent.
+    pub fn try_build(self) -> Result<CoverageTable, LayoutBuildError> {
+        if self.uses_format_2() {
+            Ok(CoverageTable::Format2(CoverageFormat2 {
+                range_records: RangeRecord::iter_for_glyphs(&self.glyphs).collect(),
+            }))
+        } else if self.glyphs.len() > u16::MAX as usize {
+            // the glyph count of a format 1 table is a u16
+            Err(LayoutBuildError::CoverageOverflow {
+                glyph_count: self.glyphs.len(),
+            })
+        } else {
+            Ok(CoverageTable::Format1(CoverageFormat1 {
+                glyph_array: self.glyphs,
+            }))
+        }
+    }
+
+    // the source format if there is one, otherwise the smaller format
+    fn uses_format_2(&self) -> bool {
+        match self.source_format {
+            Some(format) => format == 2,
+            None => should_choose_coverage_format_2(&self.glyphs),
+        }
+    }
+
+    /// Returns the number of bytes saved by encoding this table in format 2
+    /// instead of format 1.
+    ///
+    /// This is negative if format 1 is smaller. [`build`](Self::build) uses
+    /// format 2 only if this is positive.
+    pub fn format_size_delta(&self) -> i64 {
+        let range_count = RangeRecord::iter_for_glyphs(&self.glyphs).count();
+        let (format1_len, format2_len) = coverage_format_lens(self.glyphs.len(), range_count);
+        format1_len as i64 - format2_len as i64
+    }
+
+    /// Write the table this builder would produce directly to `writer`.
+    ///
+    /// This produces the same bytes as compiling the output of
+    /// [`build`](Self::build), without allocating the intermediate
+    /// [`CoverageTable`].
+    ///
+    /// # Panics
+    ///
+    /// This panics if the table would be too large to represent.
+    pub fn write_to(&self, writer: &mut TableWriter) {
+        if self.uses_format_2() {
+            let range_count = RangeRecord::iter_for_glyphs(&self.glyphs).count();
+            2u16.write_into(writer);
+            u16::try_from(range_count).unwrap().write_into(writer);
+            for record in RangeRecord::iter_for_glyphs(&self.glyphs) {
+                record.write_into(writer);
+            }
+        } else {
+            1u16.write_into(writer);
+            u16::try_from(self.glyphs.len()).unwrap().write_into(writer);
+            self.glyphs.write_into(writer);
+        }
+    }
+
+    /// Returns the final [`CoverageTable`], as well as a map from each glyph
+    /// to its coverage index.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the table would be too large to represent; use
+    /// [`try_build_with_mapping`](Self::try_build_with_mapping) to handle
+    /// this case.
+    pub fn build_with_mapping(self) -> (CoverageTable, BTreeMap<GlyphId16, u16>) {
+        self.try_build_with_mapping().unwrap()
+    }
+
+    /// Like [`build_with_mapping`](Self::build_with_mapping), but returns an
+    /// error if the table would be too large to represent.
+    pub fn try_build_with_mapping(
+        self,
+    ) -> Result<(CoverageTable, BTreeMap<GlyphId16, u16>), LayoutBuildError> {
+        // glyphs are sorted, so the coverage index is their position
+        let mapping = self
+            .glyphs
+            .iter()
+            .enumerate()
+            .map(|(i, gid)| (*gid, i as u16))
+            .collect();
+        self.try_build().map(|coverage| (coverage, mapping))
+    }
+}
+
+impl ClassDefBuilderPair {
+    /// Create a new pair from two builders.
+    pub fn new(first: ClassDefBuilder, second: ClassDefBuilder) -> Self {
+        Self { first, second }
+    }
+
+    /// Build both [`ClassDef`] tables, along with their mappings from glyph
+    /// sets to class ids.
+    ///
+    /// # Panics
+    ///
+    /// This panics if either table has more classes than can be represented
+    /// by a `u16`. Use [`try_build_with_mapping`] to handle this case.
+    ///
+    /// [`try_build_with_mapping`]: ClassDefBuilderPair::try_build_with_mapping
+    pub fn build_with_mapping(self) -> (ClassDefWithMapping, ClassDefWithMapping) {
+        self.try_build_with_mapping().unwrap()
+    }
+
+    /// Like [`build_with_mapping`], but returns an error if either table has
+    /// more classes than can be represented by a `u16`.
+    ///
+    /// [`build_with_mapping`]: ClassDefBuilderPair::build_with_mapping
+    pub fn try_build_with_mapping(
+        self,
+    ) -> Result<(ClassDefWithMapping, ClassDefWithMapping), LayoutBuildError> {
+        let ClassDefBuilderPair { first, second } = self;
+        let mut shared = first
+            .classes
+            .intersection(&second.classes)
+            .cloned()
+            .collect::<Vec<_>>();
+        first.sort_strategy.sort(&mut shared);
+        let ordered = |builder: ClassDefBuilder| {
+            let mut rest = builder
+                .classes
+                .into_iter()
+                .filter(|cls| !shared.contains(cls))
+                .collect::<Vec<_>>();
+            builder.sort_strategy.sort(&mut rest);
+            let classes = shared.iter().cloned().chain(rest).collect();
+            assign_class_ids(classes, builder.use_class_0, builder.merge_class_0_gaps)
+        };
+        Ok((ordered(first)?, ordered(second)?))
+    }
+}
+
+impl SortStrategy {
+    fn sort(self, classes: &mut [IntSet<GlyphId16>]) {
+        let first_glyph = |cls: &IntSet<GlyphId16>| cls.iter().next().unwrap_or_default();
+        match self {
+            // we match the sort order used by fonttools, see:
+            // <https://github.com/fonttools/fonttools/blob/9a46f9d3ab01e3/Lib/fontTools/otlLib/builder.py#L2677>
+            SortStrategy::LargestFirst => {
+                classes.sort_unstable_by_key(|cls| (std::cmp::Reverse(cls.len()), first_glyph(cls)))
+            }
+            SortStrategy::SmallestFirst => {
+                classes.sort_unstable_by_key(|cls| (cls.len(), first_glyph(cls)))
+            }
+            SortStrategy::ByFirstGlyph => classes.sort_unstable_by_key(first_glyph),
+        }
+    }
+}
+
+// the number of class ids representable by a u16, including class 0
+const MAX_CLASS_COUNT: usize = u16::MAX as usize + 1;
+
+// assign ids to classes, in order
+fn assign_class_ids(
+    classes: Vec<IntSet<GlyphId16>>,
+    use_class_0: bool,
+    merge_class_0_gaps: bool,
+) -> Result<ClassDefWithMapping, LayoutBuildError> {
+    assign_class_ids_with_limit(classes, use_class_0, merge_class_0_gaps, MAX_CLASS_COUNT)
+}
+
+// the limit is only configurable so that we can test overflow cheaply
+fn assign_class_ids_with_limit(
+    classes: Vec<IntSet<GlyphId16>>,
+    use_class_0: bool,
+    merge_class_0_gaps: bool,
+    max_class_count: usize,
+) -> Result<ClassDefWithMapping, LayoutBuildError> {
+    let mapping = class_ids(classes, use_class_0, max_class_count)?.collect::<HashMap<_, _>>();
+    Ok((class_def_for_mapping(&mapping, merge_class_0_gaps), mapping))
+}
+
+// like assign_class_ids, but mapping each glyph to its class id
+fn assign_glyph_class_ids(
+    classes: Vec<IntSet<GlyphId16>>,
+    use_class_0: bool,
+    merge_class_0_gaps: bool,
+) -> Result<(ClassDef, BTreeMap<GlyphId16, u16>), LayoutBuildError> {
+    let mut mapping = BTreeMap::new();
+    for (cls, id) in class_ids(classes, use_class_0, MAX_CLASS_COUNT)? {
+        mapping.extend(cls.iter().map(|gid| (gid, id)));
+    }
+    let class_def = mapping
+        .iter()
+        .map(|(gid, id)| (*gid, *id))
+        .collect::<ClassDefBuilderImpl>()
+        .build_with_gap_merging(merge_class_0_gaps);
+    Ok((class_def, mapping))
+}
+
+// pair each of the (sorted) classes with its id
+fn class_ids(
+    mut classes: Vec<IntSet<GlyphId16>>,
+    use_class_0: bool,
+    max_class_count: usize,
+) -> Result<impl Iterator<Item = (IntSet<GlyphId16>, u16)>, LayoutBuildError> {
+    classes.dedup();
+    let add_one = u16::from(!use_class_0);
+    let class_count = classes.len() + add_one as usize;
+    if class_count > max_class_count {
+        return Err(LayoutBuildError::ClassDefOverflow { class_count });
+    }
+    Ok(classes
+        .into_iter()
+        .enumerate()
+        .map(move |(i, cls)| (cls, i as u16 + add_one)))
+}
+
+fn class_def_for_mapping(
+    mapping: &HashMap<IntSet<GlyphId16>, u16>,
+    merge_class_0_gaps: bool,
+) -> ClassDef {
+    mapping
+        .iter()
+        .flat_map(|(cls, id)| cls.iter().map(move |gid| (gid, *id)))
+        .collect::<ClassDefBuilderImpl>()
+        .build_with_gap_merging(merge_class_0_gaps)
+}
+
+impl LookupFlagBuilder {
+    /// Create a new builder with no flags set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the `RIGHT_TO_LEFT` flag, used by cursive attachment lookups.
+    pub fn right_to_left(mut self) -> Self {
+        self.flags |= LookupFlag::RIGHT_TO_LEFT;
+        self
+    }
+
+    /// Set the `IGNORE_BASE_GLYPHS` flag.
+    pub fn ignore_base_glyphs(mut self) -> Self {
+        self.flags |= LookupFlag::IGNORE_BASE_GLYPHS;
+        self
+    }
+
+    /// Set the `IGNORE_LIGATURES` flag.
+    pub fn ignore_ligatures(mut self) -> Self {
+        self.flags |= LookupFlag::IGNORE_LIGATURES;
+        self
+    }
+
+    /// Set the `IGNORE_MARKS` flag.
+    ///
+    /// This cannot be combined with a mark attachment class.
+    pub fn ignore_marks(mut self) -> Self {
+        self.flags |= LookupFlag::IGNORE_MARKS;
+        self
+    }
+
+    /// Set the `USE_MARK_FILTERING_SET` flag.
+    ///
+    /// The filtering set itself is provided separately, as the `mark_set`
+    /// argument to [`LookupBuilder::new`].
+    pub fn use_mark_filtering_set(mut self) -> Self {
+        self.flags |= LookupFlag::USE_MARK_FILTERING_SET;
+        self
+    }
+
+    /// Only consider marks with this attachment class; `0` clears the class.
+    ///
+    /// This cannot be combined with `ignore_marks`.
+    pub fn mark_attachment_class(mut self, class: u8) -> Self {
+        self.mark_attachment_class = class;
+        self
+    }
+
+    /// Build the final [`LookupFlag`], or return an error if the flags conflict.
+    pub fn build(self) -> Result<LookupFlag, LayoutBuildError> {
+        if self.mark_attachment_class != 0 && self.flags.contains(LookupFlag::IGNORE_MARKS) {
+            return Err(LayoutBuildError::ConflictingLookupFlags {
+                mark_attachment_class: self.mark_attachment_class,
+            });
         }
+        let mut flags = self.flags;
+        flags.set_mark_attachment_class(self.mark_attachment_class.into());
+        Ok(flags)
+    }
+}
+
+impl<T: Default> LookupBuilder<T> {
+    /// Create a new lookup with a single empty subtable.
+    ///
+    /// If `mark_set` is present, the `USE_MARK_FILTERING_SET` flag is set.
+    pub fn new(flags: LookupFlag, mark_set: Option<FilterSetId>) -> Self {
+        Self::new_with_lookups(flags, mark_set, vec![Default::default()])
     }
 
+    /// Create a new lookup with the provided subtables.
+    ///
+    /// If `mark_set` is present, the `USE_MARK_FILTERING_SET` flag is set.
     pub fn new_with_lookups(
-        flags: LookupFlag,
+        mut flags: LookupFlag,
         mark_set: Option<FilterSetId>,
         subtables: Vec<T>,
     ) -> Self {
+        if mark_set.is_some() {
+            flags |= LookupFlag::USE_MARK_FILTERING_SET;
+        }
         Self {
             flags,
             mark_set,
@@ -338,11 +1549,53 @@ impl<T: Default> LookupBuilder<T> {
     pub fn iter_subtables(&self) -> impl Iterator<Item = &T> + '_ {
         self.subtables.iter()
     }
+
+    pub fn iter_subtables_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.subtables.iter_mut()
+    }
+}
+
+impl<T> LookupBuilder<Labeled<T>> {
+    /// Add a subtable, along with a label describing where it came from.
+    ///
+    /// The label (for instance, the location of the source rule) is included
+    /// in any error produced while building this subtable.
+    pub fn push_labeled(&mut self, subtable: T, label: impl Into<String>) {
+        self.subtables.push(Labeled {
+            label: Some(label.into()),
+            subtable,
+        });
+    }
+
+    /// The label of the subtable at `index`, if it has one.
+    pub fn subtable_label(&self, index: usize) -> Option<&str> {
+        self.subtables.get(index)?.label.as_deref()
+    }
 }
 
 impl<U> LookupBuilder<U> {
+    /// Check that the `USE_MARK_FILTERING_SET` flag is set if and only if
+    /// there is a mark filtering set.
+    ///
+    /// Since the fields are public, they can get out of sync; shapers will
+    /// ignore a mark filtering set if the flag is not set.
+    pub fn validate(&self) -> Result<(), LayoutBuildError> {
+        let has_flag = self.flags.contains(LookupFlag::USE_MARK_FILTERING_SET);
+        if has_flag != self.mark_set.is_some() {
+            return Err(LayoutBuildError::MismatchedMarkFilteringSet {
+                mark_set: self.mark_set,
+            });
+        }
+        Ok(())
+    }
+
     /// A helper method for converting from (say) ContextBuilder to PosContextBuilder
     pub fn convert<T: From<U>>(self) -> LookupBuilder<T> {
+        self.map_subtables(Into::into)
+    }
+
+    /// Apply `f` to each subtable, preserving the lookup's flags and mark set.
+    pub fn map_subtables<T>(self, f: impl FnMut(U) -> T) -> LookupBuilder<T> {
         let LookupBuilder {
             flags,
             mark_set,
@@ -351,11 +1604,85 @@ impl<U> LookupBuilder<U> {
         LookupBuilder {
             flags,
             mark_set,
-            subtables: subtables.into_iter().map(Into::into).collect(),
+            subtables: subtables.into_iter().map(f).collect(),
+        }
+    }
+}
+
+impl<T: Builder> Builder for Labeled<T> {
+    type Output = T::Output;
+
+    fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
+        self.subtable.build(var_store)
+    }
+
+    fn try_build(
+        self,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Result<Self::Output, LayoutBuildError> {
+        let Labeled { label, subtable } = self;
+        subtable.try_build(var_store).map_err(|error| match label {
+            Some(label) => LayoutBuildError::InSubtable {
+                label,
+                error: Box::new(error),
+            },
+            None => error,
+        })
+    }
+
+    fn uses_variations(&self) -> bool {
+        self.subtable.uses_variations()
+    }
+}
+
+impl<T: EstimateSize> EstimateSize for Labeled<T> {
+    fn estimated_size(&self) -> usize {
+        self.subtable.estimated_size()
+    }
+}
+
+impl<T> From<T> for Labeled<T> {
+    fn from(subtable: T) -> Self {
+        Labeled {
+            label: None,
+            subtable,
         }
     }
 }
 
+impl<T> Builder for EmptySubtable<T> {
+    type Output = Vec<T>;
+
+    fn build(self, _: &mut VariationStoreBuilder) -> Self::Output {
+        Vec::new()
+    }
+}
+
+impl<T> EstimateSize for EmptySubtable<T> {
+    fn estimated_size(&self) -> usize {
+        0
+    }
+}
+
+impl<T: EstimateSize> LookupBuilder<T> {
+    /// An upper bound on the compiled size of this lookup and its subtables,
+    /// in bytes.
+    ///
+    /// This can be used to avoid offset overflows when budgeting the size of
+    /// a lookup list. The actual size may be smaller, since identical tables
+    /// are shared when the lookup is compiled.
+    pub fn estimated_size(&self) -> usize {
+        // lookupType, lookupFlag, subTableCount, and the mark filtering set
+        let header = u16::RAW_BYTE_LEN * (3 + self.mark_set.is_some() as usize);
+        header
+            + self
+                .subtables
+                .iter()
+                .map(EstimateSize::estimated_size)
+                .sum::<usize>()
+    }
+}
+
 impl<U, T> Builder for LookupBuilder<T>
 where
     T: Builder<Output = Vec<U>>,
@@ -363,19 +1690,93 @@ where
 {
     type Output = Lookup<U>;
 
+    /// Build the lookup.
+    ///
+    /// If the lookup fails [`validate`](LookupBuilder::validate), this logs a
+    /// warning; use [`try_build`](Builder::try_build) to treat that as an error.
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
-        let subtables = self
-            .subtables
-            .into_iter()
-            .flat_map(|b| b.build(var_store).into_iter())
-            .collect();
+        if let Err(e) = self.validate() {
+            log::warn!("{e}");
+        }
+        self.build_subtables(var_store).unwrap()
+    }
+
+    fn try_build(
+        self,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Result<Self::Output, LayoutBuildError> {
+        self.validate()?;
+        self.build_subtables(var_store)
+    }
+
+    /// `true` if any subtable in this lookup contains deltas.
+    fn uses_variations(&self) -> bool {
+        self.subtables.iter().any(Builder::uses_variations)
+    }
+}
+
+impl<U, T> LookupBuilder<T>
+where
+    T: Builder<Output = Vec<U>>,
+    U: Default,
+{
+    fn build_subtables(
+        self,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Result<Lookup<U>, LayoutBuildError> {
+        let mut subtables = Vec::new();
+        for subtable in self.subtables {
+            subtables.extend(subtable.try_build(var_store)?);
+        }
         let mut out = Lookup::new(self.flags, subtables);
         out.mark_filtering_set = self.mark_set;
-        out
+        Ok(out)
+    }
+}
+
+impl<U: Default> Lookup<U> {
+    /// Build a lookup with a single subtable.
+    ///
+    /// This is shorthand for creating a [`LookupBuilder`] with one subtable
+    /// and calling [`build`](Builder::build) on it.
+    ///
+    /// If `mark_set` is present, the `USE_MARK_FILTERING_SET` flag is set.
+    pub fn single_subtable<T>(
+        flags: LookupFlag,
+        mark_set: Option<FilterSetId>,
+        subtable: T,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Self
+    where
+        T: Builder<Output = Vec<U>> + Default,
+    {
+        LookupBuilder::new_with_lookups(flags, mark_set, vec![subtable]).build(var_store)
+    }
+}
+
+impl RoundingMode {
+    /// Round `value` to an `i16`, saturating at the bounds of the type.
+    pub fn round(self, value: f64) -> i16 {
+        let rounded = match self {
+            RoundingMode::HalfEven => value.round_ties_even(),
+            RoundingMode::HalfAwayFromZero => value.round(),
+            RoundingMode::Truncate => value.trunc(),
+        };
+        // float to int casts saturate
+        rounded as i16
     }
 }
 
 impl Metric {
+    /// Create a metric from a fractional default value and deltas, rounding
+    /// each with the provided mode.
+    pub fn from_f64(default: f64, deltas: Vec<(VariationRegion, f64)>, mode: RoundingMode) -> Self {
+        Metric {
+            default: mode.round(default),
+            device_or_deltas: DeviceOrDeltas::from_f64_deltas(deltas, mode),
+        }
+    }
+
     /// Returns `true` if the default value is `0` and there is no device or deltas
     pub fn is_zero(&self) -> bool {
         self.default == 0 && !self.has_device_or_deltas()
@@ -385,231 +1786,2137 @@ impl Metric {
     pub fn has_device_or_deltas(&self) -> bool {
         !self.device_or_deltas.is_none()
     }
-}
 
-impl DeviceOrDeltas {
-    /// Returns `true` if there is no device table or variation index
-    pub fn is_none(&self) -> bool {
-        *self == DeviceOrDeltas::None
+    /// `true` if this metric has deltas (as opposed to a device table)
+    pub fn has_deltas(&self) -> bool {
+        self.device_or_deltas.has_deltas()
     }
 
-    /// Compile the device or deltas into their final form.
-    ///
-    /// In the case of a device, this generates a [`Device`] table; in the
-    /// case of deltas this adds them to the `VariationStoreBuilder`, and returns
-    /// a [`PendingVariationIndex`] that must be remapped after the builder is
-    /// finished, using the returned [`VariationIndexRemapping`].
+    /// Returns `true` if the two metrics describe the same variation.
     ///
-    /// [`PendingVariationIndex`]: super::PendingVariationIndex
-    /// [`VariationIndexRemapping`]: crate::tables::variations::ivs_builder::VariationIndexRemapping
-    pub fn build(self, var_store: &mut VariationStoreBuilder) -> Option<DeviceOrVariationIndex> {
-        match self {
-            DeviceOrDeltas::Device(dev) => Some(DeviceOrVariationIndex::Device(dev)),
-            DeviceOrDeltas::Deltas(deltas) => {
-                let temp_id = var_store.add_deltas(deltas);
-                Some(DeviceOrVariationIndex::PendingVariationIndex(
-                    PendingVariationIndex::new(temp_id),
-                ))
-            }
-            DeviceOrDeltas::None => None,
-        }
+    /// Unlike `==`, this ignores the order of the deltas, as well as any zero
+    /// deltas.
+    pub fn var_eq(&self, other: &Metric) -> bool {
+        self.default == other.default
+            && self.device_or_deltas.normalized() == other.device_or_deltas.normalized()
     }
-}
 
-impl CaretValueBuilder {
-    /// Build the final [`CaretValue`] table.
-    pub fn build(self, var_store: &mut VariationStoreBuilder) -> CaretValue {
-        match self {
-            Self::Coordinate { default, deltas } => match deltas.build(var_store) {
-                Some(deltas) => CaretValue::format_3(default, deltas),
-                None => CaretValue::format_1(default),
-            },
-            Self::PointIndex(index) => CaretValue::format_2(index),
-        }
+    /// Split this metric into its default value and its device or deltas.
+    pub fn into_parts(self) -> (i16, DeviceOrDeltas) {
+        (self.default, self.device_or_deltas)
     }
-}
 
-impl From<i16> for Metric {
-    fn from(src: i16) -> Metric {
-        Metric {
-            default: src,
-            device_or_deltas: DeviceOrDeltas::None,
-        }
+    /// Add another metric to this one, summing the defaults and the deltas.
+    ///
+    /// See [`DeviceOrDeltas::accumulate`] for how deltas are combined. If the
+    /// sum of the defaults or of any delta does not fit in an `i16`, this
+    /// returns an error and `self` is left unchanged.
+    pub fn accumulate(&mut self, other: &Metric) -> Result<(), LayoutBuildError> {
+        let default = self
+            .default
+            .checked_add(other.default)
+            .ok_or(LayoutBuildError::DeltaOverflow)?;
+        self.device_or_deltas.accumulate(&other.device_or_deltas)?;
+        self.default = default;
+        Ok(())
     }
 }
 
-impl From<Option<Device>> for DeviceOrDeltas {
-    fn from(src: Option<Device>) -> DeviceOrDeltas {
-        src.map(DeviceOrDeltas::Device).unwrap_or_default()
+impl DeviceOrDeltas {
+    /// Create a set of deltas from fractional values, rounding each with the
+    /// provided mode.
+    ///
+    /// As with the conversion from a `Vec`, an empty list produces
+    /// [`DeviceOrDeltas::None`].
+    pub fn from_f64_deltas(deltas: Vec<(VariationRegion, f64)>, mode: RoundingMode) -> Self {
+        deltas
+            .into_iter()
+            .map(|(region, delta)| (region, mode.round(delta)))
+            .collect::<Vec<_>>()
+            .into()
     }
-}
 
-impl From<Device> for DeviceOrDeltas {
-    fn from(value: Device) -> Self {
-        DeviceOrDeltas::Device(value)
+    /// Create a set of deltas from [`F2Dot14`] values multiplied by `scale`.
+    ///
+    /// Each scaled value is rounded to the nearest integer, with ties going
+    /// to the even integer, and deltas that round to zero are dropped. If no
+    /// deltas remain, this produces [`DeviceOrDeltas::None`].
+    pub fn from_f2dot14_deltas(deltas: Vec<(VariationRegion, F2Dot14)>, scale: i32) -> Self {
+        deltas
+            .into_iter()
+            .map(|(region, delta)| {
+                let scaled = delta.to_f32() as f64 * scale as f64;
+                (region, RoundingMode::HalfEven.round(scaled))
+            })
+            .filter(|(_, delta)| *delta != 0)
+            .collect::<Vec<_>>()
+            .into()
     }
-}
 
-impl From<Vec<(VariationRegion, i16)>> for DeviceOrDeltas {
-    fn from(src: Vec<(VariationRegion, i16)>) -> DeviceOrDeltas {
-        if src.is_empty() {
+    /// Create a set of deltas where each region is identified by an index
+    /// returned from [`VariationStoreBuilder::add_region`].
+    ///
+    /// As with the conversion from a `Vec`, an empty list produces
+    /// [`DeviceOrDeltas::None`].
+    ///
+    /// The deltas must be built with the same `VariationStoreBuilder` that
+    /// returned the indices; see
+    /// [`add_deltas_by_index`](VariationStoreBuilder::add_deltas_by_index).
+    pub fn from_region_indices(deltas: Vec<(u16, i16)>) -> Self {
+        if deltas.is_empty() {
             DeviceOrDeltas::None
         } else {
-            DeviceOrDeltas::Deltas(src)
+            DeviceOrDeltas::IndexedDeltas(deltas)
         }
     }
-}
-impl FromIterator<(GlyphId16, u16)> for ClassDefBuilderImpl {
-    fn from_iter<T: IntoIterator<Item = (GlyphId16, u16)>>(iter: T) -> Self {
-        Self {
-            items: iter.into_iter().filter(|(_, cls)| *cls != 0).collect(),
-        }
+
+    /// Create a [`Device`] table from per-ppem pixel adjustments.
+    ///
+    /// `deltas` contains one value for each size, starting at `start_ppem`.
+    /// The smallest [`DeltaFormat`] that can hold all of the values is used;
+    /// see [`choose_delta_format`]. An empty list produces
+    /// [`DeviceOrDeltas::None`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last size would be larger than `u16::MAX`.
+    ///
+    /// [`DeltaFormat`]: super::DeltaFormat
+    /// [`choose_delta_format`]: super::choose_delta_format
+    pub fn from_ppem_deltas(start_ppem: u16, deltas: &[i8]) -> Self {
+        let Some(last) = deltas.len().checked_sub(1) else {
+            return DeviceOrDeltas::None;
+        };
+        let end_ppem = u16::try_from(last)
+            .ok()
+            .and_then(|last| start_ppem.checked_add(last))
+            .expect("ppem range exceeds u16::MAX");
+        DeviceOrDeltas::Device(Device::new(start_ppem, end_ppem, deltas))
+    }
+
+    /// Recover the editable form of a compiled device table or variation index.
+    ///
+    /// A device table is returned unchanged. For a variation index, the deltas
+    /// are read from the `ivs` that the index refers to, and are returned with
+    /// their regions. Zero deltas (which the store may contain for regions used
+    /// by other delta sets) are omitted.
+    ///
+    /// A [`PendingVariationIndex`] has not yet been added to a store, and so
+    /// produces an error.
+    ///
+    /// [`PendingVariationIndex`]: super::PendingVariationIndex
+    pub fn from_compiled(
+        value: &DeviceOrVariationIndex,
+        ivs: &read_fonts::tables::variations::ItemVariationStore<'_>,
+    ) -> Result<DeviceOrDeltas, ReadError> {
+        let index = match value {
+            DeviceOrVariationIndex::Device(device) => {
+                return Ok(DeviceOrDeltas::Device(device.clone()))
+            }
+            DeviceOrVariationIndex::VariationIndex(index) => index,
+            DeviceOrVariationIndex::PendingVariationIndex(_) => {
+                return Err(ReadError::MalformedData(
+                    "variation index has not been remapped",
+                ))
+            }
+        };
+        let data = ivs
+            .item_variation_data()
+            .get(index.delta_set_outer_index as usize)
+            .ok_or(ReadError::OutOfBounds)??;
+        if index.delta_set_inner_index >= data.item_count() {
+            return Err(ReadError::OutOfBounds);
+        }
+        let regions = ivs.variation_region_list()?.variation_regions();
+        let mut deltas = Vec::new();
+        for (region_index, delta) in data
+            .region_indexes()
+            .iter()
+            .zip(data.delta_set(index.delta_set_inner_index))
+        {
+            if delta == 0 {
+                continue;
+            }
+            let region = regions.get(region_index.get() as usize)?;
+            let delta = i16::try_from(delta)
+                .map_err(|_| ReadError::MalformedData("delta does not fit in an i16"))?;
+            deltas.push((
+                VariationRegion::from_obj_ref(&region, FontData::default()),
+                delta,
+            ));
+        }
+        Ok(deltas.into())
+    }
+
+    /// An upper bound on the size of the device or variation index table
+    /// this compiles to, in bytes.
+    pub(crate) fn max_table_size(&self) -> usize {
+        match self {
+            // startSize, endSize, deltaFormat, and the packed deltas
+            DeviceOrDeltas::Device(device) => u16::RAW_BYTE_LEN * (3 + device.delta_value.len()),
+            // deltaSetOuterIndex, deltaSetInnerIndex, deltaFormat
+            DeviceOrDeltas::Deltas(_) | DeviceOrDeltas::IndexedDeltas(_) => u16::RAW_BYTE_LEN * 3,
+            DeviceOrDeltas::None => 0,
+        }
+    }
+
+    /// Returns `true` if there is no device table or variation index
+    pub fn is_none(&self) -> bool {
+        *self == DeviceOrDeltas::None
+    }
+
+    /// Returns `true` if this contains deltas that will be added to a variation store
+    pub fn has_deltas(&self) -> bool {
+        matches!(
+            self,
+            DeviceOrDeltas::Deltas(_) | DeviceOrDeltas::IndexedDeltas(_)
+        )
+    }
+
+    /// Remove any zero deltas, returning `None` if no non-zero deltas remain.
+    ///
+    /// Zero deltas have no effect, but they still take up space in the
+    /// variation store. Device tables are returned unchanged.
+    pub fn optimized(self) -> DeviceOrDeltas {
+        match self {
+            DeviceOrDeltas::Deltas(mut deltas) => {
+                deltas.retain(|(_, delta)| *delta != 0);
+                deltas.into()
+            }
+            DeviceOrDeltas::IndexedDeltas(mut deltas) => {
+                deltas.retain(|(_, delta)| *delta != 0);
+                DeviceOrDeltas::from_region_indices(deltas)
+            }
+            other => other,
+        }
+    }
+
+    // with zero deltas removed and the remaining deltas sorted, for comparison
+    fn normalized(&self) -> DeviceOrDeltas {
+        match self.clone().optimized() {
+            DeviceOrDeltas::Deltas(mut deltas) => {
+                deltas.sort_unstable();
+                DeviceOrDeltas::Deltas(deltas)
+            }
+            DeviceOrDeltas::IndexedDeltas(mut deltas) => {
+                deltas.sort_unstable();
+                DeviceOrDeltas::IndexedDeltas(deltas)
+            }
+            other => other,
+        }
+    }
+
+    /// Add the deltas in `other` to these deltas.
+    ///
+    /// Deltas for the same region are summed, and regions only present in
+    /// `other` are appended. [`DeviceOrDeltas::None`] is the identity.
+    ///
+    /// Compiled device tables cannot be added together, and neither can deltas
+    /// keyed by region and deltas keyed by [region index]; in these cases this
+    /// returns an error and `self` is left unchanged. The same is true if any
+    /// summed delta does not fit in an `i16`.
+    ///
+    /// [region index]: DeviceOrDeltas::IndexedDeltas
+    pub fn accumulate(&mut self, other: &DeviceOrDeltas) -> Result<(), LayoutBuildError> {
+        match (&mut *self, other) {
+            (_, DeviceOrDeltas::None) => (),
+            (this @ DeviceOrDeltas::None, other) => *this = other.clone(),
+            (DeviceOrDeltas::Deltas(deltas), DeviceOrDeltas::Deltas(other)) => {
+                *deltas = accumulate_deltas(deltas, other)?
+            }
+            (DeviceOrDeltas::IndexedDeltas(deltas), DeviceOrDeltas::IndexedDeltas(other)) => {
+                *deltas = accumulate_deltas(deltas, other)?
+            }
+            _ => return Err(LayoutBuildError::IncompatibleDeltas),
+        }
+        Ok(())
+    }
+
+    /// Compile the device or deltas into their final form.
+    ///
+    /// In the case of a device, this generates a [`Device`] table; in the
+    /// case of deltas this adds them to the `VariationStoreBuilder`, and returns
+    /// a [`PendingVariationIndex`] that must be remapped after the builder is
+    /// finished, using the returned [`VariationIndexRemapping`].
+    ///
+    /// [`PendingVariationIndex`]: super::PendingVariationIndex
+    /// [`VariationIndexRemapping`]: crate::tables::variations::ivs_builder::VariationIndexRemapping
+    pub fn build(self, var_store: &mut VariationStoreBuilder) -> Option<DeviceOrVariationIndex> {
+        match self {
+            DeviceOrDeltas::Device(dev) => Some(DeviceOrVariationIndex::Device(dev)),
+            DeviceOrDeltas::Deltas(deltas) => {
+                let temp_id = var_store.add_deltas(deltas);
+                Some(DeviceOrVariationIndex::PendingVariationIndex(
+                    PendingVariationIndex::new(temp_id),
+                ))
+            }
+            DeviceOrDeltas::IndexedDeltas(deltas) => {
+                let temp_id = var_store.add_deltas_by_index(deltas);
+                Some(DeviceOrVariationIndex::PendingVariationIndex(
+                    PendingVariationIndex::new(temp_id),
+                ))
+            }
+            DeviceOrDeltas::None => None,
+        }
+    }
+
+    /// Build a batch of items, reserving space in the store for all the
+    /// deltas up front.
+    ///
+    /// The results are the same as calling [`build`](Self::build) on each
+    /// item in order.
+    pub fn build_all(
+        items: Vec<DeviceOrDeltas>,
+        var_store: &mut VariationStoreBuilder,
+    ) -> Vec<Option<DeviceOrVariationIndex>> {
+        var_store.reserve(items.iter().filter(|item| item.has_deltas()).count());
+        items
+            .into_iter()
+            .map(|item| item.build(var_store))
+            .collect()
+    }
+}
+
+impl CaretValueBuilder {
+    /// Build the final [`CaretValue`] table.
+    pub fn build(self, var_store: &mut VariationStoreBuilder) -> CaretValue {
+        match self {
+            Self::Coordinate { default, deltas } => match deltas.build(var_store) {
+                Some(deltas) => CaretValue::format_3(default, deltas),
+                None => CaretValue::format_1(default),
+            },
+            Self::PointIndex(index) => CaretValue::format_2(index),
+        }
+    }
+
+    /// Check that each region in this caret's deltas has `axis_count` axes.
+    ///
+    /// A region with the wrong number of axes produces a corrupt variation
+    /// store. Device tables, and deltas that identify their regions by index,
+    /// are not checked.
+    pub fn validate_regions(&self, axis_count: usize) -> Result<(), CaretError> {
+        let Self::Coordinate {
+            deltas: DeviceOrDeltas::Deltas(deltas),
+            ..
+        } = self
+        else {
+            return Ok(());
+        };
+        match deltas
+            .iter()
+            .map(|(region, _)| region.region_axes.len())
+            .find(|len| *len != axis_count)
+        {
+            Some(found) => Err(CaretError {
+                expected: axis_count,
+                found,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for CaretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "caret region has {} axes, but the font has {}",
+            self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for CaretError {}
+
+impl LigCaretListBuilder {
+    /// Set the carets for a ligature glyph.
+    ///
+    /// The carets should be in increasing coordinate order. If the glyph
+    /// already has carets, they are replaced.
+    pub fn insert(&mut self, glyph: GlyphId16, carets: Vec<CaretValueBuilder>) {
+        self.ligatures.insert(glyph, carets);
+    }
+
+    /// Returns `true` if no ligatures have been added.
+    pub fn is_empty(&self) -> bool {
+        self.ligatures.is_empty()
+    }
+
+    /// Build the final [`LigCaretList`], adding any deltas to the varstore.
+    pub fn build(self, var_store: &mut VariationStoreBuilder) -> LigCaretList {
+        let coverage = self.ligatures.keys().copied().collect();
+        let lig_glyphs = self
+            .ligatures
+            .into_values()
+            .map(|carets| {
+                LigGlyph::new(
+                    carets
+                        .into_iter()
+                        .map(|caret| caret.build(var_store))
+                        .collect(),
+                )
+            })
+            .collect();
+        LigCaretList::new(coverage, lig_glyphs)
+    }
+}
+
+impl GlyphClassDefBuilder {
+    /// Assign this glyph to the base glyph class (1).
+    ///
+    /// Returns an error if the glyph is already assigned to a different class.
+    pub fn add_base(&mut self, glyph: GlyphId16) -> Result<(), LayoutBuildError> {
+        self.add(glyph, GlyphClassDef::Base)
+    }
+
+    /// Assign this glyph to the ligature glyph class (2).
+    ///
+    /// Returns an error if the glyph is already assigned to a different class.
+    pub fn add_ligature(&mut self, glyph: GlyphId16) -> Result<(), LayoutBuildError> {
+        self.add(glyph, GlyphClassDef::Ligature)
+    }
+
+    /// Assign this glyph to the mark glyph class (3).
+    ///
+    /// Returns an error if the glyph is already assigned to a different class.
+    pub fn add_mark(&mut self, glyph: GlyphId16) -> Result<(), LayoutBuildError> {
+        self.add(glyph, GlyphClassDef::Mark)
+    }
+
+    /// Assign this glyph to the component glyph class (4).
+    ///
+    /// Returns an error if the glyph is already assigned to a different class.
+    pub fn add_component(&mut self, glyph: GlyphId16) -> Result<(), LayoutBuildError> {
+        self.add(glyph, GlyphClassDef::Component)
+    }
+
+    fn add(&mut self, glyph: GlyphId16, class: GlyphClassDef) -> Result<(), LayoutBuildError> {
+        match self.classes.get(&glyph) {
+            Some(existing) if *existing != class => {
+                Err(LayoutBuildError::ConflictingAssignment { glyph })
+            }
+            _ => {
+                self.classes.insert(glyph, class);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns `true` if no glyphs have been added.
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    /// Build the final [`ClassDef`].
+    pub fn build(self) -> ClassDef {
+        self.classes
+            .into_iter()
+            .map(|(glyph, class)| (glyph, class as u16))
+            .collect()
+    }
+}
+
+impl MarkGlyphSetsBuilder {
+    /// Add a set of mark glyphs, returning its id.
+    ///
+    /// If an identical set was already added, its id is returned.
+    ///
+    /// # Panics
+    ///
+    /// This panics if there are more sets than can be identified by a `u16`.
+    pub fn add(&mut self, glyphs: IntSet<GlyphId16>) -> FilterSetId {
+        if let Some(id) = self.ids.get(&glyphs) {
+            return *id;
+        }
+        let id = FilterSetId::try_from(self.sets.len()).expect("too many mark glyph sets");
+        self.ids.insert(glyphs.clone(), id);
+        self.sets.push(glyphs);
+        id
+    }
+
+    /// Add each of the given sets, returning the id assigned to each.
+    pub fn add_all(
+        &mut self,
+        sets: impl IntoIterator<Item = IntSet<GlyphId16>>,
+    ) -> Vec<FilterSetId> {
+        sets.into_iter().map(|set| self.add(set)).collect()
+    }
+
+    /// Returns the id of this set, if it has been added.
+    pub fn get(&self, glyphs: &IntSet<GlyphId16>) -> Option<FilterSetId> {
+        self.ids.get(glyphs).copied()
+    }
+
+    /// The number of distinct sets in the builder.
+    pub fn len(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Returns `true` if no sets have been added.
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// Build the final [`MarkGlyphSets`], with one coverage table per set.
+    pub fn build(self) -> MarkGlyphSets {
+        MarkGlyphSets::new(
+            self.sets
+                .into_iter()
+                .map(|set| set.iter().collect())
+                .collect(),
+        )
+    }
+}
+
+impl FromIterator<(GlyphId16, Vec<CaretValueBuilder>)> for LigCaretListBuilder {
+    fn from_iter<T: IntoIterator<Item = (GlyphId16, Vec<CaretValueBuilder>)>>(iter: T) -> Self {
+        Self {
+            ligatures: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl From<i16> for Metric {
+    fn from(src: i16) -> Metric {
+        Metric {
+            default: src,
+            device_or_deltas: DeviceOrDeltas::None,
+        }
+    }
+}
+
+/// A default value and deltas; as when converting the deltas alone, an empty
+/// list produces [`DeviceOrDeltas::None`].
+impl From<(i16, Vec<(VariationRegion, i16)>)> for Metric {
+    fn from((default, deltas): (i16, Vec<(VariationRegion, i16)>)) -> Metric {
+        Metric {
+            default,
+            device_or_deltas: deltas.into(),
+        }
+    }
+}
+
+impl DeltaSource {
+    /// Convert these deltas into region-keyed deltas, ready for interning.
+    ///
+    /// For instance-keyed deltas this computes a region for each location,
+    /// along with the delta for that region that produces the requested
+    /// value at each instance once the contributions of overlapping regions
+    /// are taken into account.
+    pub fn lower(self, axis_count: u16) -> DeviceOrDeltas {
+        match self {
+            DeltaSource::Regions(deltas) => deltas.into(),
+            DeltaSource::Instance(deltas) => {
+                let model =
+                    VariationModel::new(axis_count, deltas.iter().map(|(loc, _)| loc.clone()));
+                model.deltas(&deltas).into()
+            }
+        }
+    }
+}
+
+impl From<Option<Device>> for DeviceOrDeltas {
+    fn from(src: Option<Device>) -> DeviceOrDeltas {
+        src.map(DeviceOrDeltas::Device).unwrap_or_default()
+    }
+}
+
+impl From<Device> for DeviceOrDeltas {
+    fn from(value: Device) -> Self {
+        DeviceOrDeltas::Device(value)
+    }
+}
+
+impl From<Vec<(VariationRegion, i16)>> for DeviceOrDeltas {
+    fn from(src: Vec<(VariationRegion, i16)>) -> DeviceOrDeltas {
+        if src.is_empty() {
+            DeviceOrDeltas::None
+        } else {
+            DeviceOrDeltas::Deltas(src)
+        }
+    }
+}
+
+impl FromIterator<(GlyphId16, u16)> for ClassDefBuilderImpl {
+    fn from_iter<T: IntoIterator<Item = (GlyphId16, u16)>>(iter: T) -> Self {
+        Self {
+            items: iter.into_iter().filter(|(_, cls)| *cls != 0).collect(),
+        }
+    }
+}
+
+/// Create a lookup from a sequence of subtables.
+///
+/// The lookup has default (empty) flags and no mark filtering set; these
+/// should be set on the result if other values are needed.
+impl<T> FromIterator<T> for LookupBuilder<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        LookupBuilder {
+            flags: LookupFlag::default(),
+            mark_set: None,
+            subtables: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl PartialEq for CoverageTableBuilder {
+    fn eq(&self, other: &Self) -> bool {
+        self.glyphs == other.glyphs
+    }
+}
+
+impl Eq for CoverageTableBuilder {}
+
+impl FromIterator<GlyphId16> for CoverageTableBuilder {
+    fn from_iter<T: IntoIterator<Item = GlyphId16>>(iter: T) -> Self {
+        let glyphs = iter.into_iter().collect::<Vec<_>>();
+        CoverageTableBuilder::from_glyphs(glyphs)
+    }
+}
+
+/// Create a builder containing every glyph in the range.
+impl From<RangeInclusive<GlyphId16>> for CoverageTableBuilder {
+    fn from(src: RangeInclusive<GlyphId16>) -> Self {
+        (src.start().to_u16()..=src.end().to_u16())
+            .map(GlyphId16::new)
+            .collect()
+    }
+}
+
+/// Create a builder containing every glyph in the range.
+impl From<Range<GlyphId16>> for CoverageTableBuilder {
+    fn from(src: Range<GlyphId16>) -> Self {
+        (src.start.to_u16()..src.end.to_u16())
+            .map(GlyphId16::new)
+            .collect()
+    }
+}
+
+/// Create a builder containing all of the glyphs in an existing coverage table.
+///
+/// This allows a parsed table to be modified and then recompiled. The format
+/// of the existing table is kept, so that an unmodified table is recompiled
+/// to the same bytes; use [`CoverageTableBuilder::reoptimize`] to choose the
+/// smallest format instead.
+///
+/// A malformed table may contain duplicate glyphs; these are merged, so the
+/// coverage index of each later glyph changes. Use
+/// [`CoverageTable::has_duplicates`] to detect this case before converting.
+///
+/// [`CoverageTable::has_duplicates`]: read_fonts::tables::layout::CoverageTable::has_duplicates
+impl From<&read_fonts::tables::layout::CoverageTable<'_>> for CoverageTableBuilder {
+    fn from(src: &read_fonts::tables::layout::CoverageTable<'_>) -> Self {
+        let mut builder: CoverageTableBuilder = src.iter().collect();
+        builder.source_format = Some(src.coverage_format());
+        builder
+    }
+}
+
+// returns the combined deltas, or an error if any sum overflows
+fn accumulate_deltas<R: Clone + PartialEq>(
+    deltas: &[(R, i16)],
+    other: &[(R, i16)],
+) -> Result<Vec<(R, i16)>, LayoutBuildError> {
+    let mut deltas = deltas.to_vec();
+    for (region, delta) in other {
+        match deltas.iter_mut().find(|(existing, _)| existing == region) {
+            Some((_, existing)) => {
+                *existing = existing
+                    .checked_add(*delta)
+                    .ok_or(LayoutBuildError::DeltaOverflow)?
+            }
+            None => deltas.push((region.clone(), *delta)),
+        }
+    }
+    Ok(deltas)
+}
+
+fn narrow_glyph_id(glyph: GlyphId) -> Result<GlyphId16, LayoutBuildError> {
+    glyph
+        .try_into()
+        .map_err(|_| LayoutBuildError::GlyphIdTooLarge { glyph })
+}
+
+/// Coalesce sorted `(glyph, class)` pairs into class range records.
+///
+/// Sequential glyphs with the same class are merged into a single record.
+pub(super) fn iter_class_ranges(
+    values: impl IntoIterator<Item = (GlyphId16, u16)>,
+) -> impl Iterator<Item = ClassRangeRecord> {
+    let mut iter = values.into_iter();
+    let mut prev = None;
+
+    #[allow(clippy::while_let_on_iterator)]
+    std::iter::from_fn(move || {
+        while let Some((gid, class)) = iter.next() {
+            match prev.take() {
+                None => prev = Some((gid, gid, class)),
+                Some((start, end, pclass))
+                    if super::are_sequential(end, gid) && pclass == class =>
+                {
+                    prev = Some((start, gid, pclass))
+                }
+                Some((start_glyph_id, end_glyph_id, pclass)) => {
+                    prev = Some((gid, gid, class));
+                    return Some(ClassRangeRecord {
+                        start_glyph_id,
+                        end_glyph_id,
+                        class: pclass,
+                    });
+                }
+            }
+        }
+        prev.take()
+            .map(|(start_glyph_id, end_glyph_id, class)| ClassRangeRecord {
+                start_glyph_id,
+                end_glyph_id,
+                class,
+            })
+    })
+}
+
+// the largest difference between two adjacent glyphs, less one
+fn max_glyph_gap(sorted_glyphs: impl Iterator<Item = GlyphId16>) -> u16 {
+    let mut prev: Option<GlyphId16> = None;
+    let mut max_gap = 0;
+    for gid in sorted_glyphs {
+        if let Some(prev) = prev {
+            max_gap = max_gap.max(gid.to_u16() - prev.to_u16() - 1);
+        }
+        prev = Some(gid);
+    }
+    max_gap
+}
+
+fn should_choose_coverage_format_2(glyphs: &[GlyphId16]) -> bool {
+    coverage_format_2_is_smaller(glyphs.len(), RangeRecord::iter_for_glyphs(glyphs).count())
+}
+
+fn coverage_format_2_is_smaller(glyph_count: usize, range_count: usize) -> bool {
+    let (format1_len, format2_len) = coverage_format_lens(glyph_count, range_count);
+    format2_len < format1_len
+}
+
+/// An upper bound on the size of a coverage table with this many glyphs.
+///
+/// Format 2 is only used when it is smaller, so this is the size of format 1.
+pub(crate) fn max_coverage_size(glyph_count: usize) -> usize {
+    coverage_format_lens(glyph_count, 0).0
+}
+
+/// An upper bound on the size of a classdef with this many glyphs.
+///
+/// Format 1 is only used when it is smaller, so this is the size of format 2
+/// with a range for each glyph.
+pub(crate) fn max_class_def_size(glyph_count: usize) -> usize {
+    u16::RAW_BYTE_LEN * 2 + glyph_count * u16::RAW_BYTE_LEN * 3
+}
+
+// the encoded size of a coverage table in (format 1, format 2)
+fn coverage_format_lens(glyph_count: usize, range_count: usize) -> (usize, usize) {
+    (4 + glyph_count * 2, 4 + range_count * 6)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::RangeInclusive;
+
+    use read_fonts::collections::IntSet;
+
+    use font_types::F2Dot14;
+
+    use crate::tables::{layout::DeltaFormat, variations::RegionAxisCoordinates};
+
+    use super::*;
+
+    #[test]
+    fn classdef_format() {
+        let builder: ClassDefBuilderImpl = [(3u16, 4u16), (4, 6), (5, 1), (9, 5), (10, 2), (11, 3)]
+            .map(|(gid, cls)| (GlyphId16::new(gid), cls))
+            .into_iter()
+            .collect();
+
+        assert!(ClassDefBuilderImpl::prefer_format_1(
+            builder.glyph_span(),
+            builder.iter_ranges().count()
+        ));
+
+        let builder: ClassDefBuilderImpl = [(1u16, 1u16), (3, 4), (9, 5), (10, 2), (11, 3)]
+            .map(|(gid, cls)| (GlyphId16::new(gid), cls))
+            .into_iter()
+            .collect();
+
+        assert!(ClassDefBuilderImpl::prefer_format_1(
+            builder.glyph_span(),
+            builder.iter_ranges().count()
+        ));
+    }
+
+    #[test]
+    fn classdef_prefer_format2() {
+        fn iter_class_items(
+            start: u16,
+            end: u16,
+            cls: u16,
+        ) -> impl Iterator<Item = (GlyphId16, u16)> {
+            (start..=end).map(move |gid| (GlyphId16::new(gid), cls))
+        }
+
+        // 3 ranges of 4 glyphs at 6 bytes a range should be smaller than writing
+        // out the 3 * 4 classes directly
+        let builder: ClassDefBuilderImpl = iter_class_items(5, 8, 3)
+            .chain(iter_class_items(9, 12, 4))
+            .chain(iter_class_items(13, 16, 5))
+            .collect();
+
+        assert!(!ClassDefBuilderImpl::prefer_format_1(
+            builder.glyph_span(),
+            builder.iter_ranges().count()
+        ));
+    }
+
+    #[test]
+    fn classdef_single_class() {
+        let builder: ClassDefBuilderImpl = (100u16..=5000)
+            .map(|gid| (GlyphId16::new(gid), 3))
+            .collect();
+        let ClassDef::Format2(table) = builder.build() else {
+            panic!("expected format 2");
+        };
+        assert_eq!(
+            table.class_range_records,
+            [ClassRangeRecord {
+                start_glyph_id: GlyphId16::new(100),
+                end_glyph_id: GlyphId16::new(5000),
+                class: 3,
+            }]
+        );
+
+        // a gap means more than one range, unless gaps are merged
+        let builder: ClassDefBuilderImpl = (100u16..=5000)
+            .filter(|gid| *gid != 200)
+            .map(|gid| (GlyphId16::new(gid), 3))
+            .collect();
+        let ClassDef::Format2(table) = builder.build() else {
+            panic!("expected format 2");
+        };
+        assert_eq!(table.class_range_records.len(), 2);
+        let ClassDef::Format2(table) = builder.build_with_gap_merging(true) else {
+            panic!("expected format 2");
+        };
+        assert_eq!(table.class_range_records.len(), 1);
+
+        // a glyph with a different class splits the range, with or without
+        // gap merging
+        let builder: ClassDefBuilderImpl = (100u16..=5000)
+            .map(|gid| (GlyphId16::new(gid), if gid == 300 { 4 } else { 3 }))
+            .collect();
+        for merge_class_0_gaps in [false, true] {
+            let ClassDef::Format2(table) = builder.build_with_gap_merging(merge_class_0_gaps)
+            else {
+                panic!("expected format 2");
+            };
+            assert_eq!(
+                table.class_range_records,
+                [(100, 299, 3), (300, 300, 4), (301, 5000, 3)].map(|(start, end, class)| {
+                    ClassRangeRecord {
+                        start_glyph_id: GlyphId16::new(start),
+                        end_glyph_id: GlyphId16::new(end),
+                        class,
+                    }
+                })
+            );
+        }
+
+        // a single glyph is smaller in format 1
+        let builder: ClassDefBuilderImpl = [(GlyphId16::new(7), 1)].into_iter().collect();
+        assert!(matches!(builder.build(), ClassDef::Format1(_)));
+    }
+
+    #[test]
+    fn classdef_merge_class_0_gaps() {
+        let builder: ClassDefBuilderImpl = [
+            (1u16..=10, 1u16),
+            (12..=20, 1),
+            (30..=40, 2),
+            (50..=60, 2),
+            (70..=80, 1),
+        ]
+        .into_iter()
+        .flat_map(|(range, cls)| range.map(move |gid| (GlyphId16::new(gid), cls)))
+        .collect();
+
+        let ranges = |class_def: ClassDef| match class_def {
+            ClassDef::Format2(table) => table
+                .class_range_records
+                .iter()
+                .map(|rec| {
+                    (
+                        rec.start_glyph_id.to_u16(),
+                        rec.end_glyph_id.to_u16(),
+                        rec.class,
+                    )
+                })
+                .collect::<Vec<_>>(),
+            ClassDef::Format1(_) => panic!("expected format 2"),
+        };
+
+        assert_eq!(ranges(builder.build()).len(), 5);
+        assert_eq!(
+            ranges(builder.build_with_gap_merging(true)),
+            [(1, 20, 1), (30, 60, 2), (70, 80, 1)]
+        );
+
+        // the same, through the public builder
+        let builder = || {
+            let mut builder = ClassDefBuilder::new();
+            assert!(builder.checked_add(
+                (1u16..=10)
+                    .chain(12..=20)
+                    .chain(70..=80)
+                    .map(GlyphId16::new)
+                    .collect(),
+            ));
+            assert!(builder.checked_add((30u16..=40).chain(50..=60).map(GlyphId16::new).collect()));
+            builder
+        };
+        assert_eq!(ranges(builder().build()).len(), 5);
+        let merged = builder().with_class_0_gap_merging(true);
+        assert_eq!(
+            ranges(merged.build()),
+            [(1, 20, 1), (30, 60, 2), (70, 80, 1)]
+        );
+        let (class_def, _) = builder()
+            .with_class_0_gap_merging(true)
+            .build_with_glyph_mapping();
+        assert_eq!(ranges(class_def).len(), 3);
+    }
+
+    #[test]
+    fn delta_format_dflt() {
+        let some: DeltaFormat = Default::default();
+        assert_eq!(some, DeltaFormat::Local2BitDeltas);
+    }
+
+    fn make_region(peak: f32) -> VariationRegion {
+        VariationRegion::new(vec![RegionAxisCoordinates {
+            start_coord: F2Dot14::from_f32(peak.min(0.0)),
+            peak_coord: F2Dot14::from_f32(peak),
+            end_coord: F2Dot14::from_f32(peak.max(0.0)),
+        }])
+    }
+
+    #[test]
+    fn indexed_deltas_match_region_deltas() {
+        let regions = [make_region(-1.0), make_region(0.5), make_region(1.0)];
+        // regions are ordered by when they are first seen, so we use them in order
+        let deltas = [[(0, 10), (1, 20)], [(1, -5), (2, 7)], [(0, 10), (1, 20)]];
+
+        let mut by_region = VariationStoreBuilder::new(1);
+        for set in deltas {
+            let set = set
+                .iter()
+                .map(|(idx, delta)| (regions[*idx].clone(), *delta))
+                .collect::<Vec<_>>();
+            DeviceOrDeltas::from(set).build(&mut by_region);
+        }
+
+        let mut by_index = VariationStoreBuilder::new(1);
+        let indices = regions
+            .iter()
+            .map(|reg| by_index.add_region(reg.clone()))
+            .collect::<Vec<_>>();
+        for set in deltas {
+            let set = set
+                .iter()
+                .map(|(idx, delta)| (indices[*idx], *delta))
+                .collect::<Vec<_>>();
+            DeviceOrDeltas::from_region_indices(set).build(&mut by_index);
+        }
+
+        let (by_region, _) = by_region.build();
+        let (by_index, _) = by_index.build();
+        assert_eq!(by_region, by_index);
+    }
+
+    fn make_glyph_vec<const N: usize>(gids: [u16; N]) -> Vec<GlyphId16> {
+        gids.into_iter().map(GlyphId16::new).collect()
+    }
+
+    #[test]
+    fn coverage_builder() {
+        let coverage = make_glyph_vec([1u16, 2, 9, 3, 6, 9])
+            .into_iter()
+            .collect::<CoverageTableBuilder>();
+        assert_eq!(coverage.glyphs, make_glyph_vec([1, 2, 3, 6, 9]));
+    }
+
+    #[test]
+    fn coverage_from_range() {
+        let coverage = CoverageTableBuilder::from(GlyphId16::new(3)..=GlyphId16::new(20));
+        assert_eq!(
+            coverage.glyphs,
+            (3..=20).map(GlyphId16::new).collect::<Vec<_>>()
+        );
+        let CoverageTable::Format2(table) = coverage.build() else {
+            panic!("expected format 2");
+        };
+        assert_eq!(table.range_records.len(), 1);
+
+        let coverage = CoverageTableBuilder::from(GlyphId16::new(3)..GlyphId16::new(6));
+        assert_eq!(coverage.glyphs, make_glyph_vec([3, 4, 5]));
+        let empty = CoverageTableBuilder::from(GlyphId16::new(3)..GlyphId16::new(3));
+        assert!(empty.glyphs.is_empty());
+    }
+
+    #[test]
+    fn coverage_append_only() {
+        let mut coverage = CoverageTableBuilder::new_append_only();
+        assert_eq!(coverage.try_add(GlyphId16::new(4)), Ok(0));
+        assert_eq!(coverage.try_add(GlyphId16::new(9)), Ok(1));
+        // re-adding an existing glyph is fine
+        assert_eq!(coverage.try_add(GlyphId16::new(4)), Ok(0));
+        assert_eq!(
+            coverage.try_add(GlyphId16::new(6)),
+            Err(LayoutBuildError::GlyphOutOfOrder {
+                glyph: GlyphId16::new(6)
+            })
+        );
+        assert_eq!(coverage.try_add(GlyphId16::new(12)), Ok(2));
+        assert_eq!(coverage.glyphs, make_glyph_vec([4, 9, 12]));
+
+        // only the glyphs are compared
+        let unrestricted = [4, 9, 12].map(GlyphId16::new).into_iter().collect();
+        assert_eq!(coverage, unrestricted);
+    }
+
+    #[test]
+    fn coverage_index_of() {
+        let mut coverage = make_glyph_vec([1u16, 4, 9, 12])
+            .into_iter()
+            .collect::<CoverageTableBuilder>();
+        assert_eq!(coverage.index_of(GlyphId16::new(5)), None);
+        let index = coverage.index_of(GlyphId16::new(9));
+        assert_eq!(index, Some(2));
+        assert_eq!(index, Some(coverage.add(GlyphId16::new(9))));
+        assert_eq!(coverage.glyphs.len(), 4);
+    }
+
+    #[test]
+    fn coverage_format_1_overflow() {
+        let all_glyphs = || {
+            (0..=u16::MAX)
+                .map(GlyphId16::new)
+                .collect::<CoverageTableBuilder>()
+        };
+        let mut coverage = all_glyphs();
+        assert_eq!(coverage.add(GlyphId16::new(u16::MAX)), u16::MAX);
+        coverage.source_format = Some(1);
+        assert_eq!(
+            coverage.try_build(),
+            Err(LayoutBuildError::CoverageOverflow { glyph_count: 65536 })
+        );
+        assert!(matches!(
+            all_glyphs().try_build(),
+            Ok(CoverageTable::Format2(_))
+        ));
+    }
+
+    fn make_class<const N: usize>(gid_class_pairs: [(u16, u16); N]) -> ClassDef {
+        gid_class_pairs
+            .iter()
+            .map(|(gid, cls)| (GlyphId16::new(*gid), *cls))
+            .collect::<ClassDefBuilderImpl>()
+            .build()
+    }
+
+    #[test]
+    fn class_def_builder_zero() {
+        // even if class 0 is provided, we don't need to assign explicit entries for it
+        let class = make_class([(4, 0), (5, 1)]);
+        assert!(class.get_raw(GlyphId16::new(4)).is_none());
+        assert_eq!(class.get_raw(GlyphId16::new(5)), Some(1));
+        assert!(class.get_raw(GlyphId16::new(100)).is_none());
+    }
+
+    #[test]
+    fn classdef_pair() {
+        fn make_pair(order: &[usize]) -> (ClassDefWithMapping, ClassDefWithMapping) {
+            let classes = [
+                vec![1u16, 2, 3],
+                vec![4, 5],
+                vec![6],
+                vec![10, 11, 12, 13],
+                vec![20, 21],
+            ]
+            .map(|gids| gids.into_iter().map(GlyphId16::new).collect::<IntSet<_>>());
+            let mut pair = ClassDefBuilderPair::default();
+            for &i in order {
+                // 0, 1, 2 are shared; 3 is only first; 4 is only second
+                if i != 4 {
+                    pair.first.checked_add(classes[i].clone());
+                }
+                if i != 3 {
+                    pair.second.checked_add(classes[i].clone());
+                }
+            }
+            pair.build_with_mapping()
+        }
+
+        let ((first, first_map), (second, second_map)) = make_pair(&[0, 1, 2, 3, 4]);
+        // shared classes come first, and have the same ids in both tables
+        for gid in [1, 4, 6].map(GlyphId16::new) {
+            assert_eq!(first.get_raw(gid), second.get_raw(gid));
+        }
+        assert_eq!(first.get_raw(GlyphId16::new(1)), Some(1));
+        assert_eq!(first.get_raw(GlyphId16::new(6)), Some(3));
+        // the larger class unique to first is still placed after the shared ones
+        assert_eq!(first.get_raw(GlyphId16::new(10)), Some(4));
+        assert_eq!(second.get_raw(GlyphId16::new(20)), Some(4));
+
+        let ((first2, first_map2), (second2, second_map2)) = make_pair(&[4, 2, 3, 0, 1]);
+        assert_eq!(first, first2);
+        assert_eq!(second, second2);
+        assert_eq!(first_map, first_map2);
+        assert_eq!(second_map, second_map2);
+    }
+
+    #[test]
+    fn classdef_round_trip() {
+        use read_fonts::FontRead;
+
+        let original = ClassDef::from(
+            [(3, 1), (4, 1), (5, 0), (6, 2), (7, 2), (8, 1)]
+                .map(|(gid, cls)| (GlyphId16::new(gid), cls)),
+        );
+        assert!(matches!(original, ClassDef::Format1(_)));
+        let bytes = crate::dump_table(&original).unwrap();
+        let parsed = read_fonts::tables::layout::ClassDef::read(bytes.as_slice().into()).unwrap();
+
+        let mut builder = ClassDefBuilder::from_class_def(&parsed, false);
+        let gids = |ids: &[u16]| {
+            ids.iter()
+                .copied()
+                .map(GlyphId16::new)
+                .collect::<IntSet<_>>()
+        };
+        assert_eq!(builder.classes, [gids(&[3, 4, 8]), gids(&[6, 7])].into());
+        assert!(!builder.all_glyphs.contains(GlyphId16::new(5)));
+
+        // add glyph 9 to the class with glyph 6
+        let mut class = builder.class_containing(GlyphId16::new(6)).unwrap().clone();
+        assert_eq!(class, gids(&[6, 7]));
+        assert!(builder.remove_class(&class));
+        assert!(!builder.remove_class(&class));
+        assert_eq!(builder.class_containing(GlyphId16::new(6)), None);
+        class.insert(GlyphId16::new(9));
+        builder.try_add(class).unwrap();
+
+        let rebuilt = builder.build();
+        assert_eq!(
+            rebuilt
+                .iter()
+                .filter(|(_, cls)| *cls != 0)
+                .collect::<Vec<_>>(),
+            [(3, 1), (4, 1), (6, 2), (7, 2), (8, 1), (9, 2)]
+                .map(|(gid, cls)| (GlyphId16::new(gid), cls))
+        );
+
+        let builder = ClassDefBuilder::from_class_def(&parsed, true);
+        assert!(builder.classes.contains(&gids(&[5])));
+    }
+
+    #[test]
+    fn classdef_max_gap() {
+        let mut builder = ClassDefBuilder::new();
+        builder.checked_add([1, 2, 3].map(GlyphId16::new).into_iter().collect());
+        assert_eq!(builder.max_gap(), 0);
+        builder.checked_add([6, 500].map(GlyphId16::new).into_iter().collect());
+        assert_eq!(builder.max_gap(), 493);
+        builder.checked_add([30_000].map(GlyphId16::new).into_iter().collect());
+        assert_eq!(builder.max_gap(), 29_499);
+
+        assert_eq!(ClassDefBuilder::new().max_gap(), 0);
+    }
+
+    #[test]
+    fn classdef_implicit_class_0() {
+        let classes = [make_glyph_class([1, 2, 3]), make_glyph_class([4])];
+
+        let mut builder = ClassDefBuilder::new();
+        classes
+            .iter()
+            .for_each(|cls| assert!(builder.checked_add(cls.clone())));
+        let built = builder.try_build_with_class_0().unwrap();
+        assert!(built.implicit_class_0);
+        assert_eq!(built.mapping.get(&classes[0]), Some(&1));
+        assert_eq!(built.mapping.get(&classes[1]), Some(&2));
+
+        let mut builder = ClassDefBuilder::new_using_class_0();
+        classes
+            .iter()
+            .for_each(|cls| assert!(builder.checked_add(cls.clone())));
+        let built = builder.try_build_with_class_0().unwrap();
+        assert!(!built.implicit_class_0);
+        assert_eq!(built.mapping.get(&classes[0]), Some(&0));
+        assert_eq!(built.mapping.get(&classes[1]), Some(&1));
+
+        // with no classes, class 0 is implicit even if it could be used
+        let built = ClassDefBuilder::new_using_class_0()
+            .try_build_with_class_0()
+            .unwrap();
+        assert!(built.implicit_class_0);
+    }
+
+    #[test]
+    fn classdef_catchall() {
+        let mut builder = ClassDefBuilder::new();
+        builder.checked_add(make_glyph_class([3]));
+        builder.checked_add(make_glyph_class([5]));
+        let all_glyphs = (1..=6).map(GlyphId16::new).collect::<IntSet<_>>();
+        let (class_def, mapping) = builder.clone().with_catchall_class(&all_glyphs, 3).unwrap();
+        assert_eq!(mapping.get(&make_glyph_class([1, 2, 4, 6])), Some(&3));
+        assert_eq!(
+            class_def.iter().collect::<BTreeMap<_, _>>(),
+            [(1, 3), (2, 3), (3, 1), (4, 3), (5, 2), (6, 3)]
+                .map(|(gid, cls)| (GlyphId16::new(gid), cls))
+                .into()
+        );
+        assert_eq!(
+            builder.with_catchall_class(&all_glyphs, 2),
+            Err(LayoutBuildError::ConflictingClassId { class_id: 2 })
+        );
+    }
+
+    #[test]
+    fn classdef_class_count() {
+        let mut builder = ClassDefBuilder::new();
+        assert_eq!(builder.class_count(), 0);
+        assert!(builder.checked_add(make_glyph_class([1, 2, 3])));
+        assert!(builder.checked_add(make_glyph_class([1, 2, 3])));
+        assert_eq!(builder.class_count(), 1);
+        assert!(builder.checked_add(make_glyph_class([4])));
+        assert_eq!(builder.class_count(), 2);
+        assert_eq!(builder.build_with_mapping().1.len(), 2);
+    }
+
+    #[test]
+    fn classdef_sort_strategy() {
+        let classes = [
+            make_glyph_class([20, 21]),
+            make_glyph_class([5]),
+            make_glyph_class([10, 11, 12]),
+            make_glyph_class([1, 2]),
+        ];
+        let ids = |strategy| {
+            let mut builder = ClassDefBuilder::new().with_sort_strategy(strategy);
+            for cls in &classes {
+                assert!(builder.checked_add(cls.clone()));
+            }
+            let (_, mapping) = builder.build_with_mapping();
+            classes.iter().map(|cls| mapping[cls]).collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(SortStrategy::LargestFirst), [3, 4, 1, 2]);
+        assert_eq!(ids(SortStrategy::SmallestFirst), [3, 1, 4, 2]);
+        assert_eq!(ids(SortStrategy::ByFirstGlyph), [4, 2, 3, 1]);
+        assert_eq!(
+            ids(SortStrategy::default()),
+            ids(SortStrategy::LargestFirst)
+        );
+    }
+
+    #[test]
+    fn classdef_density() {
+        let mut dense = ClassDefBuilder::new();
+        dense.checked_add(make_glyph_class([1, 2, 3]));
+        dense.checked_add(make_glyph_class([4, 6]));
+        assert_eq!(dense.density(), 5.0 / 6.0);
+
+        let mut sparse = ClassDefBuilder::new();
+        sparse.checked_add(make_glyph_class([10, 11]));
+        sparse.checked_add(make_glyph_class([108]));
+        assert_eq!(sparse.density(), 3.0 / 99.0);
+
+        assert_eq!(ClassDefBuilder::new().density(), 0.0);
+
+        // the span of the whole glyph id range does not fit in a u16
+        let mut full_range = ClassDefBuilder::new();
+        full_range.checked_add(make_glyph_class([0, u16::MAX]));
+        assert_eq!(full_range.density(), 2.0 / 65536.0);
+    }
+
+    // https://github.com/googlefonts/fontations/issues/923
+    // an empty classdef should always be format 2
+    #[test]
+    fn class_def_builder_empty() {
+        let builder = ClassDefBuilderImpl::from_iter([]);
+        let built = builder.build();
+
+        assert_eq!(
+            built,
+            ClassDef::Format2(ClassDefFormat2 {
+                class_range_records: vec![]
+            })
+        )
+    }
+
+    #[test]
+    fn lig_caret_list() {
+        let coordinate = |default| CaretValueBuilder::Coordinate {
+            default,
+            deltas: DeviceOrDeltas::None,
+        };
+        let builder: LigCaretListBuilder = [
+            (
+                GlyphId16::new(20),
+                vec![
+                    coordinate(100),
+                    CaretValueBuilder::Coordinate {
+                        default: 200,
+                        deltas: vec![(make_region(1.0), 10)].into(),
+                    },
+                    CaretValueBuilder::PointIndex(4),
+                ],
+            ),
+            (GlyphId16::new(7), vec![coordinate(300)]),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut var_store = VariationStoreBuilder::new(1);
+        let list = builder.build(&mut var_store);
+        assert_eq!(
+            list.coverage.iter().collect::<Vec<_>>(),
+            [GlyphId16::new(7), GlyphId16::new(20)]
+        );
+        let caret_counts = list
+            .lig_glyphs
+            .iter()
+            .map(|lig| lig.caret_values.len())
+            .collect::<Vec<_>>();
+        assert_eq!(caret_counts, [1, 3]);
+        assert_eq!(
+            *list.lig_glyphs[0].caret_values[0],
+            CaretValue::format_1(300)
+        );
+        assert!(matches!(
+            *list.lig_glyphs[1].caret_values[1],
+            CaretValue::Format3(_)
+        ));
+        assert_eq!(*list.lig_glyphs[1].caret_values[2], CaretValue::format_2(4));
+    }
+
+    #[test]
+    fn caret_validate_regions() {
+        let two_axes = VariationRegion::new(vec![make_region(1.0).region_axes[0].clone(); 2]);
+        let caret = CaretValueBuilder::Coordinate {
+            default: 200,
+            deltas: vec![(make_region(1.0), 10), (two_axes, 5)].into(),
+        };
+        assert_eq!(
+            caret.validate_regions(1),
+            Err(CaretError {
+                expected: 1,
+                found: 2
+            })
+        );
+        assert_eq!(
+            caret.validate_regions(2),
+            Err(CaretError {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert!(CaretValueBuilder::PointIndex(4).validate_regions(1).is_ok());
+
+        let caret = CaretValueBuilder::Coordinate {
+            default: 200,
+            deltas: vec![(make_region(1.0), 10)].into(),
+        };
+        assert!(caret.validate_regions(1).is_ok());
+    }
+
+    #[test]
+    fn glyph_class_def() {
+        let mut builder = GlyphClassDefBuilder::default();
+        builder.add_base(GlyphId16::new(1)).unwrap();
+        builder.add_ligature(GlyphId16::new(2)).unwrap();
+        builder.add_mark(GlyphId16::new(3)).unwrap();
+        builder.add_component(GlyphId16::new(4)).unwrap();
+        // adding a glyph to the same class again is fine
+        builder.add_mark(GlyphId16::new(3)).unwrap();
+        assert_eq!(
+            builder.add_base(GlyphId16::new(3)),
+            Err(LayoutBuildError::ConflictingAssignment {
+                glyph: GlyphId16::new(3)
+            })
+        );
+
+        let class_def = builder.build();
+        assert_eq!(class_def.get(GlyphId16::new(1)), 1);
+        assert_eq!(class_def.get(GlyphId16::new(2)), 2);
+        assert_eq!(class_def.get(GlyphId16::new(3)), 3);
+        assert_eq!(class_def.get(GlyphId16::new(4)), 4);
+        assert_eq!(class_def.get(GlyphId16::new(5)), 0);
+    }
+
+    #[test]
+    fn mark_glyph_sets() {
+        let mut builder = MarkGlyphSetsBuilder::default();
+        let ids = builder.add_all([
+            make_glyph_class([10, 11]),
+            make_glyph_class([12]),
+            make_glyph_class([11, 10]),
+        ]);
+        assert_eq!(ids, [0, 1, 0]);
+        assert_eq!(builder.len(), 2);
+        assert_eq!(builder.get(&make_glyph_class([12])), Some(1));
+        assert_eq!(builder.add(make_glyph_class([10, 11, 12])), 2);
+
+        let table = builder.build();
+        let coverages = table
+            .coverages
+            .iter()
+            .map(|coverage| coverage.iter().map(|gid| gid.to_u16()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(coverages, [vec![10, 11], vec![12], vec![10, 11, 12]]);
+    }
+
+    #[test]
+    fn device_from_ppem_deltas() {
+        let DeviceOrDeltas::Device(small) = DeviceOrDeltas::from_ppem_deltas(12, &[1, -2, 0])
+        else {
+            panic!("expected a device table");
+        };
+        assert_eq!((small.start_size, small.end_size), (12, 14));
+        assert_eq!(small.delta_format, DeltaFormat::Local2BitDeltas);
+        assert_eq!(small, Device::new(12, 14, &[1, -2, 0]));
+
+        let DeviceOrDeltas::Device(large) = DeviceOrDeltas::from_ppem_deltas(9, &[1, -100]) else {
+            panic!("expected a device table");
+        };
+        assert_eq!((large.start_size, large.end_size), (9, 10));
+        assert_eq!(large.delta_format, DeltaFormat::Local8BitDeltas);
+        assert_eq!(large.delta_value, [0x019c]);
+
+        assert!(DeviceOrDeltas::from_ppem_deltas(9, &[]).is_none());
+    }
+
+    #[test]
+    fn deltas_from_compiled() {
+        use crate::tables::variations::ivs_builder::RemapVariationIndices;
+        use read_fonts::{tables::variations::ItemVariationStore, FontRead};
+
+        let sources: Vec<DeviceOrDeltas> = vec![
+            vec![(make_region(1.0), 10), (make_region(-1.0), -5)].into(),
+            vec![(make_region(1.0), 3)].into(),
+            DeviceOrDeltas::Device(Device::new(8, 9, &[1, 2])),
+        ];
+        let mut var_store = VariationStoreBuilder::new(1);
+        let mut compiled = sources
+            .iter()
+            .map(|item| item.clone().build(&mut var_store).unwrap())
+            .collect::<Vec<_>>();
+        let pending = compiled[0].clone();
+        let (ivs, key_map) = var_store.build();
+        compiled
+            .iter_mut()
+            .for_each(|item| item.remap_variation_indices(&key_map));
+        let ivs_bytes = crate::dump_table(&ivs).unwrap();
+        let ivs = ItemVariationStore::read(FontData::new(&ivs_bytes)).unwrap();
+
+        for (source, compiled) in sources.iter().zip(&compiled) {
+            let recovered = DeviceOrDeltas::from_compiled(compiled, &ivs).unwrap();
+            assert_eq!(recovered.normalized(), source.normalized());
+        }
+        assert!(DeviceOrDeltas::from_compiled(&pending, &ivs).is_err());
+    }
+
+    #[test]
+    fn optimize_deltas() {
+        let all_zeros: DeviceOrDeltas = vec![(make_region(-1.0), 0), (make_region(1.0), 0)].into();
+        assert!(all_zeros.has_deltas());
+        assert_eq!(all_zeros.optimized(), DeviceOrDeltas::None);
+
+        let some_zeros: DeviceOrDeltas = vec![(make_region(-1.0), 0), (make_region(1.0), 3)].into();
+        assert_eq!(
+            some_zeros.optimized(),
+            DeviceOrDeltas::Deltas(vec![(make_region(1.0), 3)])
+        );
+
+        let device = DeviceOrDeltas::Device(Device::new(10, 11, &[0, 0]));
+        assert_eq!(device.clone().optimized(), device);
+    }
+
+    #[test]
+    fn lower_instance_deltas() {
+        let location = InstanceLocation::new([F2Dot14::from_f32(0.5), F2Dot14::from_f32(1.0)]);
+        let lowered = DeltaSource::Instance(vec![(location, 30)]).lower(2);
+        let region = VariationRegion::new(vec![
+            RegionAxisCoordinates::new(
+                F2Dot14::ZERO,
+                F2Dot14::from_f32(0.5),
+                F2Dot14::from_f32(0.5),
+            ),
+            RegionAxisCoordinates::new(F2Dot14::ZERO, F2Dot14::ONE, F2Dot14::ONE),
+        ]);
+        assert_eq!(lowered, DeviceOrDeltas::Deltas(vec![(region, 30)]));
+
+        let regions = DeltaSource::Regions(vec![(make_region(1.0), 4)]).lower(1);
+        assert_eq!(regions, DeviceOrDeltas::Deltas(vec![(make_region(1.0), 4)]));
+    }
+
+    #[test]
+    fn lookup_try_build_propagates_errors() {
+        use crate::tables::gpos::builders::{PairPosBuilder, ValueRecordBuilder};
+
+        // every glyph in its own second class, which needs class ids up to 65536
+        let mut pairpos = PairPosBuilder::default();
+        let class1 = [GlyphId16::new(1)].into_iter().collect::<IntSet<_>>();
+        for gid in 0..=u16::MAX {
+            pairpos.insert_classes(
+                class1.clone(),
+                ValueRecordBuilder::new().with_x_advance(-10),
+                [GlyphId16::new(gid)].into_iter().collect(),
+                ValueRecordBuilder::new(),
+            );
+        }
+        let lookup = LookupBuilder::new_with_lookups(LookupFlag::empty(), None, vec![pairpos]);
+        assert_eq!(
+            lookup
+                .try_build(&mut VariationStoreBuilder::new(0))
+                .unwrap_err(),
+            LayoutBuildError::ClassDefOverflow {
+                class_count: u16::MAX as usize + 2
+            }
+        );
+    }
+
+    // lets us write a coverage builder's bytes as a standalone table
+    struct Streamed(CoverageTableBuilder);
+
+    impl FontWrite for Streamed {
+        fn write_into(&self, writer: &mut TableWriter) {
+            self.0.write_to(writer)
+        }
+    }
+
+    impl crate::validate::Validate for Streamed {
+        fn validate_impl(&self, _ctx: &mut crate::validate::ValidationCtx) {}
+    }
+
+    #[test]
+    fn coverage_write_to() {
+        let sparse = [1u16, 5, 9, 40, 41];
+        let dense = [1u16, 2, 3, 4, 5, 6, 7, 8, 20, 21, 22, 23];
+        for glyphs in [&sparse[..], &dense[..], &[]] {
+            let builder = || {
+                glyphs
+                    .iter()
+                    .copied()
+                    .map(GlyphId16::new)
+                    .collect::<CoverageTableBuilder>()
+            };
+            let streamed = crate::dump_table(&Streamed(builder())).unwrap();
+            let built = crate::dump_table(&builder().build()).unwrap();
+            assert_eq!(streamed, built, "{glyphs:?}");
+        }
+    }
+
+    #[test]
+    fn mutate_subtables() {
+        let mut lookup = LookupBuilder::new_with_lookups(LookupFlag::empty(), None, vec![1u16, 2]);
+        lookup.force_subtable_break();
+        for subtable in lookup.iter_subtables_mut() {
+            *subtable += 10;
+        }
+        assert_eq!(
+            lookup.iter_subtables().copied().collect::<Vec<_>>(),
+            [11, 12, 10]
+        );
+    }
+
+    #[test]
+    fn lookup_from_iter() {
+        let lookup = (1u16..=3).collect::<LookupBuilder<_>>();
+        assert_eq!(lookup.iter_subtables().count(), 3);
+        assert_eq!(lookup.flags, LookupFlag::empty());
+        assert_eq!(lookup.mark_set, None);
+    }
+
+    #[test]
+    fn classdef_try_from_map() {
+        let map = BTreeMap::from(
+            [(1, 5), (2, 5), (3, 2), (9, 0)].map(|(gid, cls)| (GlyphId16::new(gid), cls)),
+        );
+        let builder = ClassDefBuilder::try_from_map(map).unwrap();
+        let mut expected = ClassDefBuilder::new();
+        expected.checked_add(make_glyph_class([1, 2]));
+        expected.checked_add(make_glyph_class([3]));
+        expected.checked_add(make_glyph_class([9]));
+        assert_eq!(builder, expected);
+
+        // repeating an assignment is fine, but changing it is not
+        let pairs =
+            [(1, 5), (2, 5), (1, 5), (3, 2), (2, 2)].map(|(gid, cls)| (GlyphId16::new(gid), cls));
+        assert_eq!(
+            ClassDefBuilder::try_from_map(pairs),
+            Err(LayoutBuildError::ConflictingAssignment {
+                glyph: GlyphId16::new(2)
+            })
+        );
+    }
+
+    #[test]
+    fn classdef_preserving_ids() {
+        let mut builder = ClassDefBuilder::new();
+        builder.try_add_with_id(make_glyph_class([1]), 1).unwrap();
+        builder
+            .try_add_with_id(make_glyph_class([2, 3, 4]), 3)
+            .unwrap();
+        builder
+            .try_add_with_id(make_glyph_class([5, 6]), 2)
+            .unwrap();
+        builder.checked_add(make_glyph_class([7, 8]));
+        builder.checked_add(make_glyph_class([9, 10, 11]));
+        // adding the same class with the same id again is fine
+        builder.try_add_with_id(make_glyph_class([1]), 1).unwrap();
+        assert_eq!(
+            builder.try_add_with_id(make_glyph_class([12]), 2),
+            Err(LayoutBuildError::ConflictingClassId { class_id: 2 })
+        );
+        assert_eq!(
+            builder.try_add_with_id(make_glyph_class([1]), 7),
+            Err(LayoutBuildError::ConflictingClassId { class_id: 7 })
+        );
+
+        let (class_def, mapping) = builder.clone().build_with_mapping_preserving_ids();
+        assert_eq!(mapping.get(&make_glyph_class([1])), Some(&1));
+        assert_eq!(mapping.get(&make_glyph_class([5, 6])), Some(&2));
+        assert_eq!(mapping.get(&make_glyph_class([2, 3, 4])), Some(&3));
+        // the remaining classes follow, largest first
+        assert_eq!(mapping.get(&make_glyph_class([9, 10, 11])), Some(&4));
+        assert_eq!(mapping.get(&make_glyph_class([7, 8])), Some(&5));
+        assert_eq!(class_def.get(GlyphId16::new(3)), 3);
+
+        // the normal build ignores the requested ids
+        let (_, mapping) = builder.build_with_mapping();
+        assert_eq!(mapping.get(&make_glyph_class([2, 3, 4])), Some(&1));
+    }
+
+    #[test]
+    fn classdef_preserving_ids_class_0() {
+        // class 0 is reserved unless the builder uses it
+        let mut builder = ClassDefBuilder::new();
+        assert_eq!(
+            builder.try_add_with_id(make_glyph_class([1, 2]), 0),
+            Err(LayoutBuildError::ConflictingClassId { class_id: 0 })
+        );
+        assert!(builder.class_containing(GlyphId16::new(1)).is_none());
+
+        let mut builder = ClassDefBuilder::new_using_class_0();
+        builder
+            .try_add_with_id(make_glyph_class([1, 2]), 0)
+            .unwrap();
+        builder.checked_add(make_glyph_class([3]));
+        let (class_def, mapping) = builder.try_build_with_mapping_preserving_ids().unwrap();
+        assert_eq!(mapping.get(&make_glyph_class([1, 2])), Some(&0));
+        assert_eq!(mapping.get(&make_glyph_class([3])), Some(&1));
+        assert_eq!(class_def.get(GlyphId16::new(3)), 1);
+    }
+
+    #[test]
+    fn classdef_glyph_mapping() {
+        let make_builder = || {
+            let mut builder = ClassDefBuilder::new_using_class_0();
+            builder.checked_add(make_glyph_class([1, 2, 3]));
+            builder.checked_add(make_glyph_class([7, 9]));
+            builder.checked_add(make_glyph_class([4]));
+            builder
+        };
+        let (class_def, mapping) = make_builder().build_with_glyph_mapping();
+        // larger classes first, starting from class 0
+        assert_eq!(
+            mapping.into_iter().collect::<Vec<_>>(),
+            [(1, 0), (2, 0), (3, 0), (4, 2), (7, 1), (9, 1)]
+                .map(|(gid, cls)| (GlyphId16::new(gid), cls))
+        );
+        assert_eq!(class_def, make_builder().build());
+    }
+
+    #[test]
+    fn coverage_from_remapped_ranges() {
+        let ranges = RangeRecord::iter_for_glyphs(&[10, 11, 12, 13, 14, 15].map(GlyphId16::new))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges.len(), 1);
+        // drop 12, and spread the rest out
+        let coverage = CoverageTableBuilder::from_remapped_ranges(ranges, |gid| {
+            (gid.to_u16() != 12).then(|| GlyphId16::new(gid.to_u16() * 3 - 20))
+        });
+        let coverage = coverage.build();
+        assert!(matches!(coverage, CoverageTable::Format1(_)));
+        assert_eq!(
+            coverage.iter().collect::<Vec<_>>(),
+            [10, 13, 19, 22, 25].map(GlyphId16::new)
+        );
+    }
+
+    #[test]
+    fn coverage_first_last() {
+        let empty = CoverageTableBuilder::default();
+        assert_eq!((empty.first(), empty.last()), (None, None));
+
+        let gids = [40u16, 7, 300, 12, 7, 199, 3];
+        let coverage = gids
+            .iter()
+            .copied()
+            .map(GlyphId16::new)
+            .collect::<CoverageTableBuilder>();
+        let min = gids.iter().copied().min().map(GlyphId16::new);
+        let max = gids.iter().copied().max().map(GlyphId16::new);
+        assert_eq!(coverage.first(), min);
+        assert_eq!(coverage.last(), max);
+    }
+
+    #[test]
+    fn coverage_format_size_delta() {
+        let sparse = [1u16, 5, 9, 40, 41];
+        let dense = [1u16, 2, 3, 4, 5, 6, 7, 8, 20, 21, 22, 23];
+        let tied = [1u16, 2, 3];
+        for (glyphs, expected) in [(&sparse[..], -14), (&dense, 12), (&tied, 0)] {
+            let builder = glyphs
+                .iter()
+                .copied()
+                .map(GlyphId16::new)
+                .collect::<CoverageTableBuilder>();
+            let delta = builder.format_size_delta();
+            assert_eq!(delta, expected, "{glyphs:?}");
+            let is_format_2 = matches!(builder.build(), CoverageTable::Format2(_));
+            assert_eq!(is_format_2, delta > 0, "{glyphs:?}");
+        }
+    }
+
+    #[test]
+    fn glyph_id_too_large() {
+        let max = GlyphId::new(u16::MAX as u32);
+        let too_large = GlyphId::new(u16::MAX as u32 + 1);
+
+        let mut coverage = CoverageTableBuilder::default();
+        assert_eq!(coverage.try_add_glyph_id(max), Ok(0));
+        assert_eq!(
+            coverage.try_add_glyph_id(too_large),
+            Err(LayoutBuildError::GlyphIdTooLarge { glyph: too_large })
+        );
+        assert_eq!(coverage.glyphs.len(), 1);
+
+        let mut class_def = ClassDefBuilder::new();
+        assert_eq!(
+            class_def.try_add_glyph_ids([GlyphId::new(5), too_large]),
+            Err(LayoutBuildError::GlyphIdTooLarge { glyph: too_large })
+        );
+        assert!(class_def.try_add_glyph_ids([GlyphId::new(5), max]).is_ok());
+        assert_eq!(class_def.classes.len(), 1);
+    }
+
+    #[test]
+    fn map_lookup_subtables() {
+        let lookup =
+            LookupBuilder::new_with_lookups(LookupFlag::IGNORE_MARKS, Some(2), vec![1u16, 2]);
+        let lookup_flags = lookup.flags;
+        let mapped = lookup.map_subtables(|sub| format!("subtable {sub}"));
+        assert_eq!(mapped.flags, lookup_flags);
+        assert_eq!(mapped.mark_set, Some(2));
+        assert_eq!(mapped.subtables, ["subtable 1", "subtable 2"]);
+    }
+
+    #[test]
+    fn single_subtable_lookup() {
+        use crate::tables::gsub::builders::SingleSubBuilder;
+
+        let mut subtable = SingleSubBuilder::default();
+        subtable.insert(GlyphId16::new(5), GlyphId16::new(6));
+        subtable.insert(GlyphId16::new(7), GlyphId16::new(9));
+
+        let manual = LookupBuilder::new_with_lookups(
+            LookupFlag::IGNORE_MARKS,
+            Some(3),
+            vec![subtable.clone()],
+        )
+        .build(&mut VariationStoreBuilder::new(0));
+        let lookup = Lookup::single_subtable(
+            LookupFlag::IGNORE_MARKS,
+            Some(3),
+            subtable,
+            &mut VariationStoreBuilder::new(0),
+        );
+        assert_eq!(lookup, manual);
+        assert!(lookup
+            .lookup_flag
+            .contains(LookupFlag::USE_MARK_FILTERING_SET));
+        assert_eq!(lookup.subtables.len(), 1);
     }
-}
 
-impl FromIterator<GlyphId16> for CoverageTableBuilder {
-    fn from_iter<T: IntoIterator<Item = GlyphId16>>(iter: T) -> Self {
-        let glyphs = iter.into_iter().collect::<Vec<_>>();
-        CoverageTableBuilder::from_glyphs(glyphs)
+    #[test]
+    fn lookup_mark_filtering_set_consistency() {
+        use crate::tables::gsub::builders::SingleSubBuilder;
+
+        let lookup = LookupBuilder::<SingleSubBuilder>::new(LookupFlag::empty(), Some(1));
+        assert!(lookup.flags.contains(LookupFlag::USE_MARK_FILTERING_SET));
+        assert!(lookup.validate().is_ok());
+
+        let mut missing_flag = lookup.clone();
+        missing_flag.flags = LookupFlag::empty();
+        assert_eq!(
+            missing_flag
+                .try_build(&mut VariationStoreBuilder::new(0))
+                .unwrap_err(),
+            LayoutBuildError::MismatchedMarkFilteringSet { mark_set: Some(1) }
+        );
+
+        let mut missing_set = lookup;
+        missing_set.mark_set = None;
+        assert_eq!(
+            missing_set.validate(),
+            Err(LayoutBuildError::MismatchedMarkFilteringSet { mark_set: None })
+        );
+        // the infallible build still succeeds
+        missing_set.build(&mut VariationStoreBuilder::new(0));
     }
-}
 
-fn iter_class_ranges(
-    values: &BTreeMap<GlyphId16, u16>,
-) -> impl Iterator<Item = ClassRangeRecord> + '_ {
-    let mut iter = values.iter();
-    let mut prev = None;
+    #[test]
+    fn rounding_modes() {
+        let round_all = |value| {
+            [
+                RoundingMode::HalfEven,
+                RoundingMode::HalfAwayFromZero,
+                RoundingMode::Truncate,
+            ]
+            .map(|mode| mode.round(value))
+        };
+        assert_eq!(round_all(2.5), [2, 3, 2]);
+        assert_eq!(round_all(3.5), [4, 4, 3]);
+        assert_eq!(round_all(-2.5), [-2, -3, -2]);
+        assert_eq!(round_all(1e9), [i16::MAX; 3]);
 
-    #[allow(clippy::while_let_on_iterator)]
-    std::iter::from_fn(move || {
-        while let Some((gid, class)) = iter.next() {
-            match prev.take() {
-                None => prev = Some((*gid, *gid, *class)),
-                Some((start, end, pclass))
-                    if super::are_sequential(end, *gid) && pclass == *class =>
-                {
-                    prev = Some((start, *gid, pclass))
-                }
-                Some((start_glyph_id, end_glyph_id, pclass)) => {
-                    prev = Some((*gid, *gid, *class));
-                    return Some(ClassRangeRecord {
-                        start_glyph_id,
-                        end_glyph_id,
-                        class: pclass,
-                    });
-                }
-            }
-        }
-        prev.take()
-            .map(|(start_glyph_id, end_glyph_id, class)| ClassRangeRecord {
-                start_glyph_id,
-                end_glyph_id,
-                class,
-            })
-    })
-}
+        let metric = Metric::from_f64(
+            10.5,
+            vec![(make_region(1.0), -4.5)],
+            RoundingMode::HalfAwayFromZero,
+        );
+        assert_eq!(metric.default, 11);
+        assert_eq!(
+            metric.device_or_deltas,
+            DeviceOrDeltas::Deltas(vec![(make_region(1.0), -5)])
+        );
+        let metric = Metric::from_f64(10.5, vec![(make_region(1.0), -4.5)], Default::default());
+        assert_eq!(metric.default, 10);
+        assert_eq!(
+            metric.device_or_deltas,
+            DeviceOrDeltas::Deltas(vec![(make_region(1.0), -4)])
+        );
+    }
 
-fn should_choose_coverage_format_2(glyphs: &[GlyphId16]) -> bool {
-    let format2_len = 4 + RangeRecord::iter_for_glyphs(glyphs).count() * 6;
-    let format1_len = 4 + glyphs.len() * 2;
-    format2_len < format1_len
-}
+    #[test]
+    fn f2dot14_deltas() {
+        let deltas = DeviceOrDeltas::from_f2dot14_deltas(
+            vec![
+                (make_region(1.0), F2Dot14::from_f32(0.25)),
+                (make_region(0.5), F2Dot14::from_f32(0.75)),
+                (make_region(-1.0), F2Dot14::from_f32(-0.5)),
+                (make_region(-0.5), F2Dot14::from_f32(0.0)),
+            ],
+            2,
+        );
+        // 0.5 rounds to 0 and is dropped; 1.5 rounds to 2
+        assert_eq!(
+            deltas,
+            DeviceOrDeltas::Deltas(vec![(make_region(0.5), 2), (make_region(-1.0), -1)])
+        );
 
-#[cfg(test)]
-mod tests {
-    use std::ops::RangeInclusive;
+        let deltas = DeviceOrDeltas::from_f2dot14_deltas(
+            vec![(make_region(1.0), F2Dot14::from_f32(0.25))],
+            10,
+        );
+        assert_eq!(deltas, DeviceOrDeltas::Deltas(vec![(make_region(1.0), 2)]));
 
-    use read_fonts::collections::IntSet;
+        let deltas = DeviceOrDeltas::from_f2dot14_deltas(
+            vec![(make_region(1.0), F2Dot14::from_f32(0.25))],
+            1,
+        );
+        assert_eq!(deltas, DeviceOrDeltas::None);
+    }
 
-    use crate::tables::layout::DeltaFormat;
+    #[test]
+    fn accumulate_delta_sets() {
+        let mut deltas: DeviceOrDeltas = vec![(make_region(1.0), 10), (make_region(0.5), 3)].into();
+        let other: DeviceOrDeltas = vec![(make_region(0.5), 4), (make_region(-1.0), -7)].into();
+        deltas.accumulate(&other).unwrap();
+        assert_eq!(
+            deltas,
+            DeviceOrDeltas::Deltas(vec![
+                (make_region(1.0), 10),
+                (make_region(0.5), 7),
+                (make_region(-1.0), -7),
+            ])
+        );
 
-    use super::*;
+        let mut metric = Metric {
+            default: 50,
+            device_or_deltas: vec![(make_region(1.0), 10)].into(),
+        };
+        metric
+            .accumulate(&Metric {
+                default: -20,
+                device_or_deltas: vec![(make_region(1.0), 5)].into(),
+            })
+            .unwrap();
+        assert_eq!(metric.default, 30);
+        assert_eq!(
+            metric.device_or_deltas,
+            DeviceOrDeltas::Deltas(vec![(make_region(1.0), 15)])
+        );
+    }
 
     #[test]
-    fn classdef_format() {
-        let builder: ClassDefBuilderImpl = [(3u16, 4u16), (4, 6), (5, 1), (9, 5), (10, 2), (11, 3)]
-            .map(|(gid, cls)| (GlyphId16::new(gid), cls))
-            .into_iter()
-            .collect();
+    fn metric_var_eq() {
+        let metric = Metric {
+            default: 10,
+            device_or_deltas: vec![(make_region(1.0), 3), (make_region(-1.0), -2)].into(),
+        };
+        let reordered = Metric {
+            default: 10,
+            device_or_deltas: vec![
+                (make_region(-1.0), -2),
+                (make_region(0.5), 0),
+                (make_region(1.0), 3),
+            ]
+            .into(),
+        };
+        assert_ne!(metric, reordered);
+        assert!(metric.var_eq(&reordered));
 
-        assert!(builder.prefer_format_1());
+        let different_default = Metric {
+            default: 11,
+            ..reordered.clone()
+        };
+        assert!(!metric.var_eq(&different_default));
+        let all_zero = Metric {
+            default: 10,
+            device_or_deltas: vec![(make_region(1.0), 0)].into(),
+        };
+        assert!(all_zero.var_eq(&Metric {
+            default: 10,
+            device_or_deltas: DeviceOrDeltas::None
+        }));
+    }
 
-        let builder: ClassDefBuilderImpl = [(1u16, 1u16), (3, 4), (9, 5), (10, 2), (11, 3)]
-            .map(|(gid, cls)| (GlyphId16::new(gid), cls))
-            .into_iter()
-            .collect();
+    #[test]
+    fn metric_tuple_round_trip() {
+        let deltas = vec![(make_region(1.0), 10), (make_region(-1.0), -4)];
+        let metric = Metric::from((25, deltas));
+        assert!(metric.has_deltas());
+        let (default, device_or_deltas) = metric.clone().into_parts();
+        assert_eq!(default, 25);
+        let DeviceOrDeltas::Deltas(round_tripped) = device_or_deltas else {
+            panic!("expected deltas");
+        };
+        assert_eq!(Metric::from((default, round_tripped)), metric);
 
-        assert!(builder.prefer_format_1());
+        let metric = Metric::from((25, Vec::new()));
+        assert_eq!(metric, Metric::from(25));
+        assert_eq!(metric.into_parts(), (25, DeviceOrDeltas::None));
     }
 
     #[test]
-    fn classdef_prefer_format2() {
-        fn iter_class_items(
-            start: u16,
-            end: u16,
-            cls: u16,
-        ) -> impl Iterator<Item = (GlyphId16, u16)> {
-            (start..=end).map(move |gid| (GlyphId16::new(gid), cls))
-        }
+    fn accumulate_none_is_identity() {
+        let deltas: DeviceOrDeltas = vec![(make_region(1.0), 10)].into();
+        let mut none = DeviceOrDeltas::None;
+        none.accumulate(&deltas).unwrap();
+        assert_eq!(none, deltas);
 
-        // 3 ranges of 4 glyphs at 6 bytes a range should be smaller than writing
-        // out the 3 * 4 classes directly
-        let builder: ClassDefBuilderImpl = iter_class_items(5, 8, 3)
-            .chain(iter_class_items(9, 12, 4))
-            .chain(iter_class_items(13, 16, 5))
-            .collect();
+        let mut same = deltas.clone();
+        same.accumulate(&DeviceOrDeltas::None).unwrap();
+        assert_eq!(same, deltas);
+    }
+
+    #[test]
+    fn accumulate_devices_fails() {
+        let device = DeviceOrDeltas::Device(Device::new(8, 9, &[1, 2]));
+        let mut this = device.clone();
+        assert_eq!(
+            this.accumulate(&device),
+            Err(LayoutBuildError::IncompatibleDeltas)
+        );
+        assert_eq!(this, device);
 
-        assert!(!builder.prefer_format_1());
+        let by_region: DeviceOrDeltas = vec![(make_region(1.0), 2)].into();
+        let mut this = DeviceOrDeltas::from_region_indices(vec![(0, 3)]);
+        assert_eq!(
+            this.accumulate(&by_region),
+            Err(LayoutBuildError::IncompatibleDeltas)
+        );
+        this.accumulate(&DeviceOrDeltas::from_region_indices(vec![(0, 1), (1, 4)]))
+            .unwrap();
+        assert_eq!(this, DeviceOrDeltas::IndexedDeltas(vec![(0, 4), (1, 4)]));
     }
 
     #[test]
-    fn delta_format_dflt() {
-        let some: DeltaFormat = Default::default();
-        assert_eq!(some, DeltaFormat::Local2BitDeltas);
+    fn accumulate_overflow() {
+        let region = make_region(1.0);
+        let deltas: DeviceOrDeltas =
+            vec![(region.clone(), i16::MAX - 10), (make_region(-1.0), 5)].into();
+        let mut this = deltas.clone();
+        assert_eq!(
+            this.accumulate(&vec![(region.clone(), 11)].into()),
+            Err(LayoutBuildError::DeltaOverflow)
+        );
+        assert_eq!(this, deltas);
+
+        let mut metric = Metric {
+            default: i16::MIN,
+            device_or_deltas: DeviceOrDeltas::None,
+        };
+        assert_eq!(
+            metric.accumulate(&Metric {
+                default: -1,
+                device_or_deltas: vec![(region, 1)].into(),
+            }),
+            Err(LayoutBuildError::DeltaOverflow)
+        );
+        assert!(metric.device_or_deltas.is_none());
     }
 
-    fn make_glyph_vec<const N: usize>(gids: [u16; N]) -> Vec<GlyphId16> {
-        gids.into_iter().map(GlyphId16::new).collect()
+    #[test]
+    fn build_all_deltas() {
+        let items = || {
+            (0..100i16)
+                .map(|i| match i % 4 {
+                    0 => DeviceOrDeltas::None,
+                    1 => vec![(make_region(1.0), i)].into(),
+                    2 => vec![(make_region(-1.0), i % 10), (make_region(1.0), 2)].into(),
+                    _ => vec![(make_region(0.5), i)].into(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut batch_store = VariationStoreBuilder::new(1);
+        let batch = DeviceOrDeltas::build_all(items(), &mut batch_store);
+
+        let mut single_store = VariationStoreBuilder::new(1);
+        let single = items()
+            .into_iter()
+            .map(|item| item.build(&mut single_store))
+            .collect::<Vec<_>>();
+        assert_eq!(batch.len(), 100);
+        assert_eq!(batch, single);
+        let (batch_ivs, _) = batch_store.build();
+        let (single_ivs, _) = single_store.build();
+        assert_eq!(batch_ivs, single_ivs);
     }
 
     #[test]
-    fn coverage_builder() {
-        let coverage = make_glyph_vec([1u16, 2, 9, 3, 6, 9])
+    fn coverage_build_with_mapping() {
+        let glyphs = [40u16, 3, 7, 8, 9, 10, 11, 12, 3, 100];
+        let builder = glyphs
+            .map(GlyphId16::new)
             .into_iter()
             .collect::<CoverageTableBuilder>();
-        assert_eq!(coverage.glyphs, make_glyph_vec([1, 2, 3, 6, 9]));
+        let (coverage, mapping) = builder.build_with_mapping();
+        assert_eq!(mapping.len(), coverage.len());
+        assert_eq!(
+            mapping.values().copied().collect::<Vec<_>>(),
+            (0..mapping.len() as u16).collect::<Vec<_>>()
+        );
+        for (gid, idx) in coverage.iter().zip(0u16..) {
+            assert_eq!(mapping.get(&gid), Some(&idx));
+        }
+        assert_eq!(mapping.get(&GlyphId16::new(100)), Some(&8));
     }
 
-    fn make_class<const N: usize>(gid_class_pairs: [(u16, u16); N]) -> ClassDef {
-        gid_class_pairs
-            .iter()
-            .map(|(gid, cls)| (GlyphId16::new(*gid), *cls))
-            .collect::<ClassDefBuilderImpl>()
-            .build()
+    #[test]
+    fn coverage_round_trip() {
+        use read_fonts::FontRead;
+
+        let original = [1u16, 2, 3, 4, 5, 6, 20, 21, 22, 23, 24]
+            .map(GlyphId16::new)
+            .into_iter()
+            .collect::<CoverageTableBuilder>()
+            .build();
+        assert!(matches!(original, CoverageTable::Format2(_)));
+        let bytes = crate::dump_table(&original).unwrap();
+        let parsed =
+            read_fonts::tables::layout::CoverageTable::read(bytes.as_slice().into()).unwrap();
+
+        let mut builder = CoverageTableBuilder::from(&parsed);
+        assert_eq!(builder.index_of(GlyphId16::new(20)), Some(6));
+        assert_eq!(builder.add(GlyphId16::new(7)), 6);
+        let rebuilt = builder.build();
+        assert!(matches!(rebuilt, CoverageTable::Format2(_)));
+        assert_eq!(
+            rebuilt.iter().map(GlyphId16::to_u16).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 6, 7, 20, 21, 22, 23, 24]
+        );
     }
 
     #[test]
-    fn class_def_builder_zero() {
-        // even if class 0 is provided, we don't need to assign explicit entries for it
-        let class = make_class([(4, 0), (5, 1)]);
-        assert!(class.get_raw(GlyphId16::new(4)).is_none());
-        assert_eq!(class.get_raw(GlyphId16::new(5)), Some(1));
-        assert!(class.get_raw(GlyphId16::new(100)).is_none());
+    fn coverage_round_trip_keeps_format() {
+        use read_fonts::FontRead;
+
+        // a contiguous range, which would be smaller in format 2
+        let original = CoverageTable::Format1(CoverageFormat1 {
+            glyph_array: (10..20).map(GlyphId16::new).collect(),
+        });
+        let bytes = crate::dump_table(&original).unwrap();
+        let parsed =
+            read_fonts::tables::layout::CoverageTable::read(bytes.as_slice().into()).unwrap();
+
+        let builder = CoverageTableBuilder::from(&parsed);
+        assert_eq!(builder.source_format(), Some(1));
+        assert_eq!(crate::dump_table(&builder.build()).unwrap(), bytes);
+
+        let mut builder = CoverageTableBuilder::from(&parsed);
+        builder.reoptimize();
+        assert!(matches!(builder.build(), CoverageTable::Format2(_)));
+
+        // writing directly uses the same format as building
+        for reoptimize in [false, true] {
+            let builder = || {
+                let mut builder = CoverageTableBuilder::from(&parsed);
+                if reoptimize {
+                    builder.reoptimize();
+                }
+                builder
+            };
+            let streamed = crate::dump_table(&Streamed(builder())).unwrap();
+            let built = crate::dump_table(&builder().build()).unwrap();
+            assert_eq!(streamed, built, "reoptimize: {reoptimize}");
+            assert_eq!(streamed == bytes, !reoptimize);
+        }
     }
 
-    // https://github.com/googlefonts/fontations/issues/923
-    // an empty classdef should always be format 2
     #[test]
-    fn class_def_builder_empty() {
-        let builder = ClassDefBuilderImpl::from_iter([]);
-        let built = builder.build();
+    fn empty_encodings() {
+        let coverage = CoverageTableBuilder::default().build();
+        assert_eq!(coverage, CoverageTableBuilder::empty());
+        // format 1, glyph count 0
+        assert_eq!(crate::dump_table(&coverage).unwrap(), [0, 1, 0, 0]);
 
-        assert_eq!(
-            built,
-            ClassDef::Format2(ClassDefFormat2 {
-                class_range_records: vec![]
-            })
-        )
+        let class_def = ClassDefBuilder::default().build();
+        // format 2, class range count 0
+        assert_eq!(crate::dump_table(&class_def).unwrap(), [0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn class_def_from_array() {
+        let class = ClassDef::from([
+            (GlyphId16::new(3), 1),
+            (GlyphId16::new(4), 0),
+            (GlyphId16::new(5), 2),
+        ]);
+        // class 0 is filtered, as with the iterator path
+        assert_eq!(class, make_class([(3, 1), (5, 2)]));
+        assert_eq!(class.get(GlyphId16::new(4)), 0);
+        assert_eq!(class.get(GlyphId16::new(5)), 2);
     }
 
     #[test]
@@ -652,6 +3959,24 @@ mod tests {
         assert_eq!(cls.get(GlyphId16::new(20)), 0);
     }
 
+    #[test]
+    fn classdef_iter_ranges() {
+        // 3 and 4 are class 1, 5 and 6 class 2, 7 is unassigned, 8 is class 2
+        let class = ClassDef::Format1(ClassDefFormat1 {
+            start_glyph_id: GlyphId16::new(3),
+            class_value_array: vec![1, 1, 2, 2, 0, 2],
+        });
+        let ranges = class.iter_ranges().collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            vec![
+                ClassRangeRecord::new(GlyphId16::new(3), GlyphId16::new(4), 1),
+                ClassRangeRecord::new(GlyphId16::new(5), GlyphId16::new(6), 2),
+                ClassRangeRecord::new(GlyphId16::new(8), GlyphId16::new(8), 2),
+            ]
+        );
+    }
+
     fn make_glyph_class<const N: usize>(glyphs: [u16; N]) -> IntSet<GlyphId16> {
         glyphs.into_iter().map(GlyphId16::new).collect()
     }
@@ -670,6 +3995,20 @@ mod tests {
         assert_eq!(cls.get(GlyphId16::new(10)), 0);
     }
 
+    #[test]
+    fn classdef_verify_against_coverage() {
+        let mut builder = ClassDefBuilder::new_using_class_0();
+        builder.checked_add(make_glyph_class([1, 2]));
+        builder.checked_add(make_glyph_class([5]));
+        let coverage = make_glyph_vec([1, 2, 4, 5])
+            .into_iter()
+            .collect::<CoverageTableBuilder>();
+        assert_eq!(
+            builder.verify_against_coverage(&coverage),
+            vec![GlyphId16::new(4)]
+        );
+    }
+
     #[test]
     fn classdef_assign_order() {
         // - longer classes before short ones
@@ -701,4 +4040,363 @@ mod tests {
         assert_eq!(map.get(&c1), map.get(&c2));
         assert!(!map.contains_key(&c3));
     }
+
+    #[test]
+    fn classdef_conflicting_assignment() {
+        let mut builder = ClassDefBuilder::default();
+        builder.try_add(make_glyph_class([1, 2, 3])).unwrap();
+        // adding an identical class is fine
+        builder.try_add(make_glyph_class([3, 2, 1])).unwrap();
+        assert_eq!(
+            builder.try_add(make_glyph_class([5, 3])),
+            Err(LayoutBuildError::ConflictingAssignment {
+                glyph: GlyphId16::new(3)
+            })
+        );
+        // the rejected class was not added
+        assert!(builder.try_add(make_glyph_class([5])).is_ok());
+    }
+
+    #[test]
+    fn classdef_checked_add_all() {
+        let mut builder = ClassDefBuilder::default();
+        let rejected = builder.checked_add_all([
+            make_glyph_class([1, 2]),
+            make_glyph_class([2, 3]),
+            make_glyph_class([4]),
+        ]);
+        assert_eq!(rejected, [make_glyph_class([2, 3])]);
+        let (_, mapping) = builder.build_with_mapping();
+        assert_eq!(mapping.len(), 2);
+        assert!(mapping.contains_key(&make_glyph_class([4])));
+    }
+
+    #[test]
+    fn classdef_overflow_reduced_limit() {
+        let classes = || {
+            (1..=3)
+                .map(|gid| make_glyph_class([gid]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            assign_class_ids_with_limit(classes(), false, false, 3).unwrap_err(),
+            LayoutBuildError::ClassDefOverflow { class_count: 4 }
+        );
+        let (_, mapping) = assign_class_ids_with_limit(classes(), true, false, 3).unwrap();
+        assert_eq!(mapping.values().max(), Some(&2));
+    }
+
+    #[test]
+    fn classdef_overflow() {
+        let mut builder = ClassDefBuilder::default();
+        for gid in 0..=u16::MAX {
+            builder.checked_add(make_glyph_class([gid]));
+        }
+        // 65536 classes plus the reserved class 0
+        assert_eq!(
+            builder.clone().try_build(),
+            Err(LayoutBuildError::ClassDefOverflow {
+                class_count: u16::MAX as usize + 2
+            })
+        );
+
+        // if class 0 is used, every class fits
+        let mut builder = ClassDefBuilder::new_using_class_0();
+        for gid in 0..=u16::MAX {
+            builder.checked_add(make_glyph_class([gid]));
+        }
+        assert!(builder.try_build().is_ok());
+    }
+
+    #[test]
+    fn coverage_index_at_limit() {
+        // every possible glyph id still has a representable coverage index
+        let mut builder = (0..u16::MAX)
+            .map(GlyphId16::new)
+            .collect::<CoverageTableBuilder>();
+        assert_eq!(builder.try_add(GlyphId16::new(u16::MAX)), Ok(u16::MAX));
+        let coverage = builder.try_build().unwrap();
+        assert_eq!(coverage.len(), u16::MAX as usize + 1);
+    }
+
+    #[test]
+    fn lookup_flag_builder() {
+        let build = |builder: LookupFlagBuilder| builder.build().unwrap();
+        assert_eq!(build(LookupFlagBuilder::new()), LookupFlag::empty());
+        assert_eq!(
+            build(LookupFlagBuilder::new().right_to_left()),
+            LookupFlag::RIGHT_TO_LEFT
+        );
+        assert_eq!(
+            build(LookupFlagBuilder::new().ignore_base_glyphs()),
+            LookupFlag::IGNORE_BASE_GLYPHS
+        );
+        assert_eq!(
+            build(LookupFlagBuilder::new().ignore_ligatures()),
+            LookupFlag::IGNORE_LIGATURES
+        );
+        assert_eq!(
+            build(LookupFlagBuilder::new().ignore_marks()),
+            LookupFlag::IGNORE_MARKS
+        );
+        assert_eq!(
+            build(LookupFlagBuilder::new().use_mark_filtering_set()),
+            LookupFlag::USE_MARK_FILTERING_SET
+        );
+        let flags = build(
+            LookupFlagBuilder::new()
+                .ignore_ligatures()
+                .mark_attachment_class(3),
+        );
+        assert_eq!(flags.mark_attachment_class(), Some(3));
+        assert_eq!(flags.to_bits(), 0x0304);
+    }
+
+    #[test]
+    fn lookup_flag_builder_conflict() {
+        let result = LookupFlagBuilder::new()
+            .mark_attachment_class(2)
+            .ignore_marks()
+            .build();
+        assert_eq!(
+            result,
+            Err(LayoutBuildError::ConflictingLookupFlags {
+                mark_attachment_class: 2
+            })
+        );
+        // clearing the class resolves the conflict
+        let result = LookupFlagBuilder::new()
+            .mark_attachment_class(2)
+            .ignore_marks()
+            .mark_attachment_class(0)
+            .build();
+        assert_eq!(result, Ok(LookupFlag::IGNORE_MARKS));
+    }
+
+    #[test]
+    fn lookup_build_twice() {
+        use crate::tables::gpos::builders::{SinglePosBuilder, ValueRecordBuilder};
+
+        let mut lookup = LookupBuilder::<SinglePosBuilder>::new(LookupFlag::empty(), None);
+        let subtable = lookup.last_mut().unwrap();
+        subtable.insert(
+            GlyphId16::new(1),
+            ValueRecordBuilder::new().with_x_advance(50),
+        );
+        subtable.insert(
+            GlyphId16::new(2),
+            ValueRecordBuilder::new()
+                .with_x_advance(20)
+                .with_x_advance_device(Device::new(10, 11, &[1, 2])),
+        );
+        let mut var_store = VariationStoreBuilder::new(1);
+        let (first, second) = lookup.build_twice(&mut var_store);
+        assert_eq!(
+            crate::dump_table(&first).unwrap(),
+            crate::dump_table(&second).unwrap()
+        );
+
+        // deltas are shared between the two builds
+        lookup.last_mut().unwrap().insert(
+            GlyphId16::new(3),
+            ValueRecordBuilder::new()
+                .with_y_placement(5)
+                .with_y_placement_device(vec![(make_region(1.0), 12)]),
+        );
+        let (first, second) = lookup.build_twice(&mut var_store);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn remap_lookup_variation_indices() {
+        use crate::tables::gpos::{
+            builders::{SinglePosBuilder, ValueRecordBuilder},
+            SinglePos, ValueRecord,
+        };
+        use crate::tables::variations::ivs_builder::RemapVariationIndices;
+
+        let mut lookup = LookupBuilder::<SinglePosBuilder>::new(LookupFlag::empty(), None);
+        let subtable = lookup.last_mut().unwrap();
+        subtable.insert(
+            GlyphId16::new(1),
+            ValueRecordBuilder::new()
+                .with_x_advance(10)
+                .with_x_advance_device(vec![(make_region(1.0), 12)]),
+        );
+        lookup.force_subtable_break();
+        lookup.last_mut().unwrap().insert(
+            GlyphId16::new(2),
+            ValueRecordBuilder::new()
+                .with_x_placement(-5)
+                .with_x_placement_device(vec![(make_region(-1.0), 3)])
+                .with_y_advance(20)
+                .with_y_advance_device(vec![(make_region(1.0), -7)]),
+        );
+        let mut var_store = VariationStoreBuilder::new(1);
+        let mut lookup = lookup.build(&mut var_store);
+        let (_, key_map) = var_store.build();
+        lookup.remap_variation_indices(&key_map);
+
+        fn devices(record: &ValueRecord) -> impl Iterator<Item = &DeviceOrVariationIndex> {
+            [
+                &record.x_placement_device,
+                &record.y_placement_device,
+                &record.x_advance_device,
+                &record.y_advance_device,
+            ]
+            .into_iter()
+            .filter_map(|device| device.as_ref())
+        }
+
+        let mut n_devices = 0;
+        for subtable in &lookup.subtables {
+            let SinglePos::Format1(subtable) = subtable.as_ref() else {
+                panic!("expected format 1");
+            };
+            for device in devices(&subtable.value_record) {
+                n_devices += 1;
+                assert!(matches!(device, DeviceOrVariationIndex::VariationIndex(_)));
+            }
+        }
+        assert_eq!(n_devices, 3);
+        assert!(crate::dump_table(&lookup).is_ok());
+    }
+
+    #[test]
+    fn lookup_estimated_size() {
+        use crate::tables::gsub::builders::{LigatureSubBuilder, SingleSubBuilder};
+
+        let gids = |ids: &[u16]| ids.iter().copied().map(GlyphId16::new).collect::<Vec<_>>();
+        let mut lookup = LookupBuilder::<SingleSubBuilder>::new(LookupFlag::empty(), Some(2));
+        let subtable = lookup.last_mut().unwrap();
+        subtable.insert(GlyphId16::new(1), GlyphId16::new(10));
+        subtable.insert(GlyphId16::new(2), GlyphId16::new(30));
+        lookup.force_subtable_break();
+        lookup
+            .last_mut()
+            .unwrap()
+            .insert(GlyphId16::new(7), GlyphId16::new(8));
+        let estimate = lookup.estimated_size();
+        let actual = crate::dump_table(&lookup.build(&mut VariationStoreBuilder::new(0)))
+            .unwrap()
+            .len();
+        assert!(estimate >= actual, "{estimate} < {actual}");
+
+        let mut lookup = LookupBuilder::<LigatureSubBuilder>::new(LookupFlag::empty(), None);
+        let subtable = lookup.last_mut().unwrap();
+        subtable.insert(gids(&[1, 2, 3]), GlyphId16::new(20));
+        subtable.insert(gids(&[1, 3]), GlyphId16::new(21));
+        subtable.insert(gids(&[4, 4]), GlyphId16::new(22));
+        let estimate = lookup.estimated_size();
+        let actual = crate::dump_table(&lookup.build(&mut VariationStoreBuilder::new(0)))
+            .unwrap()
+            .len();
+        assert!(estimate >= actual, "{estimate} < {actual}");
+    }
+
+    #[test]
+    fn lookup_of_empty_subtables() {
+        use crate::tables::gsub::SingleSubst;
+
+        let mut lookup =
+            LookupBuilder::<EmptySubtable<SingleSubst>>::new(LookupFlag::IGNORE_MARKS, None);
+        lookup.force_subtable_break();
+        assert!(!lookup.uses_variations());
+        assert_eq!(lookup.estimated_size(), 6);
+        let lookup = lookup.build(&mut VariationStoreBuilder::new(0));
+        assert!(lookup.subtables.is_empty());
+        assert_eq!(lookup.lookup_flag, LookupFlag::IGNORE_MARKS);
+    }
+
+    #[test]
+    fn labeled_subtable_errors() {
+        // a subtable that fails to build if it has too many glyphs
+        #[derive(Clone, Debug, Default)]
+        struct Subtable(usize);
+
+        impl Builder for Subtable {
+            type Output = Vec<()>;
+
+            fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
+                self.try_build(var_store).unwrap()
+            }
+
+            fn try_build(
+                self,
+                _: &mut VariationStoreBuilder,
+            ) -> Result<Self::Output, LayoutBuildError> {
+                if self.0 > 10 {
+                    return Err(LayoutBuildError::CoverageOverflow {
+                        glyph_count: self.0,
+                    });
+                }
+                Ok(vec![()])
+            }
+        }
+
+        let mut lookup = LookupBuilder::<Labeled<Subtable>>::new(LookupFlag::empty(), None);
+        lookup.force_subtable_break();
+        lookup.push_labeled(Subtable(5), "features.fea:12");
+        lookup.push_labeled(Subtable(20), "features.fea:20");
+        assert_eq!(lookup.subtable_label(0), None);
+        assert_eq!(lookup.subtable_label(2), Some("features.fea:12"));
+
+        // labels stay with their subtables when they are reordered
+        let mut reordered = lookup.clone();
+        reordered.subtables.reverse();
+        reordered.subtables.remove(1);
+        assert_eq!(reordered.subtable_label(0), Some("features.fea:20"));
+        assert_eq!(reordered.subtable_label(1), None);
+
+        let err = lookup
+            .clone()
+            .try_build(&mut VariationStoreBuilder::new(0))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            LayoutBuildError::InSubtable {
+                label: "features.fea:20".into(),
+                error: Box::new(LayoutBuildError::CoverageOverflow { glyph_count: 20 }),
+            }
+        );
+        assert!(err.to_string().contains("features.fea:20"));
+
+        // unlabeled subtables report the error unchanged
+        let lookup = [Subtable(5), Subtable(30)]
+            .into_iter()
+            .collect::<LookupBuilder<_>>();
+        assert_eq!(
+            lookup.try_build(&mut VariationStoreBuilder::new(0)),
+            Err(LayoutBuildError::CoverageOverflow { glyph_count: 30 })
+        );
+    }
+
+    #[test]
+    fn lookup_uses_variations() {
+        use crate::tables::gpos::builders::{SinglePosBuilder, ValueRecordBuilder};
+
+        let mut static_lookup = LookupBuilder::<SinglePosBuilder>::new(LookupFlag::empty(), None);
+        let subtable = static_lookup.last_mut().unwrap();
+        subtable.insert(
+            GlyphId16::new(1),
+            ValueRecordBuilder::new().with_x_advance(50),
+        );
+        subtable.insert(
+            GlyphId16::new(2),
+            ValueRecordBuilder::new()
+                .with_x_advance(20)
+                .with_x_advance_device(Device::new(10, 11, &[1, 2])),
+        );
+        assert!(!static_lookup.uses_variations());
+
+        let mut var_lookup = static_lookup.clone();
+        var_lookup.force_subtable_break();
+        var_lookup.last_mut().unwrap().insert(
+            GlyphId16::new(3),
+            ValueRecordBuilder::new()
+                .with_y_placement(5)
+                .with_y_placement_device(vec![(make_region(1.0), 12)]),
+        );
+        assert!(var_lookup.uses_variations());
+    }
 }
@@ -5,6 +5,9 @@ include!("../../generated/generated_variations.rs");
 pub use read_fonts::tables::variations::{DeltaRunType, TupleIndex, TupleVariationCount};
 
 pub mod ivs_builder;
+pub(crate) mod model;
+
+pub use model::InstanceLocation;
 
 impl TupleVariationHeader {
     pub fn new(
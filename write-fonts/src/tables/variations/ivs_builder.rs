@@ -7,7 +7,7 @@ use std::{
 };
 
 use crate::tables::{
-    layout::VariationIndex,
+    layout::{PendingVariationIndex, VariationIndex},
     variations::{ItemVariationData, ItemVariationStore, VariationRegion, VariationRegionList},
 };
 use indexmap::IndexMap;
@@ -90,6 +90,17 @@ impl VariationStoreBuilder {
         }
     }
 
+    /// Reserve capacity for at least `additional` more delta sets.
+    ///
+    /// This avoids repeated reallocation when a large number of delta sets
+    /// are known to be coming.
+    pub fn reserve(&mut self, additional: usize) {
+        match &mut self.delta_sets {
+            DeltaSetStorage::Direct(val) => val.reserve(additional),
+            DeltaSetStorage::Deduplicated(val) => val.reserve(additional),
+        }
+    }
+
     /// Create a builder that does not share deltas between entries.
     ///
     /// This is used in HVAR, where it is possible to use glyph ids as the
@@ -106,10 +117,47 @@ impl VariationStoreBuilder {
     pub fn add_deltas<T: Into<i32>>(
         &mut self,
         deltas: Vec<(VariationRegion, T)>,
+    ) -> TemporaryDeltaSetId {
+        let deltas = deltas
+            .into_iter()
+            .map(|(region, delta)| (self.add_region(region), delta))
+            .collect();
+        self.add_deltas_by_index(deltas)
+    }
+
+    /// Register a region with this builder, returning its index.
+    ///
+    /// The returned index can be used with [`add_deltas_by_index`], which
+    /// avoids re-hashing the region for each set of deltas. Adding the same
+    /// region multiple times will always return the same index.
+    ///
+    /// Regions are ordered in the final region list based on when they were
+    /// first added to the builder.
+    ///
+    /// [`add_deltas_by_index`]: VariationStoreBuilder::add_deltas_by_index
+    pub fn add_region(&mut self, region: VariationRegion) -> u16 {
+        self.canonical_index_for_region(region) as u16
+    }
+
+    /// Add a set of deltas, where each region is identified by an index
+    /// previously returned from [`add_region`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a region index was not returned by [`add_region`], since
+    /// the deltas would refer to a region missing from the final store.
+    ///
+    /// [`add_region`]: VariationStoreBuilder::add_region
+    pub fn add_deltas_by_index<T: Into<i32>>(
+        &mut self,
+        deltas: Vec<(u16, T)>,
     ) -> TemporaryDeltaSetId {
         let mut delta_set = Vec::with_capacity(deltas.len());
-        for (region, delta) in deltas {
-            let region_idx = self.canonical_index_for_region(region) as u16;
+        for (region_idx, delta) in deltas {
+            assert!(
+                (region_idx as usize) < self.all_regions.len(),
+                "region index {region_idx} was not added to this builder"
+            );
             delta_set.push((region_idx, delta.into()));
         }
         delta_set.sort_unstable();
@@ -123,6 +171,29 @@ impl VariationStoreBuilder {
         self.delta_sets.add(DeltaSet(delta_set))
     }
 
+    /// Returns the regions referenced by at least one non-zero delta.
+    ///
+    /// Regions that were added to the builder but that only have zero deltas
+    /// (or no deltas at all) are excluded. The regions are returned in the order
+    /// they were first added.
+    pub fn used_regions(&self) -> Vec<VariationRegion> {
+        let used = self
+            .delta_sets
+            .iter()
+            .flat_map(|(delta_set, _)| delta_set.0.iter())
+            .filter(|(_, delta)| *delta != 0)
+            .map(|(idx, _)| *idx as usize)
+            .collect::<HashSet<_>>();
+        let mut regions = self
+            .all_regions
+            .iter()
+            .filter(|(_, idx)| used.contains(idx))
+            .map(|(reg, idx)| (*idx, reg))
+            .collect::<Vec<_>>();
+        regions.sort_unstable_by_key(|(idx, _)| *idx);
+        regions.into_iter().map(|(_, reg)| reg.clone()).collect()
+    }
+
     fn canonical_index_for_region(&mut self, region: VariationRegion) -> usize {
         let next_idx = self.all_regions.len();
         *self.all_regions.entry(region).or_insert(next_idx)
@@ -798,6 +869,29 @@ impl VariationIndexRemapping {
         self.map.get(&from).cloned()
     }
 
+    /// Resolve a batch of pending indices to their final values.
+    ///
+    /// Returns `None` if any of the indices was not produced by the builder
+    /// that generated this mapping.
+    pub fn remap_all(&self, indices: &[PendingVariationIndex]) -> Option<Vec<VariationIndex>> {
+        indices
+            .iter()
+            .map(|idx| self.get(idx.delta_set_id))
+            .collect()
+    }
+
+    /// Remap every item in `items` in place.
+    ///
+    /// This is a convenience for callers holding many tables (such as
+    /// [`DeviceOrVariationIndex`]) that each need to be remapped.
+    ///
+    /// [`DeviceOrVariationIndex`]: crate::tables::layout::DeviceOrVariationIndex
+    pub fn remap_in_place<T: RemapVariationIndices>(&self, items: &mut [T]) {
+        for item in items {
+            item.remap_variation_indices(self);
+        }
+    }
+
     /// convert to tuple for easier comparisons in tests
     #[cfg(test)]
     fn get_raw(&self, from: TemporaryDeltaSetId) -> Option<(u16, u16)> {
@@ -984,6 +1078,48 @@ mod tests {
         ]
     }
 
+    #[test]
+    fn remap_batch_of_pending_indices() {
+        use crate::tables::layout::DeviceOrVariationIndex;
+
+        let [r1, r2, r3] = test_regions();
+        let mut builder = VariationStoreBuilder::new(2);
+        let pending = [
+            builder.add_deltas(vec![(r1.clone(), 10), (r2.clone(), 20)]),
+            builder.add_deltas(vec![(r3.clone(), 5)]),
+            builder.add_deltas(vec![(r1, -300), (r3, 1)]),
+            builder.add_deltas(vec![(r2, 7)]),
+        ]
+        .map(PendingVariationIndex::new);
+
+        let (_, key_map) = builder.build();
+        let resolved = key_map.remap_all(&pending).unwrap();
+        assert_eq!(resolved.len(), pending.len());
+        for (idx, var_idx) in pending.iter().zip(&resolved) {
+            assert_eq!(key_map.get(idx.delta_set_id).as_ref(), Some(var_idx));
+        }
+
+        let mut tables = pending
+            .iter()
+            .cloned()
+            .map(DeviceOrVariationIndex::PendingVariationIndex)
+            .collect::<Vec<_>>();
+        key_map.remap_in_place(&mut tables);
+        let remapped = tables
+            .into_iter()
+            .map(|table| match table {
+                DeviceOrVariationIndex::VariationIndex(var_idx) => var_idx,
+                other => panic!("unexpected table {other:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(remapped, resolved);
+
+        // an id the builder never handed out can't be resolved
+        assert!(key_map
+            .remap_all(&[PendingVariationIndex::new(1000)])
+            .is_none());
+    }
+
     #[test]
     #[allow(clippy::redundant_clone)]
     fn smoke_test() {
@@ -1469,6 +1605,27 @@ mod tests {
         assert_eq!(var_data.delta_sets, vec![50, 100]);
     }
 
+    #[test]
+    #[should_panic(expected = "was not added to this builder")]
+    fn add_deltas_by_unknown_index() {
+        let [r1, ..] = test_regions();
+        let mut builder = VariationStoreBuilder::new(2);
+        let idx = builder.add_region(r1.clone());
+        builder.add_deltas_by_index(vec![(idx + 1, 5)]);
+    }
+
+    #[test]
+    fn used_regions() {
+        let [r1, r2, r3] = test_regions();
+        let mut builder = VariationStoreBuilder::new(2);
+        builder.add_region(r1.clone());
+        builder.add_deltas(vec![(r2.clone(), 10), (r1.clone(), 0)]);
+        builder.add_deltas(vec![(r3.clone(), -5)]);
+        builder.add_deltas(vec![(r1.clone(), 0)]);
+
+        assert_eq!(builder.used_regions(), vec![r2, r3]);
+    }
+
     #[test]
     fn we_match_fonttools_stable_order() {
         use rand::seq::SliceRandom;
@@ -0,0 +1,394 @@
+//! A simple variation model, for lowering instance-keyed deltas into regions
+//!
+//! This is a port of the core of fontTools' `VariationModel`: given a set of
+//! master locations, it computes a region (support) for each non-default
+//! master, and can then convert values at those masters into deltas for the
+//! computed regions.
+
+use std::cmp::{Ordering, Reverse};
+
+use font_types::F2Dot14;
+
+use super::{RegionAxisCoordinates, VariationRegion};
+
+/// A location in design space, as normalized coordinates in 'fvar' axis order.
+///
+/// Axes not included in the coordinates are at their default position.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstanceLocation(Vec<F2Dot14>);
+
+/// A model of a variable font's design space, built from a set of master locations.
+#[derive(Clone, Debug)]
+pub(crate) struct VariationModel {
+    axis_count: u16,
+    // non-default locations, in the order their regions are solved
+    locations: Vec<InstanceLocation>,
+    regions: Vec<VariationRegion>,
+    // for each location, the scalar of each previous region at that location
+    weights: Vec<Vec<(usize, f64)>>,
+}
+
+impl InstanceLocation {
+    /// Create a new location from normalized coordinates.
+    pub fn new(coords: impl IntoIterator<Item = F2Dot14>) -> Self {
+        Self(coords.into_iter().collect())
+    }
+
+    /// The coordinates of this location.
+    pub fn coords(&self) -> &[F2Dot14] {
+        &self.0
+    }
+
+    /// `true` if this location is at the default position on every axis.
+    pub fn is_default(&self) -> bool {
+        self.0.iter().all(|coord| *coord == F2Dot14::ZERO)
+    }
+
+    fn get(&self, axis: usize) -> F2Dot14 {
+        self.0.get(axis).copied().unwrap_or(F2Dot14::ZERO)
+    }
+
+    fn nonzero_axes(&self) -> impl Iterator<Item = (usize, F2Dot14)> + '_ {
+        self.0
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, coord)| *coord != F2Dot14::ZERO)
+    }
+}
+
+impl From<Vec<F2Dot14>> for InstanceLocation {
+    fn from(src: Vec<F2Dot14>) -> Self {
+        InstanceLocation(src)
+    }
+}
+
+impl VariationModel {
+    /// Create a model for the provided master locations.
+    ///
+    /// The default location, and any duplicate locations, are ignored.
+    pub(crate) fn new(
+        axis_count: u16,
+        locations: impl IntoIterator<Item = InstanceLocation>,
+    ) -> Self {
+        let mut locations = locations
+            .into_iter()
+            .filter(|loc| !loc.is_default())
+            .map(|loc| {
+                // normalize so that equal locations compare equal
+                let mut coords = loc.0;
+                coords.resize(axis_count as usize, F2Dot14::ZERO);
+                InstanceLocation(coords)
+            })
+            .collect::<Vec<_>>();
+        locations.sort();
+        locations.dedup();
+        let on_axis = on_axis_points(&locations);
+        locations.sort_by_key(|loc| sort_key(loc, &on_axis));
+
+        let regions = compute_regions(&locations);
+        let weights = locations
+            .iter()
+            .enumerate()
+            .map(|(i, loc)| {
+                regions[..i]
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, region)| {
+                        let scalar = region_scalar(region, loc);
+                        (scalar != 0.0).then_some((j, scalar))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        VariationModel {
+            axis_count,
+            locations,
+            regions,
+            weights,
+        }
+    }
+
+    /// The regions computed for this model's non-default masters.
+    #[cfg(test)]
+    pub(crate) fn regions(&self) -> &[VariationRegion] {
+        &self.regions
+    }
+
+    /// Convert values at master locations into deltas for this model's regions.
+    ///
+    /// Each value is the delta from the default at the associated location;
+    /// masters with no value are treated as having a delta of zero. Values
+    /// at locations that are not part of the model are ignored.
+    pub(crate) fn deltas(&self, values: &[(InstanceLocation, i16)]) -> Vec<(VariationRegion, i16)> {
+        let mut master_values = vec![0.0f64; self.locations.len()];
+        for (loc, value) in values {
+            let mut coords = loc.0.clone();
+            coords.resize(self.axis_count as usize, F2Dot14::ZERO);
+            if let Some(idx) = self.locations.iter().position(|l| l.0 == coords) {
+                master_values[idx] = *value as f64;
+            }
+        }
+
+        let mut deltas: Vec<f64> = Vec::with_capacity(master_values.len());
+        for (value, weights) in master_values.iter().zip(&self.weights) {
+            let interpolated: f64 = weights.iter().map(|(j, w)| deltas[*j] * w).sum();
+            deltas.push(value - interpolated);
+        }
+
+        self.regions
+            .iter()
+            .cloned()
+            .zip(deltas.into_iter().map(ot_round))
+            .collect()
+    }
+}
+
+// the values each axis takes at masters that lie on that axis alone
+fn on_axis_points(locations: &[InstanceLocation]) -> Vec<Vec<F2Dot14>> {
+    let mut result = Vec::new();
+    for loc in locations {
+        let mut nonzero = loc.nonzero_axes();
+        if let (Some((axis, coord)), None) = (nonzero.next(), nonzero.next()) {
+            if result.len() <= axis {
+                result.resize(axis + 1, Vec::new());
+            }
+            result[axis].push(coord);
+        }
+    }
+    result
+}
+
+// matches the ordering used by fontTools' getMasterLocationsSortKeyFunc:
+// masters on fewer axes come first, so their regions are solved first.
+fn sort_key(
+    loc: &InstanceLocation,
+    on_axis: &[Vec<F2Dot14>],
+) -> (usize, Reverse<usize>, Vec<usize>, Vec<i8>, Vec<F2Dot14>) {
+    let axes = loc.nonzero_axes().map(|(axis, _)| axis).collect::<Vec<_>>();
+    let on_point = loc
+        .nonzero_axes()
+        .filter(|(axis, coord)| on_axis.get(*axis).is_some_and(|pts| pts.contains(coord)))
+        .count();
+    let signs = loc
+        .nonzero_axes()
+        .map(|(_, coord)| if coord < F2Dot14::ZERO { -1 } else { 1 })
+        .collect();
+    let magnitudes = loc.nonzero_axes().map(|(_, coord)| coord.abs()).collect();
+    (axes.len(), Reverse(on_point), axes, signs, magnitudes)
+}
+
+// an axis triple of (start, peak, end)
+type Triple = (F2Dot14, F2Dot14, F2Dot14);
+
+fn compute_regions(locations: &[InstanceLocation]) -> Vec<VariationRegion> {
+    let axis_count = locations.first().map(|loc| loc.0.len()).unwrap_or(0);
+    let mut min = vec![F2Dot14::ZERO; axis_count];
+    let mut max = vec![F2Dot14::ZERO; axis_count];
+    for loc in locations {
+        for (axis, coord) in loc.nonzero_axes() {
+            min[axis] = min[axis].min(coord);
+            max[axis] = max[axis].max(coord);
+        }
+    }
+
+    // start with each master's region reaching to the extremes of the design space
+    let mut supports: Vec<Vec<Option<Triple>>> = locations
+        .iter()
+        .map(|loc| {
+            (0..axis_count)
+                .map(|axis| {
+                    let peak = loc.get(axis);
+                    match peak.cmp(&F2Dot14::ZERO) {
+                        Ordering::Greater => Some((F2Dot14::ZERO, peak, max[axis])),
+                        Ordering::Less => Some((min[axis], peak, F2Dot14::ZERO)),
+                        Ordering::Equal => None,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    // then shrink each region so that it stops at previous masters that it overlaps
+    for i in 0..supports.len() {
+        for prev in 0..i {
+            let same_axes = supports[prev]
+                .iter()
+                .zip(&supports[i])
+                .all(|(a, b)| a.is_some() == b.is_some());
+            if !same_axes {
+                continue;
+            }
+            let relevant =
+                supports[i]
+                    .iter()
+                    .zip(&supports[prev])
+                    .all(|(cur, prev)| match (cur, prev) {
+                        (Some((lower, peak, upper)), Some((_, prev_peak, _))) => {
+                            prev_peak == peak || (lower < prev_peak && prev_peak < upper)
+                        }
+                        _ => true,
+                    });
+            if !relevant {
+                continue;
+            }
+
+            let mut best_axes = Vec::new();
+            let mut best_ratio = -1.0;
+            for (axis, (cur, prev)) in supports[i].iter().zip(&supports[prev]).enumerate() {
+                let (Some((lower, peak, upper)), Some((_, val, _))) = (*cur, *prev) else {
+                    continue;
+                };
+                let (new_triple, ratio) = match val.cmp(&peak) {
+                    Ordering::Less => (
+                        (val, peak, upper),
+                        (val.to_f32() - peak.to_f32()) / (lower.to_f32() - peak.to_f32()),
+                    ),
+                    Ordering::Greater => (
+                        (lower, peak, val),
+                        (val.to_f32() - peak.to_f32()) / (upper.to_f32() - peak.to_f32()),
+                    ),
+                    Ordering::Equal => continue,
+                };
+                if ratio > best_ratio {
+                    best_axes.clear();
+                    best_ratio = ratio;
+                }
+                if ratio == best_ratio {
+                    best_axes.push((axis, new_triple));
+                }
+            }
+            for (axis, triple) in best_axes {
+                supports[i][axis] = Some(triple);
+            }
+        }
+    }
+
+    supports
+        .into_iter()
+        .map(|support| {
+            VariationRegion::new(
+                support
+                    .into_iter()
+                    .map(|triple| {
+                        let (start, peak, end) = triple.unwrap_or_default();
+                        RegionAxisCoordinates::new(start, peak, end)
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+// the scalar of `region` at `loc`, following the spec's algorithm
+fn region_scalar(region: &VariationRegion, loc: &InstanceLocation) -> f64 {
+    let mut scalar = 1.0;
+    for (axis, coords) in region.region_axes.iter().enumerate() {
+        let (start, peak, end) = (
+            coords.start_coord.to_f32() as f64,
+            coords.peak_coord.to_f32() as f64,
+            coords.end_coord.to_f32() as f64,
+        );
+        let coord = loc.get(axis).to_f32() as f64;
+        // axes with an invalid triple, or one that crosses zero, are ignored
+        if peak == 0.0 || start > peak || peak > end || (start < 0.0 && end > 0.0) {
+            continue;
+        }
+        if coord == peak {
+            continue;
+        }
+        if coord <= start || coord >= end {
+            return 0.0;
+        }
+        scalar *= if coord < peak {
+            (coord - start) / (peak - start)
+        } else {
+            (end - coord) / (end - peak)
+        };
+    }
+    scalar
+}
+
+// round half up, as fontTools' otRound
+fn ot_round(val: f64) -> i16 {
+    (val + 0.5).floor().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(coords: &[f32]) -> InstanceLocation {
+        InstanceLocation::new(coords.iter().copied().map(F2Dot14::from_f32))
+    }
+
+    fn region(axes: &[(f32, f32, f32)]) -> VariationRegion {
+        VariationRegion::new(
+            axes.iter()
+                .map(|(start, peak, end)| {
+                    RegionAxisCoordinates::new(
+                        F2Dot14::from_f32(*start),
+                        F2Dot14::from_f32(*peak),
+                        F2Dot14::from_f32(*end),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn two_axis_model() {
+        let model = VariationModel::new(
+            2,
+            [
+                loc(&[0.0, 0.0]),
+                loc(&[1.0, 1.0]),
+                loc(&[1.0, 0.0]),
+                loc(&[0.0, 1.0]),
+            ],
+        );
+        assert_eq!(
+            model.regions(),
+            [
+                region(&[(0.0, 1.0, 1.0), (0.0, 0.0, 0.0)]),
+                region(&[(0.0, 0.0, 0.0), (0.0, 1.0, 1.0)]),
+                region(&[(0.0, 1.0, 1.0), (0.0, 1.0, 1.0)]),
+            ]
+        );
+
+        let deltas = model.deltas(&[
+            (loc(&[1.0, 0.0]), 10),
+            (loc(&[0.0, 1.0]), 20),
+            (loc(&[1.0, 1.0]), 50),
+        ]);
+        let deltas = deltas.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+        // the corner master only needs to make up the difference
+        assert_eq!(deltas, [10, 20, 20]);
+    }
+
+    #[test]
+    fn region_crossing_zero_is_ignored() {
+        // the first axis is not a valid region axis, and does not contribute
+        let crossing = region(&[(-1.0, 0.5, 1.0), (0.0, 1.0, 1.0)]);
+        assert_eq!(region_scalar(&crossing, &loc(&[0.25, 0.5])), 0.5);
+        assert_eq!(region_scalar(&crossing, &loc(&[-0.5, 1.0])), 1.0);
+        // a region that does not cross zero still limits the scalar
+        let positive = region(&[(0.0, 0.5, 1.0), (0.0, 1.0, 1.0)]);
+        assert_eq!(region_scalar(&positive, &loc(&[0.25, 0.5])), 0.25);
+        assert_eq!(region_scalar(&positive, &loc(&[-0.5, 1.0])), 0.0);
+    }
+
+    #[test]
+    fn intermediate_master() {
+        let model = VariationModel::new(1, [loc(&[1.0]), loc(&[0.5])]);
+        assert_eq!(
+            model.regions(),
+            [region(&[(0.0, 0.5, 1.0)]), region(&[(0.5, 1.0, 1.0)])]
+        );
+        let deltas = model.deltas(&[(loc(&[0.5]), 10), (loc(&[1.0]), 40)]);
+        assert_eq!(deltas[0].1, 10);
+        assert_eq!(deltas[1].1, 40);
+    }
+}